@@ -64,17 +64,36 @@ cylinderX = namedtuple('cylinderX', 'y,z,r')
 cylinderY = namedtuple('cylinderY', 'x,z,r')
 cylinderZ = namedtuple('cylinderZ', 'x,y,r')
 sphere    = namedtuple('sphere', 'x,y,z,r')
+cuboid    = namedtuple('cuboid', 'x,y,z,dx,dy,dz')
+ellipsoid = namedtuple('ellipsoid', 'x,y,z,a,b,c')
+finiteZ   = namedtuple('finiteZ', 'x,y,r,z_min,z_max')
+union        = namedtuple('union', 'union')
+intersection = namedtuple('intersection', 'intersection')
+difference   = namedtuple('difference', 'difference')
+mask         = namedtuple('mask', 'file,label')
 
 @parametrize('roi, expected',
              ((cylinderX(1,2,3), 'X 1 2 3'),
               (cylinderY(4,5,6), 'Y 4 5 6'),
               (cylinderZ(7,8,9), 'Z 7 8 9'),
-              (sphere(2,4,6,8), 'S 2 4 6 8')
+              (sphere(2,4,6,8), 'S 2 4 6 8'),
+              (cuboid(1,2,3,4,5,6), 'B 1 2 3 4 5 6'),
+              (ellipsoid(1,2,3,4,5,6), 'E 1 2 3 4 5 6'),
+              (finiteZ(1,2,3,4,5), 'FZ 1 2 3 4 5'),
+              (union([sphere(1,2,3,4), cylinderZ(5,6,7)]), 'U ( S 1 2 3 4 Z 5 6 7 )'),
+              (intersection([sphere(1,2,3,4), cylinderZ(5,6,7)]), 'I ( S 1 2 3 4 Z 5 6 7 )'),
+              (difference([sphere(1,2,3,4), cylinderZ(5,6,7)]), 'D ( S 1 2 3 4 Z 5 6 7 )'),
+              (mask('labels.raw', 3), 'M labels.raw 3'),
               ))
 def test_rois(roi, expected):
     assert fulano.roi(roi) == expected
 
 
+def test_difference_needs_two_rois():
+    with raises(ValueError):
+        fulano.fom_config((), (difference([sphere(1,2,3,4)]),), 1)
+
+
 def test_crcs():
     from math import sin, cos, pi
 
@@ -1,12 +1,12 @@
-import struct
+import sys
 from collections import namedtuple
 from glob import glob
 from math import sin, cos, pi
+from pathlib import Path
 from fulano import FomConfig
 
-def read_raw(filename):
-    buffer = open(filename, 'rb').read()
-    return struct.unpack_from('f' * (len(buffer) // 4), buffer)
+sys.path.insert(0, str(Path(__file__).resolve().parent.parent / 'src'))
+from utils import read_raw
 
 def polar(r, phi):
     return (r * cos(phi), r * sin(phi))
@@ -47,7 +47,7 @@ cfg = FomConfig(rois, bg_rois, bg_activity, voxels, size)
 pattern = '../data/out/mlem/60_60_60_tof_100_*.raw'
 
 for filename in sorted(glob(pattern)):
-    data = read_raw(filename)
+    _, data = read_raw(filename)
     for datum in cfg.crcs(data):
         print(f'{datum:8.2f}', end='')
     print()
@@ -1,4 +1,7 @@
+use std::path::Path;
+
 use pyo3::prelude::*;
+use pyo3::exceptions::{PyIOError, PyValueError};
 
 type L = f32;
 use petalo::{image::Image, fov::FOV, fom};
@@ -51,22 +54,22 @@ struct FomConfig {
 impl FomConfig {
 
     #[new]
-    fn new(rois: Vec<(ROI, Intensityf32)>, bg_rois: Vec<ROI>, bg: Intensityf32, voxels: (usize, usize, usize), size: (L,L,L)) -> Self {
+    fn new(rois: Vec<(ROI, Intensityf32)>, bg_rois: Vec<ROI>, bg: Intensityf32, voxels: (usize, usize, usize), size: (L,L,L)) -> PyResult<Self> {
         let rois: Vec<(petalo::fom::ROI, Intensityf32)> = rois.into_iter()
-            .map(|(r,i)| (pyroi_to_fomroi(r), i))
-            .collect();
-        let background_rois = bg_rois.into_iter().map(pyroi_to_fomroi).collect();
+            .map(|(r,i)| Ok((pyroi_to_fomroi(r)?, i)))
+            .collect::<PyResult<_>>()?;
+        let background_rois = bg_rois.into_iter().map(pyroi_to_fomroi).collect::<PyResult<_>>()?;
 
         let cfg = fom::FomConfig{ rois, background_rois, background_activity: bg};
         use units::mm;
         let size = (mm(size.0), mm(size.1), mm(size.2));
-        FomConfig{ cfg, fov: FOV::new(size, voxels)}
+        Ok(FomConfig{ cfg, fov: FOV::new(size, voxels)})
     }
 
     /// Calculate CRC for a 60x60x60 voxel image
     fn crcs(&self, data: Vec<Intensityf32>) -> Vec<Intensityf32> {
         let image = Image::new(self.fov, data);
-        image.foms(&self.cfg, true).crcs
+        image.foms(&self.cfg).crcs
     }
 
 }
@@ -93,40 +96,75 @@ impl Lift {
 #[pyfunction]
 fn roi(roi: ROI) -> String {
     use ROI::*;
+    let all = |rois: Vec<ROI>| rois.into_iter().map(self::roi).collect::<Vec<_>>().join(" ");
     match roi {
         Sphere{x, y, z, r} => format!("S {} {} {} {}", x, y, z, r),
         CylinderX{y, z, r} => format!("X {} {} {}", y, z, r),
         CylinderY{x, z, r} => format!("Y {} {} {}", x, z, r),
         CylinderZ{x, y, r} => format!("Z {} {} {}", x, y, r),
+        FiniteCylinderZ{x, y, r, z_min, z_max} => format!("FZ {} {} {} {} {}", x, y, r, z_min, z_max),
+        Cuboid{x, y, z, dx, dy, dz} => format!("B {} {} {} {} {} {}", x, y, z, dx, dy, dz),
+        Ellipsoid{x, y, z, a, b, c} => format!("E {} {} {} {} {} {}", x, y, z, a, b, c),
+        Mask{file, label} => format!("M {} {}", file, label),
+        Union{union} => format!("U ( {} )", all(union)),
+        Intersection{intersection} => format!("I ( {} )", all(intersection)),
+        Difference{difference} => format!("D ( {} )", all(difference)),
     }
 }
 
 
-fn pyroi_to_fomroi(pyroi: ROI) -> petalo::fom::ROI {
+fn pyroi_to_fomroi(pyroi: ROI) -> PyResult<petalo::fom::ROI> {
     use              ROI as lr;
     use petalo::fom::ROI as fr;
-    match pyroi {
+    let all = |rois: Vec<ROI>| rois.into_iter().map(pyroi_to_fomroi).collect::<PyResult<_>>();
+    Ok(match pyroi {
         lr::Sphere {x,y,z,r} => fr::Sphere((mm(x), mm(y), mm(z)), mm(r)),
         lr::CylinderX{y,z,r} => fr::CylinderX(    (mm(y), mm(z)), mm(r)),
-        lr::CylinderY{x,z,r} => fr::CylinderX(    (mm(x), mm(z)), mm(r)),
+        lr::CylinderY{x,z,r} => fr::CylinderY(    (mm(x), mm(z)), mm(r)),
         lr::CylinderZ{x,y,r} => fr::CylinderZ(    (mm(x), mm(y)), mm(r)),
-    }
+        lr::FiniteCylinderZ{x,y,r,z_min,z_max} => fr::FiniteCylinderZ((mm(x), mm(y)), mm(r), (mm(z_min), mm(z_max))),
+        lr::Cuboid   {x,y,z,dx,dy,dz} => fr::Cuboid   ((mm(x), mm(y), mm(z)), (mm(dx), mm(dy), mm(dz))),
+        lr::Ellipsoid{x,y,z,a ,b ,c } => fr::Ellipsoid((mm(x), mm(y), mm(z)), (mm(a ), mm(b ), mm(c ))),
+        lr::Mask{file, label}         => {
+            let labels = Image::from_file(Path::new(&file))
+                .map_err(|e| PyIOError::new_err(format!("Cannot read mask image {file}: {e}")))?;
+            fr::Mask(fom::Mask::new(labels, label))
+        },
+        lr::Union{union}               => fr::Union       (all(union)?),
+        lr::Intersection{intersection} => fr::Intersection(all(intersection)?),
+        lr::Difference{difference}     => {
+            let [a, b]: [ROI; 2] = difference.try_into()
+                .map_err(|_| PyValueError::new_err("difference requires exactly 2 ROIs"))?;
+            pyroi_to_fomroi(a)? - pyroi_to_fomroi(b)?
+        },
+    })
 }
 
 #[pyfunction]
-fn fom_config(rois: Vec<(ROI, Intensityf32)>, bg_rois: Vec<ROI>, bg: Intensityf32) -> String /*FomConfig*/ {
+fn fom_config(rois: Vec<(ROI, Intensityf32)>, bg_rois: Vec<ROI>, bg: Intensityf32) -> PyResult<String> /*FomConfig*/ {
     let rois: Vec<(petalo::fom::ROI, Intensityf32)> = rois.into_iter()
-        .map(|(r,i)| (pyroi_to_fomroi(r), i))
-        .collect();
-    let background_rois = bg_rois.into_iter().map(pyroi_to_fomroi).collect();
+        .map(|(r,i)| Ok((pyroi_to_fomroi(r)?, i)))
+        .collect::<PyResult<_>>()?;
+    let background_rois = bg_rois.into_iter().map(pyroi_to_fomroi).collect::<PyResult<_>>()?;
 
     let config = fom::FomConfig{ rois, background_rois, background_activity: bg};
-    format!("{:?}", config)
+    Ok(format!("{:?}", config))
 }
 
+// Variants are tried in order, so those whose attributes are a superset of
+// another's must come first
 #[derive(FromPyObject)]
 enum ROI {
+    Cuboid{ x: L, y: L, z: L, dx: L, dy: L, dz: L },
+    Ellipsoid{ x: L, y: L, z: L, a: L, b: L, c: L },
     Sphere{ x: L, y: L, z: L, r: L },
+    FiniteCylinderZ{ x: L, y: L, r: L, z_min: L, z_max: L },
+    Union{ union: Vec<ROI> },
+    Intersection{ intersection: Vec<ROI> },
+    /// The first ROI minus the second
+    Difference{ difference: Vec<ROI> },
+    /// Voxels with value `label` in the label image in `file`
+    Mask{ file: String, label: L },
     CylinderZ{ x: L, y: L, r: L },
     CylinderY{ x: L, z: L, r: L },
     CylinderX{ y: L, z: L, r: L },
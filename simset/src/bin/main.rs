@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::path::PathBuf;
 use clap::Parser;
-use simset::{compare, custom, standard};
+use simset::{compare, custom, standard, custom::Fields};
 
 /// Try to parse SimSET (custom) history files
 #[derive(Parser, Debug)]
@@ -19,6 +19,11 @@ struct Args {
 
     #[arg(value_enum, short = 't', long = "file-type", default_value_t = HistoryFileType::Custom)]
     history_file_type: HistoryFileType,
+
+    /// Custom history parameters file, which selects the fields stored in custom
+    /// history files [default: positions, scatters, weight, energy and travel distance]
+    #[arg(short = 'p', long)]
+    params: Option<PathBuf>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -27,7 +32,13 @@ fn main() -> Result<(), Box<dyn Error>> {
     else {
         match args.history_file_type {
             HistoryFileType::Standard => standard(&args.file, args.stop_after),
-            HistoryFileType::Custom   => custom  (&args.file, args.stop_after),
+            HistoryFileType::Custom   => {
+                let fields = match args.params {
+                    Some(params) => Fields::from_params(&std::fs::read_to_string(&params)?)?,
+                    None         => Fields::fixed(),
+                };
+                custom(&args.file, fields, args.stop_after)
+            },
         }
     }
 }
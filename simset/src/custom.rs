@@ -0,0 +1,283 @@
+//! Custom history files
+//!
+//! Which fields each photon record contains is chosen in a custom history
+//! parameters file (see `parameter-files/template`), which must be supplied to
+//! read the history file, unless it contains the `Fields::fixed` fields. Each record is the number of photons (`u8`) followed
+//! by the photons; the blue and pink photons of each decay are in consecutive
+//! records. The photon fields, when present, appear in this order:
+//!
+//! | field                                             | type          |
+//! |---------------------------------------------------|---------------|
+//! | `x_position`, `y_position`, `z_position`          | `f64` each    |
+//! | `x_cosine`, `y_cosine`, `z_cosine`                | `f64` each    |
+//! | `scatters_in_object`, `scatters_in_collimator`    | `u32` each    |
+//! | `decay_weight`, `weight`, `energy`                | `f64` each    |
+//! | `travel_distance`                                 | `f64`         |
+//! | `decay_{x,y,z}_position`, `decay_time`            | `f64` each    |
+//! | `decay_type`                                      | `u32`         |
+//! | `transaxial_distance`                             | `f64`         |
+//! | `azimuthal_angle_index`                           | `u32`         |
+//! | `axial_position`                                  | `f64`         |
+//! | `detector_{x,y,z}_position`, `detector_angle`     | `f64` each    |
+//! | `detector_crystal`                                | `u32`         |
+//! | `num_detector_interactions`                       | `i32`         |
+//! | `det_interaction_positions`: number written, then | `i32`         |
+//! | that many `DetectorInteraction`s                  | 34 bytes each |
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use binrw::{BinReaderExt, BinResult};
+use binrw::io::{Read, Seek};
+
+use crate::{DecayType, DetectorInteraction, Point192, header::Header};
+
+/// The fields present in the photons of a custom history file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields(HashSet<String>);
+
+/// Names of all the fields which may be selected, in the order in which they
+/// are written
+const FIELDS: [&str; 27] = [
+    "x_position", "y_position", "z_position",
+    "x_cosine", "y_cosine", "z_cosine",
+    "scatters_in_object", "scatters_in_collimator",
+    "decay_weight", "weight", "energy", "travel_distance",
+    "decay_x_position", "decay_y_position", "decay_z_position", "decay_time", "decay_type",
+    "transaxial_distance", "azimuthal_angle_index", "axial_position",
+    "detector_x_position", "detector_y_position", "detector_z_position", "detector_angle",
+    "detector_crystal",
+    "num_detector_interactions", "det_interaction_positions",
+];
+
+impl Fields {
+
+    /// The fields set to `TRUE` in the text of a custom history parameters file
+    pub fn from_params(text: &str) -> Result<Self, String> {
+        let mut fields = HashSet::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let words = line.split(|c: char| c.is_whitespace() || c == '=').filter(|w| !w.is_empty()).collect::<Vec<_>>();
+            let [kind, name, value] = words[..] else { return Err(format!("Cannot parse history parameter: {line}")) };
+            if !kind.eq_ignore_ascii_case("BOOL") { continue } // Ranges of accepted values
+            if !FIELDS.contains(&name) { return Err(format!("Unknown history field: {name}")) }
+            match value.to_uppercase().as_str() {
+                "TRUE"  => { fields.insert(name.to_string()); },
+                "FALSE" => {},
+                _ => return Err(format!("Invalid value of {name}: {value}")),
+            }
+        }
+        Ok(Self(fields))
+    }
+
+    pub fn all() -> Self { Self(FIELDS.iter().map(|f| f.to_string()).collect()) }
+
+    /// The fields which were assumed to be present before the fields could be
+    /// read from a parameters file
+    pub fn fixed() -> Self {
+        Self(["x_position", "y_position", "z_position", "scatters_in_object", "scatters_in_collimator",
+              "weight", "energy", "travel_distance"]
+             .iter().map(|f| f.to_string()).collect())
+    }
+
+    pub fn contains(&self, field: &str) -> bool { self.0.contains(field) }
+}
+
+/// A photon in a custom history file. Fields which were not saved are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Photon {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+    pub z: Option<f64>,
+    pub x_cosine: Option<f64>,
+    pub y_cosine: Option<f64>,
+    pub z_cosine: Option<f64>,
+    pub scatters_in_object: Option<u32>,
+    pub scatters_in_collimator: Option<u32>,
+    pub decay_weight: Option<f64>,
+    pub weight: Option<f64>,
+    pub energy: Option<f64>,
+    pub travel_distance: Option<f64>,
+    pub decay_x: Option<f64>,
+    pub decay_y: Option<f64>,
+    pub decay_z: Option<f64>,
+    pub decay_time: Option<f64>,
+    pub decay_type: Option<DecayType>,
+    pub transaxial_distance: Option<f64>,
+    pub azimuthal_angle_index: Option<u32>,
+    pub axial_position: Option<f64>,
+    pub detector_x: Option<f64>,
+    pub detector_y: Option<f64>,
+    pub detector_z: Option<f64>,
+    pub detector_angle: Option<f64>,
+    pub detector_crystal: Option<u32>,
+    pub num_detector_interactions: Option<i32>,
+    /// Empty unless `det_interaction_positions` was saved
+    pub detector_interactions: Vec<DetectorInteraction>,
+}
+
+impl Photon {
+
+    pub fn read<R: Read + Seek>(reader: &mut R, fields: &Fields) -> BinResult<Self> {
+        macro_rules! field {
+            ($name:literal) => { if fields.contains($name) { Some(reader.read_le()?) } else { None } };
+        }
+        // Struct fields are evaluated in the order in which they are written
+        Ok(Self {
+            x                     : field!("x_position"),
+            y                     : field!("y_position"),
+            z                     : field!("z_position"),
+            x_cosine              : field!("x_cosine"),
+            y_cosine              : field!("y_cosine"),
+            z_cosine              : field!("z_cosine"),
+            scatters_in_object    : field!("scatters_in_object"),
+            scatters_in_collimator: field!("scatters_in_collimator"),
+            decay_weight          : field!("decay_weight"),
+            weight                : field!("weight"),
+            energy                : field!("energy"),
+            travel_distance       : field!("travel_distance"),
+            decay_x               : field!("decay_x_position"),
+            decay_y               : field!("decay_y_position"),
+            decay_z               : field!("decay_z_position"),
+            decay_time            : field!("decay_time"),
+            decay_type            : field!("decay_type").map(|t: u32| DecayType::from(t as u64)),
+            transaxial_distance   : field!("transaxial_distance"),
+            azimuthal_angle_index : field!("azimuthal_angle_index"),
+            axial_position        : field!("axial_position"),
+            detector_x            : field!("detector_x_position"),
+            detector_y            : field!("detector_y_position"),
+            detector_z            : field!("detector_z_position"),
+            detector_angle        : field!("detector_angle"),
+            detector_crystal      : field!("detector_crystal"),
+            num_detector_interactions: field!("num_detector_interactions"),
+            detector_interactions : if fields.contains("det_interaction_positions") {
+                let n: i32 = reader.read_le()?;
+                (0..n.max(0)).map(|_| reader.read_le()).collect::<BinResult<_>>()?
+            } else { vec![] },
+        })
+    }
+
+    /// Position, if all its coordinates were saved
+    pub fn position(&self) -> Option<Point192> {
+        Some(Point192 { x: self.x?, y: self.y?, z: self.z? })
+    }
+}
+
+/// The blue and pink photons of one decay
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub blue: Vec<Photon>,
+    pub pink: Vec<Photon>,
+}
+
+/// Iterate over the events in the custom history `file`, whose photons
+/// contain `fields`
+pub fn events(file: impl AsRef<Path>, fields: Fields) -> Result<Events<BufReader<File>>, Box<dyn Error>> {
+    let file = file.as_ref();
+    let reader = File::open(file).map_err(|e| format!("Cannot open {}: {e}", file.display()))?;
+    Events::new(BufReader::new(reader), fields)
+}
+
+pub struct Events<R> {
+    reader: R,
+    /// Total size of the history file
+    len: u64,
+    fields: Fields,
+}
+
+impl<R: Read + Seek> Events<R> {
+    pub fn new(mut reader: R, fields: Fields) -> Result<Self, Box<dyn Error>> {
+        let len = reader.seek(binrw::io::SeekFrom::End(0))?;
+        Header::read(&mut reader)?;
+        Ok(Self { reader, len, fields })
+    }
+
+    fn photons(&mut self) -> BinResult<Vec<Photon>> {
+        let n: u8 = self.reader.read_le()?;
+        (0..n).map(|_| Photon::read(&mut self.reader, &self.fields)).collect()
+    }
+
+    fn next_event(&mut self) -> Result<Option<Event>, Box<dyn Error>> {
+        if self.reader.stream_position()? >= self.len { return Ok(None) }
+        let blue = self.photons()?;
+        let pink = self.photons()?;
+        Ok(Some(Event { blue, pink }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for Events<R> {
+    type Item = Result<Event, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test_custom {
+    use super::*;
+    use std::io::Cursor;
+    use crate::header::DEFAULT_HEADER_SIZE;
+
+    const PARAMS: &str = "
+        # Comment
+        BOOL    x_position   = TRUE
+        BOOL    y_position   = TRUE
+        BOOL    z_position   = true
+        BOOL    energy       = TRUE
+        # REAL  energy_min   = x.xx
+        REAL    energy_min   = 100.0
+        BOOL    decay_type   = TRUE
+        BOOL    weight       = FALSE
+        BOOL    det_interaction_positions = TRUE
+    ";
+
+    fn photon(x: f64, decay_type: u32, interactions: &[f64]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for v in [x, 0.0, 1.0, 511.0] { bytes.extend(v.to_le_bytes()) }
+        bytes.extend(decay_type.to_le_bytes());
+        bytes.extend((interactions.len() as i32).to_le_bytes());
+        for &e in interactions {
+            for v in [x, 0.0, 1.0, e] { bytes.extend(v.to_le_bytes()) }
+            bytes.extend([1, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parse_params() {
+        let fields = Fields::from_params(PARAMS).unwrap();
+        assert!(fields.contains("z_position") && fields.contains("decay_type"));
+        assert!(!fields.contains("weight"));
+        assert!(Fields::from_params("BOOL nonsense = TRUE").is_err());
+        assert!(Fields::from_params(include_str!("../parameter-files/template")).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn events_with_detector_interactions() -> Result<(), Box<dyn Error>> {
+        let mut file = vec![0; DEFAULT_HEADER_SIZE as usize];
+        file.push(1); file.extend(photon( 10.0, 2, &[300.0, 211.0]));
+        file.push(2); file.extend(photon(-10.0, 2, &[511.0])); file.extend(photon(-11.0, 2, &[]));
+        file.push(0);
+        file.push(1); file.extend(photon( 20.0, 1, &[]));
+
+        let fields = Fields::from_params(PARAMS).unwrap();
+        let events = Events::new(Cursor::new(file), fields)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(events.len(), 2);
+        let Event { blue, pink } = &events[0];
+        assert_eq!((blue.len(), pink.len()), (1, 2));
+        assert_eq!(blue[0].position(), Some(Point192 { x: 10.0, y: 0.0, z: 1.0 }));
+        assert_eq!(blue[0].energy, Some(511.0));
+        assert_eq!(blue[0].weight, None);
+        assert_eq!(blue[0].decay_type, Some(DecayType::PETRandom));
+        let deposits = blue[0].detector_interactions.iter().map(|i| i.energy_deposited).collect::<Vec<_>>();
+        assert_eq!(deposits, vec![300.0, 211.0]);
+        assert_eq!(blue[0].detector_interactions[0].is_active, 1);
+        assert_eq!(pink[1].x, Some(-11.0));
+        assert!(events[1].blue.is_empty());
+        assert_eq!(events[1].pink[0].decay_type, Some(DecayType::Positron));
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+//! The header at the start of every SimSET history file
+//!
+//! SimSET (`LbHeader`) stores the header as a sequence of elements, each of
+//! which is a key (`u32`), the size of its data in bytes (`u32`) and the data.
+//! The first element holds the size of the whole header (`u32`), after which
+//! the records start. Unused space is zero-filled, so a zero key ends the
+//! elements.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use binrw::BinReaderExt;
+use binrw::io::{Read, Seek, SeekFrom};
+
+/// Header size used by SimSET, when none is recorded in the header itself
+pub const DEFAULT_HEADER_SIZE: u64 = 2_u64.pow(15);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// Size in bytes: the records start here
+    pub size: u64,
+    /// The data of each element, by key
+    pub elements: HashMap<u32, Vec<u8>>,
+}
+
+impl Header {
+
+    /// Read the header at the start of `reader`, leaving it at the first record
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, Box<dyn Error>> {
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut elements = HashMap::new();
+        let mut size = None;
+        let end = |size: Option<u64>| size.unwrap_or(DEFAULT_HEADER_SIZE).min(file_len);
+        // The element headers are 8 bytes long
+        while reader.stream_position()? + 8 <= end(size) {
+            let key: u32 = reader.read_le()?;
+            let len: u32 = reader.read_le()?;
+            if key == 0 { break }
+            let start = reader.stream_position()?;
+            if start + len as u64 > end(size) {
+                return Err(format!("Header element {key} of {len} bytes at byte {start} overruns the {}-byte header", end(size)).into())
+            }
+            let mut data = vec![0; len as usize];
+            reader.read_exact(&mut data)?;
+            if size.is_none() && elements.is_empty() {
+                size = header_size(&data, reader.stream_position()?, file_len);
+            }
+            elements.insert(key, data);
+        }
+        let size = size.unwrap_or(DEFAULT_HEADER_SIZE);
+        if size > file_len {
+            return Err(format!("History file of {file_len} bytes is too short for its {size}-byte header").into())
+        }
+        reader.seek(SeekFrom::Start(size))?;
+        Ok(Self { size, elements })
+    }
+
+    /// The data of the element with `key`, as a `u32`
+    pub fn u32(&self, key: u32) -> Option<u32> {
+        Some(u32::from_le_bytes(self.elements.get(&key)?.get(..4)?.try_into().ok()?))
+    }
+}
+
+/// The header size recorded in the `data` of the first element, if it is
+/// plausible: at least as large as the elements read so far (`read`), and
+/// no larger than the file.
+fn header_size(data: &[u8], read: u64, file_len: u64) -> Option<u64> {
+    let size = u32::from_le_bytes(data.try_into().ok()?) as u64;
+    (read <= size && size <= file_len).then_some(size)
+}
+
+#[cfg(test)]
+mod test_header {
+    use super::*;
+    use std::io::Cursor;
+
+    fn element(key: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = key.to_le_bytes().to_vec();
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn size_recorded_in_header() -> Result<(), Box<dyn Error>> {
+        let mut file = element(7, &1024_u32.to_le_bytes());
+        file.extend(element(12, &42_u32.to_le_bytes()));
+        file.resize(1024, 0);
+        file.extend([9; 10]);
+        let mut reader = Cursor::new(file);
+        let header = Header::read(&mut reader)?;
+        assert_eq!(header.size, 1024);
+        assert_eq!(header.u32(12), Some(42));
+        assert_eq!(reader.position(), 1024);
+        Ok(())
+    }
+
+    #[test]
+    fn default_size() -> Result<(), Box<dyn Error>> {
+        // Implausible size in first element
+        let mut file = element(7, &[1, 2]);
+        file.resize(DEFAULT_HEADER_SIZE as usize + 3, 0);
+        assert_eq!(Header::read(&mut Cursor::new(file))?.size, DEFAULT_HEADER_SIZE);
+        // File shorter than header
+        assert!(Header::read(&mut Cursor::new(vec![0; 100])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn element_overrunning_header() {
+        let mut file = element(7, &64_u32.to_le_bytes());
+        file.extend(12_u32.to_le_bytes());
+        file.extend(u32::MAX.to_le_bytes());
+        file.resize(1024, 0);
+        assert!(Header::read(&mut Cursor::new(file)).is_err());
+    }
+}
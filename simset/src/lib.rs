@@ -1,11 +1,17 @@
 use std::error::Error;
 use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
 use binrw::binrw;
-use binrw::io::{Seek, SeekFrom};
+use binrw::io::Seek;
 use binrw::BinReaderExt;
 
+pub mod custom;
+pub mod header;
+
+use header::Header;
+
 
 #[binrw]
 #[derive(Debug)]
@@ -15,29 +21,22 @@ enum Standard {
     // according to struct PHG_DetectedPhoton in file Photon.h
 }
 
-#[binrw]
-#[derive(Debug)]
-struct Custom {
-    n_pairs: u8,
-
-    #[br(count = n_pairs)]
-    pairs: Vec<custom::Photon>,
-}
-
-mod standard {
-    use super::{Point96, Point192, binrw};
+pub mod standard {
+    use super::{DecayType, Point96, Point192, binrw};
 
     #[binrw]
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Decay {
         pub pos       : Point192, // 24
         pub weight    : f64,      //  8
         pub time      : f64,      //  8
-        pub decay_type: u64,      //  8  // Needs to be mapped to an enum (PhgEn_DecayTypeTy)
+        #[br(map = |t: u64| DecayType::from(t))]
+        #[bw(map = |t: &DecayType| *t as u64)]
+        pub decay_type: DecayType,//  8
     } // bytes wasted: probably all 48 of them
 
     #[binrw]
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq)]
     pub struct Photon { // Both blue and pink?                        -- comments from struct definicion in SimSET: Photon.h --
         pub location              : Point96,  // 123456789aba  12  12 photon current location or, in detector list mode, detection position
         pub angle                 : Point96,  // 123456789aba  12  24 photon durrent direction.  perhaps undefined in detector list mode.
@@ -55,104 +54,94 @@ mod standard {
     } // bytes wasted: 17 on padding, 10 on SPECT, 12 on undefined-in-LM direction, 4 on block detectors; 43/72 = 60%
 }
 
-mod custom {
-    use super::{Point192, binrw};
-
-    #[binrw]
-    #[derive(Debug)]
-    pub struct Photon {
-        pub pos: Point192,
-        // x-cos
-        // y-cos
-        // z-cos
-        pub scatters_in_object: u32,
-        pub scatters_in_collimator: u32,
-        // decay_weight: f64,
-        pub weight: f64,
-        pub energy: f64,
-        pub travel_distance: f64,
-        // decay_pos: Point192,
-        // decay_time: f64,
-        // decay_type: u32,
-
-        // LOR
-        // transaxial_distance: f64
-        // azimuthal angle index
-        // axial_position: f64,
-
-        // detector_pos: Point192,
-        // detector_angle: f64,
-        // detector crystal
-
-        // num_detector_interactions: i32, // Supposed to be 1 char, but it looks like alignment sometimes pads it!
-        // num_detector_interactions_written: i32, // ? Will be zero if detector_interactions not stored
-
-        // #[br(count = num_detector_interactions_written)]
-        // detector_interactions: Vec<DetectorInteraction>,
+/// Kinds of decay (`PhgEn_DecayTypeTy` in SimSET)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum DecayType {
+    SinglePhoton = 0,
+    Positron     = 1,
+    /// Artificial random coincidence events
+    PETRandom    = 2,
+    /// For isotopes with multiple possible decay products - not implemented in SimSET
+    Complex      = 3,
+    /// Unassigned or error situations, and values not known to SimSET
+    Unknown      = 4,
+}
 
+impl From<u64> for DecayType {
+    fn from(decay_type: u64) -> Self {
+        use DecayType::*;
+        match decay_type {
+            0 => SinglePhoton,
+            1 => Positron,
+            2 => PETRandom,
+            3 => Complex,
+            _ => Unknown,
+        }
     }
 }
+
 #[binrw]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point192 {
-    x: f64,
-    y: f64,
-    z: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
 }
 
 #[binrw]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point96 {
-    x: f32,
-    y: f32,
-    z: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
 }
 
+/// An interaction of a photon in the detector, as stored in custom history files
 #[binrw]
-#[derive(Debug)]
-struct DetectorInteraction {
-    pos: Point192,
-    energy_deposited: f64,
-    is_active: u8,
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorInteraction {
+    pub pos: Point192,
+    pub energy_deposited: f64,
+    pub is_active: u8,
     padding: u8, // Is this here because of alignment before writing?
 }
 
-pub fn custom(file: impl AsRef<Path>, stop_after: Option<usize>) -> Result<(), Box<dyn Error>> {
-    let mut file = File::open(file)?;
-    file.seek(SeekFrom::Start(2_u64.pow(15)))?;
-    let mut count = 0;
-    let mut blue = true;
-    while let Ok(Custom { n_pairs: n_photons, pairs }) = file.read_le::<Custom>() {
-        if blue { println!("============================================================"); }
-        if let Some(stop) = stop_after { if count >= stop { break } }; count += 1;
-        println!("------ N {} photons: {n_photons} --------", if blue { "blue" } else { "pink" });
-        for custom::Photon { pos: Point192 { x, y, z },  energy, travel_distance, scatters_in_object: so, scatters_in_collimator: sc, weight } in pairs {
-            let t = travel_distance / 0.03;
-            println!("({x:7.2} {y:7.2} {z:7.2})   E:{energy:7.2}   t:{t:4.1} ps  w: {weight:4.2}   scatters obj:{so:2} col:{sc:2}");
+pub fn custom(file: impl AsRef<Path>, fields: custom::Fields, stop_after: Option<usize>) -> Result<(), Box<dyn Error>> {
+    for event in custom::events(file, fields)?.take(stop_after.unwrap_or(usize::MAX)) {
+        let custom::Event { blue, pink } = event?;
+        println!("============================================================");
+        for (colour, photons) in [("blue", blue), ("pink", pink)] {
+            println!("------ N {colour} photons: {} --------", photons.len());
+            for photon in photons {
+                let show = |x: Option<f64>| x.map_or("   -   ".into(), |x| format!("{x:7.2}"));
+                let t = photon.travel_distance.map(|d| d / 0.03);
+                let (so, sc) = (photon.scatters_in_object, photon.scatters_in_collimator);
+                println!("({} {} {})   E:{}   t:{} ps  w: {}   scatters obj:{so:?} col:{sc:?}   decay: {:?}   interactions: {}",
+                         show(photon.x), show(photon.y), show(photon.z), show(photon.energy), show(t), show(photon.weight),
+                         photon.decay_type, photon.detector_interactions.len());
+            }
         }
-        blue = ! blue;
     }
     Ok(())
 }
 
 pub fn standard(file: impl AsRef<Path>, stop_after: Option<usize>) -> Result<(), Box<dyn Error>> {
-    use standard as st;
-    let mut file = File::open(file)?;
-    file.seek(SeekFrom::Start(2_u64.pow(15)))?;
     let mut count = 0;
     let mut _ts = [None, None];
-    while let Ok(decay) = StandardDecayWithPhotons::read(&mut file) {// file.read_le::<Standard>() {
+    for event in standard_events(file)? {
 
         // ----- Process the decay data -------------------------------------------
-        let st::Decay { pos: Point192 { x, y, z }, weight, time, decay_type } = decay.decay;
+        let Event { decay, photons } = event?;
+        let standard::Decay { pos: Point192 { x, y, z }, weight, time, decay_type } = decay;
         if let Some(stop) = stop_after { if count >= stop { break } }; count += 1;
         _ts = [None, None];
         println!("=====================================================================================");
-        println!("({x:6.2} {y:6.2} {z:6.2})    t: {time:5.2}  s   w:{weight:6.2}   type:{decay_type}");
+        println!("({x:6.2} {y:6.2} {z:6.2})    t: {time:5.2}  s   w:{weight:6.2}   type:{decay_type:?}");
 
         // ----- Process each photon associated with the decay --------------------
         let mut seen_pink = false;
-        for st::Photon { location: Point96 { x, y, z }, flags, weight, energy, time, .. } in decay.photons {
+        for standard::Photon { location: Point96 { x, y, z }, flags, weight, energy, time, .. } in photons {
             let time = time * 1e12;
             _ts[blue_or_pink_index(flags)] = Some(time);
             let (c, s, t) = interpret_flags(flags);
@@ -173,13 +162,9 @@ pub fn standard(file: impl AsRef<Path>, stop_after: Option<usize>) -> Result<(),
 }
 
 pub fn compare(file1: &Path, file2: &Path, stop_after: Option<usize>) -> Result<(), Box<dyn Error>> {
-    let mut file1 = File::open(file1)?; file1.seek(SeekFrom::Start(2_u64.pow(15)))?;
-    let mut file2 = File::open(file2)?; file2.seek(SeekFrom::Start(2_u64.pow(15)))?;
     let mut count = 0;
-    let mut read1 = || StandardDecayWithPhotons::read(&mut file1);
-    let mut read2 = || StandardDecayWithPhotons::read(&mut file2);
-    type D = StandardDecayWithPhotons;
-    while let (Ok(D { decay: _ldecay, photons: lphotons }), Ok(D { decay: _rdecay, photons: rphotons })) = (read1(), read2()) {
+    for (left, right) in standard_events(file1)?.zip(standard_events(file2)?) {
+        let (Event { photons: lphotons, .. }, Event { photons: rphotons, .. }) = (left?, right?);
         //println!();
         if let Some(stop) = stop_after {
             if count >= stop { break };
@@ -193,32 +178,97 @@ pub fn compare(file1: &Path, file2: &Path, stop_after: Option<usize>) -> Result<
     Ok(())
 }
 
-fn blue_or_pink_index(flag: u8) -> usize { (flag & 0x1) as usize }
-fn interpret_flags(flag: u8) -> (&'static str, &'static str, &'static str) {
-    let c = if (flag & 0x1) == 0x1 { "blue"    } else { "pink"    };
-    let s = if (flag & 0x2) == 0x2 { "scatter" } else { "-------" };
-    let t = if (flag & 0x4) == 0x4 { "primary" } else { "-------" };
-    (c, s, t)
+/// A decay, with the photons it produced which were recorded in a standard
+/// history file
+#[derive(Debug)]
+pub struct Event {
+    pub decay: standard::Decay,
+    pub photons: Vec<standard::Photon>,
+}
+
+impl Event {
+    /// Artificial random coincidence, made of photons from different decays
+    pub fn is_random(&self) -> bool { self.decay.decay_type == DecayType::PETRandom }
+
+    /// The first blue and the first pink photon, if both were detected
+    pub fn coincidence(&self) -> Option<(&standard::Photon, &standard::Photon)> {
+        let blue = self.photons.iter().find(|p|  p.is_blue())?;
+        let pink = self.photons.iter().find(|p| !p.is_blue())?;
+        Some((blue, pink))
+    }
+}
+
+impl standard::Photon {
+    pub fn is_blue  (&self) -> bool { interpret_flags(self.flags).0 == "blue"    }
+    pub fn scattered(&self) -> bool { interpret_flags(self.flags).1 == "scatter" }
+    pub fn primary  (&self) -> bool { interpret_flags(self.flags).2 == "primary" }
+}
+
+/// Iterate over the events in the standard history `file`
+pub fn standard_events(file: impl AsRef<Path>) -> Result<Events<BufReader<File>>, Box<dyn Error>> {
+    let file = file.as_ref();
+    let reader = File::open(file).map_err(|e| format!("Cannot open {}: {e}", file.display()))?;
+    Events::new(BufReader::new(reader))
 }
 
-struct StandardDecayWithPhotons {
-    decay: standard::Decay,
-    photons: Vec<standard::Photon>,
+/// The events in a standard history file: a `Header`, followed by records, each
+/// of which is a decay or one of the photons produced by the preceding decay
+pub struct Events<R> {
+    reader: R,
+    /// Total size of the history file
+    len: u64,
+    /// The decay whose photons are being collected
+    pending: Option<standard::Decay>,
 }
 
-const DECAY_SIZE_INCLUDING_MAGIC_: i64 = (std::mem::size_of::<standard::Decay>() + 1) as i64;
+impl<R: Read + Seek> Events<R> {
+    pub fn new(mut reader: R) -> Result<Self, Box<dyn Error>> {
+        let len = reader.seek(binrw::io::SeekFrom::End(0))?;
+        Header::read(&mut reader)?;
+        Ok(Self { reader, len, pending: None })
+    }
+
+    fn at_end(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.reader.stream_position()? >= self.len)
+    }
 
-impl StandardDecayWithPhotons {
-    fn read(file: &mut File) -> Result<Self, Box<dyn Error>> {
+    fn next_event(&mut self) -> Result<Option<Event>, Box<dyn Error>> {
         use Standard::*;
         let mut photons = vec![];
-        let       Decay (decay ) = file.read_le::<Standard>()? else { panic!("Expected decay") };
-        while let Photon(photon) = file.read_le::<Standard>()? { photons.push(photon); }
-        file.seek(std::io::SeekFrom::Current(-DECAY_SIZE_INCLUDING_MAGIC_))?;
-        Ok(Self { decay, photons })
+        let decay = match self.pending.take() {
+            Some(decay) => decay,
+            None => {
+                if self.at_end()? { return Ok(None) }
+                let Decay(decay) = self.reader.read_le::<Standard>()? else { return Err("Expected decay".into()) };
+                decay
+            },
+        };
+        while !self.at_end()? {
+            match self.reader.read_le::<Standard>()? {
+                Photon(photon) => photons.push(photon),
+                Decay (next  ) => { self.pending = Some(next); break },
+            }
+        }
+        Ok(Some(Event { decay, photons }))
     }
 }
 
+impl<R: Read + Seek> Iterator for Events<R> {
+    type Item = Result<Event, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+fn blue_or_pink_index(flag: u8) -> usize { (flag & 0x1) as usize }
+fn interpret_flags(flag: u8) -> (&'static str, &'static str, &'static str) {
+    let c = if (flag & 0x1) == 0x1 { "blue"    } else { "pink"    };
+    let s = if (flag & 0x2) == 0x2 { "scatter" } else { "-------" };
+    let t = if (flag & 0x4) == 0x4 { "primary" } else { "-------" };
+    (c, s, t)
+}
+
 // typedef struct  {
 //     double x_position;
 //     double y_position;
@@ -240,3 +290,53 @@ impl StandardDecayWithPhotons {
 //     PhgEn_Complex,      /* For isotopes with multiple possible decay products - not implemented */
 //     PhgEn_Unknown       /* for unassigned or error situations */
 // } PhgEn_DecayTypeTy;
+
+#[cfg(test)]
+mod test_events {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decay(time: f64, decay_type: u64) -> Vec<u8> {
+        let mut bytes = vec![1_u8];
+        for x in [1.0, 2.0, 3.0, 1.0, time] { bytes.extend(f64::to_le_bytes(x)) }
+        bytes.extend(decay_type.to_le_bytes());
+        bytes
+    }
+
+    fn photon(flags: u8, x: f32, time: f64) -> Vec<u8> {
+        let mut bytes = vec![2_u8];
+        for c in [x, 0.0, 0.0, 0.0, 0.0, 1.0] { bytes.extend(c.to_le_bytes()) }
+        bytes.push(flags);
+        bytes.extend([0; 7]);
+        bytes.extend(0.5_f64.to_le_bytes());
+        bytes.extend(511_f32.to_le_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend(time.to_le_bytes());
+        bytes.extend([0; 16]);
+        bytes
+    }
+
+    #[test]
+    fn events_group_photons_by_decay() -> Result<(), Box<dyn Error>> {
+        let mut file = vec![0; header::DEFAULT_HEADER_SIZE as usize];
+        file.extend(decay(1.0, 1));
+        file.extend(photon(0b101, 40.0, 1.0 + 1e-9));
+        file.extend(photon(0b110, -40.0, 1.0 + 2e-9));
+        file.extend(decay(2.0, 2));
+        file.extend(photon(0b101, 40.0, 2.0));
+        file.extend(decay(3.0, 9));
+
+        let events = Events::new(Cursor::new(file))?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(events.iter().map(|e| e.photons.len()).collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert_eq!(events.iter().map(|e| e.decay.time).collect::<Vec<_>>(), vec![1.0, 2.0, 3.0]);
+        assert_eq!(events.iter().map(|e| e.decay.decay_type).collect::<Vec<_>>(),
+                   vec![DecayType::Positron, DecayType::PETRandom, DecayType::Unknown]);
+        assert!(events[1].is_random() && !events[0].is_random());
+
+        let (blue, pink) = events[0].coincidence().unwrap();
+        assert_eq!((blue.location.x, pink.location.x), (40.0, -40.0));
+        assert!(!blue.scattered() && pink.scattered() && blue.primary());
+        assert!(events[1].coincidence().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use petalo::{
+    config::mlem::Bounds,
+    discrete::{Adjust, Discretize},
+    io::{castor::{self, Events, Scanner}, hdf5::{Hdf5Lor, iter_lors}},
+    utils::group_digits,
+};
+use units::{Length, Time};
+
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "castorlm", about = "Convert LORs to and from CASToR list-mode data")]
+pub struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Command {
+
+    /// Write LORs from HDF5 as CASToR list-mode data, and the scanner geometry
+    /// as `<name>.geom` next to the header
+    Export {
+        /// HDF5 input file containing the LORs
+        input: PathBuf,
+
+        /// CASToR header (`.Cdh`) to be written. The data go next to it, in `.Cdf`
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// The dataset location inside the input file
+        #[clap(short, long, default_value = "reco_info/lors")]
+        dataset: String,
+
+        /// How to store the ends of the LORs
+        #[clap(short, long, value_enum, default_value = "crystal-ids")]
+        events: Events,
+
+        /// TOF resolution FWHM (eg '200 ps'). TOF is not written if not supplied
+        #[clap(short, long)]
+        tof: Option<Time>,
+
+        #[clap(flatten)]
+        scanner: ScannerArgs,
+    },
+
+    /// Read CASToR list-mode data into an HDF5 LOR dataset
+    Import {
+        /// CASToR header (`.Cdh`)
+        input: PathBuf,
+
+        /// HDF5 output file
+        #[clap(short, long)]
+        out: PathBuf,
+
+        /// The dataset location inside the output file
+        #[clap(short, long, default_value = "reco_info/lors")]
+        dataset: String,
+
+        /// Scanner geometry (`.geom`) written by `export`: needed for events
+        /// with crystal ids
+        #[clap(short, long)]
+        geometry: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct ScannerArgs {
+    /// Name of the scanner in CASToR
+    #[clap(long, default_value = "petalo")]
+    name: String,
+
+    /// Inner radius of scintillator
+    #[clap(short, long)]
+    r_min: Length,
+
+    /// Radial size of elements = thickness of scintillator
+    #[clap(long)]
+    dr: Length,
+
+    /// Axial size of elements
+    #[clap(long)]
+    dz: Length,
+
+    /// Azimuthal width of scintillator elements at `r_min + dr/2`?
+    #[clap(long)]
+    da: Length,
+
+    /// Axial length of scintillator
+    #[clap(short, long)]
+    length: Length,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Cli::parse().command {
+        Command::Export { input, out, dataset, events, tof, scanner } => {
+            let ScannerArgs { name, r_min, dr, dz, da, length } = scanner;
+            let scanner = Scanner::new(&name, Discretize::new(r_min, dr, dz, da, Adjust::No), length);
+            let geometry = out.with_file_name(format!("{name}.geom"));
+            std::fs::write(&geometry, scanner.geometry())?;
+            println!("Wrote geometry of {} crystals to {}", group_digits(scanner.n_crystals()), geometry.display());
+            let lors = iter_lors::<Hdf5Lor>(&input, &dataset, Bounds::none())?;
+            let n = itertools::process_results(lors, |lors| castor::write(lors, &out, &scanner, events, tof))??;
+            println!("Wrote {} events to {}", group_digits(n), out.display());
+        },
+        Command::Import { input, out, dataset, geometry } => {
+            let scanner = geometry
+                .map(|path| -> Result<_, Box<dyn Error>> {
+                    let text = std::fs::read_to_string(&path)
+                        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+                    Ok(Scanner::from_geometry(&text)?)
+                })
+                .transpose()?;
+            let lors = castor::read(&input, scanner.as_ref())?;
+            let file = hdf5::File::create(&out)?;
+            let (group, name) = dataset.rsplit_once('/').unwrap_or(("", &dataset));
+            let group = if group.is_empty() { file.group("/")? } else { file.create_group(group)? };
+            group.new_dataset_builder().with_data(&lors).create(name)?;
+            println!("Wrote {} LORs to {}", group_digits(lors.len()), out.display());
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// ----------------------------------- CLI -----------------------------------
+use clap::Parser;
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "countrates", about = "NEMA NU 2 scatter fraction, randoms fraction and NECR from LOR datasets")]
+pub struct Cli {
+
+    /// HDF5 files containing LORs, one per acquisition of the scatter phantom
+    #[clap(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// The dataset location inside the input files
+    #[clap(short, long, default_value = "reco_info/lors")]
+    pub dataset: String,
+
+    /// Duration of each acquisition (e.g. '10 s'). A single value applies to all
+    #[clap(short = 't', long, required = true, num_args = 1..)]
+    pub duration: Vec<Time>,
+
+    /// Discard sinogram bins further than this from the centre of the phantom
+    #[clap(long, default_value = "120 mm")]
+    pub r_max: Length,
+
+    /// Half-width of the band around the line source peak
+    #[clap(long, default_value = "20 mm")]
+    pub window: Length,
+
+    /// Radial width of sinogram bins
+    #[clap(long, default_value = "2 mm")]
+    pub bin_width: Length,
+
+    /// Number of angular sinogram bins
+    #[clap(long, default_value = "180")]
+    pub phi_bins: usize,
+
+    /// Validate the scatter and randoms fractions against MC truth: the LORs'
+    /// classification if they have one (LOR record version 2), otherwise
+    /// inferred from photon energies (scatter fraction only)
+    #[clap(long)]
+    pub mc_truth: bool,
+}
+
+// --------------------------------------------------------------------------------
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let durations = match args.duration.as_slice() {
+        &[t] => vec![t; args.inputs.len()],
+        ts if ts.len() == args.inputs.len() => ts.to_vec(),
+        ts => return Err(format!("Got {} durations for {} acquisitions", ts.len(), args.inputs.len()).into()),
+    };
+    let n_s = ratio_(2.0 * args.r_max / args.bin_width).round() as usize;
+    let mut progress = Progress::new();
+
+    let acquisitions = args.inputs.par_iter()
+        .map(|path| -> Result<_, String> {
+            let mut sinogram = Sinogram::new(args.phi_bins, n_s, args.r_max);
+            let mut truth = TruthCounts::default();
+            let lors = iter_lors::<ExtendedLor>(path, &args.dataset, Bounds::none())
+                .map_err(|e| format!("Cannot read LORs from {}: {e}", path.display()))?;
+            for lor in lors {
+                let lor = lor.map_err(|e| format!("Cannot read LORs from {}: {e}", path.display()))?;
+                if args.mc_truth { truth.fill(&lor, args.r_max) }
+                sinogram.fill(&Hdf5Lor::from(lor));
+            }
+            Ok((sinogram.strip_counts(args.r_max, args.window), truth))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    progress.done_with_message(&format!("Read {} acquisitions", acquisitions.len()));
+
+    let strips = acquisitions.iter().map(|(c, _)| *c).zip(durations).collect::<Vec<_>>();
+    let (sf, rates) = count_rates(&strips).ok_or("No acquisitions")?;
+
+    println!("Scatter fraction (lowest count rate acquisition): {:.1} %\n", 100.0 * sf);
+    print!("{:>30} {:>12} {:>12} {:>12} {:>12} {:>8} {:>8} {:>12}",
+           "acquisition", "total/cps", "trues/cps", "scatters/cps", "randoms/cps", "SF %", "RF %", "NECR/cps");
+    if args.mc_truth { print!(" {:>8} {:>8}", "MC SF %", "MC RF %") }
+    println!();
+    for ((path, rates), (_, truth)) in args.inputs.iter().zip(&rates).zip(&acquisitions) {
+        let CountRates { total, trues, scatters, randoms, scatter_fraction, randoms_fraction, necr } = rates;
+        let name = path.file_name().map_or(path.display().to_string(), |n| n.to_string_lossy().into());
+        print!("{name:>30} {total:12.0} {trues:12.0} {scatters:12.0} {randoms:12.0} {:8.1} {:8.1} {necr:12.0}",
+               100.0 * scatter_fraction, 100.0 * randoms_fraction);
+        if args.mc_truth { print!(" {:8.1} {:8.1}", 100.0 * truth.scatter_fraction(), 100.0 * truth.randoms_fraction()) }
+        println!();
+    }
+    Ok(())
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{
+    error::Error,
+    path::PathBuf,
+};
+
+use rayon::prelude::*;
+
+use units::{Length, Time, ratio_};
+
+use petalo::{
+    config::mlem::Bounds,
+    countrates::{CountRates, Sinogram, TruthCounts, count_rates},
+    io::hdf5::{ExtendedLor, Hdf5Lor, iter_lors},
+    utils::timing::Progress,
+};
@@ -0,0 +1,86 @@
+// ----------------------------------- CLI -----------------------------------
+use clap::Parser;
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "filter", about = "Apply a smoothing filter to a raw image")]
+pub struct Cli {
+
+    /// Image to be filtered
+    pub input: PathBuf,
+
+    /// Where to write the filtered image
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    #[clap(subcommand)]
+    pub filter: FilterKind,
+}
+
+#[derive(clap::Parser, Debug, Clone)]
+pub enum FilterKind {
+
+    /// Gaussian smoothing
+    Gaussian {
+        /// FWHM of the Gaussian (eg '4 mm')
+        #[clap(short, long)]
+        fwhm: Length,
+    },
+
+    /// Median over cubic neighbourhood
+    Median {
+        /// Number of voxels on each side of the central voxel
+        #[clap(short, long, default_value = "1")]
+        radius: usize,
+    },
+
+    /// Edge-preserving smoothing
+    Bilateral {
+        /// FWHM of the spatial Gaussian (eg '4 mm')
+        #[clap(short, long)]
+        fwhm: Length,
+
+        /// Sigma of the intensity Gaussian, relative to the image maximum (eg '10 %')
+        #[clap(short, long)]
+        range: Ratio,
+    },
+}
+
+impl From<FilterKind> for Filter {
+    fn from(kind: FilterKind) -> Self {
+        match kind {
+            FilterKind::Gaussian  { fwhm }        => Filter::Gaussian  { fwhm },
+            FilterKind::Median    { radius }      => Filter::Median    { radius },
+            FilterKind::Bilateral { fwhm, range } => Filter::Bilateral { fwhm, range },
+        }
+    }
+}
+
+// --------------------------------------------------------------------------------
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Cli { input, output, filter } = Cli::parse();
+    let filter: Filter = filter.into();
+    let mut progress = Progress::new();
+
+    let image = Image::from_file(&input)
+        .map_err(|e| format!("Cannot read image {}: {e}", input.display()))?;
+    progress.done_with_message("Read image");
+
+    let filtered = filter.apply(&image);
+    progress.done_with_message(&format!("Applied {filter:?}"));
+
+    filtered.write_to_file(&output)?;
+    progress.done_with_message(&format!("Wrote {}", output.display()));
+    Ok(())
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::error::Error;
+use std::path::PathBuf;
+
+use units::{Length, Ratio};
+
+use petalo::{
+    image::{Image, filter::Filter},
+    utils::timing::Progress,
+};
@@ -1,286 +1,308 @@
 use clap::Parser;
-use ordered_float::NotNan;
-
-#[derive(Debug, Clone, clap::ValueEnum)]
-pub enum Phantom {
-    Nema7,
-    Jaszczak,
-}
 
 #[derive(clap::Parser, Debug, Clone)]
-#[clap(name = "foms", about = "Calculate NEMA7 or Jaszczak Figures of Merit (FOMs) from raw image files")]
+#[clap(name = "foms", about = "Calculate Figures of Merit (FOMs) of phantoms from image files")]
 pub struct Cli {
 
-    /// Which phantom is being analysed.
-    #[clap(value_enum)]
-    phantom: Phantom,
-
-    /// Image file to analyse
-    pub input_file: String,
-
-}
-
-// --------------------------------------------------------------------------------
-use std::error::Error;
-use units::{Length, mm, mm_, todo::Intensityf32};
-use petalo::{fom::{InRoiFn, PointValue}, io::raw::Image3D};
-use petalo::image::Image;
-use petalo::fom;
-use petalo::fom::{Sphere, ROI, centres_of_slices_closest_to};
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Cli::parse();
-    let image = Image3D::read_from_file(&args.input_file)?;
-    let image = Image::from(&image);
+    /// Which phantom is being analysed: `nema7`, `jaszczak`, the path of a
+    /// TOML phantom description, or `resolution` for NEMA NU 2 spatial
+    /// resolution from point sources
+    phantom: String,
 
-    match args.phantom {
-        Phantom::Nema7    =>    nema7_foms(&image),
-        Phantom::Jaszczak => jaszczak_foms(&image),
-    }
-}
+    /// Image files to analyse. Directories are replaced by the image files
+    /// they contain (e.g. the `NN-MM.raw` images written by `mlem`)
+    #[clap(required = true)]
+    pub inputs: Vec<PathBuf>,
 
-fn sphere_foms(
-    image         : &Image,
-    sphere_ring_r : Length,
-    sphere_z      : Length,
-    sphere_spec   : &[(u16, Length, Intensityf32)],
-    background_zs : &[Length],
-    background_xys: &[(Length, Length)],
-    background_a  : Intensityf32,
-) -> Result<Vec<fom::FOM>, Box<dyn Error>> {
-    let z_voxel_size = image.fov.voxel_size[2];
-    let z_half_width = image.fov.half_width[2];
+    /// Output format
+    #[clap(short, long, value_enum, default_value = "table")]
+    pub format: Format,
 
-    // Ensure that the ROIs are z-aligned with some slice
-    let background_zs = centres_of_slices_closest_to(
-        background_zs, z_half_width, z_voxel_size
-    );
-    let foreground_z = fom::centre_of_slice_closest_to(z_half_width, z_voxel_size)(sphere_z);
+    /// Where to write the FOMs. Standard output if not supplied
+    #[clap(short, long)]
+    pub out: Option<PathBuf>,
 
+    /// `resolution` only: ignore local maxima below this fraction of the image maximum
+    #[clap(long, default_value = "0.2")]
+    pub threshold: Ratiof32,
 
-    let mut background_roi_centres = vec![];
-    for z in &background_zs {
-        for (cx, cy) in background_xys {
-            background_roi_centres.push((*cx,*cy,*z));
-        }
-    };
+    /// `resolution` only: minimum distance between point sources
+    #[clap(long, default_value = "10 mm")]
+    pub min_separation: Length,
 
-    let spheres: Vec<_> = sphere_spec.iter()
-        .map(|&(n, d, a)| sphere(sphere_ring_r, n,d,a))
-        .collect();
-
-    let sphere_rois: Vec<_> = spheres.iter()
-        .map(|Sphere {x,y,r,..}| ROI::DiscZ((*x,*y,foreground_z), *r) )
-        .collect();
-
-    // Annotate each voxel value with its 3D position
-    let all_voxels = image.values_with_positions();
-
-    // Discard voxels which do not lie inside any of the ROIs
-    let relevant_voxels = discard_irrelevant_voxels(&sphere_rois, &background_roi_centres, &all_voxels);
-
-    Ok(spheres.into_iter()
-       .map(|sphere| contrast_and_variability(sphere, foreground_z,
-                                              background_xys, &background_zs, background_a,
-                                              &relevant_voxels).unwrap())
-       .collect())
 }
 
-fn nema7_foms(image: &Image) -> Result<(), Box<dyn Error>> {
-
-    // The 6 hot spheres
-    let ring_r = mm(114.4 / 2.0); // displacement of centre of sphere from centre of body
-    let sphere_z = mm(0.0);
-    let spheres = vec![ // (position, diameter, activity)
-        (1, mm(10.0), 4.0),
-        (2, mm(13.0), 4.0),
-        (3, mm(17.0), 4.0),
-        (4, mm(22.0), 4.0),
-        (5, mm(28.0), 4.0),
-        (0, mm(37.0), 4.0),
-    ];
-
-    // x-y centres of the 12 background ROIs
-    let bg_xys = [
-        (   0.0, -86.0), // TODO all these should be 15 mm from edge of phantom
-        (   0.0, -82.0),
-        (  60.0, -82.0),
-        ( -60.0, -82.0),
-        (-100.0, -62.0),
-        ( 100.0, -62.0),
-        (-110.0, -20.0),
-        ( 110.0, -20.0),
-        ( -85.0,  40.0),
-        (  85.0,  40.0),
-        ( -52.0,  72.0),
-        (  52.0,  72.0),
-        (   0.0,  82.0),
-    ].into_iter().map(|(x,y)| (mm(x), mm(y))).collect::<Vec<_>>();
-
-    // Background ROIs should be placed at z-slices closest to 0, ±1, ±2 cm.
-    let bg_zs = [-20.0, -10.0, 0.0, 10.0, 20.0].into_iter().map(mm).collect::<Vec<_>>();
-    let bg_activity = 1.0;
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    /// Human-readable table per image
+    Table,
+    /// One row per (image, sphere or region)
+    Csv,
+    /// Array of objects, one per (image, sphere or region)
+    Json,
+}
 
-    // Calculate the contrasts and background variabilities
-    let foms = sphere_foms(image, ring_r, sphere_z, &spheres, &bg_zs, &bg_xys, bg_activity)?;
+// --------------------------------------------------------------------------------
 
-    // The background count of the largest sphere (37mm) is also needed later
-    // for the Accuracy of Corrections calculation. Create a place to store it.
-    let mut bg_37 = None;
-    println!("Sphere diameter / mm    CRC %   bg var %    SNR %");
-    for &fom::FOM{ r, crc, bg_variability, snr } in foms.iter() {
-        if r == mm(37.0/2.0) { bg_37 = Some(crc) }
-        println!("{:20.1} {:8.1} {:8.1} {:10.1}", mm_(r) * 2.0, crc, bg_variability, snr);
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let files = expand_directories(&args.inputs)?;
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None       => Box::new(std::io::stdout().lock()),
+    };
+    if args.phantom == "resolution" {
+        resolution_foms(&args, &files, &mut out)?;
+        out.flush()?;
+        return Ok(())
     }
 
-    // --- 7.4.2 ---------------------------------------------------------------------
-    let z_voxel_size = image.fov.voxel_size[2];
-    let z_half_width = image.fov.half_width[2];
-    // ignore slices which lie within 30mm of ends.
-    let hi_limit = mm(70.0         - 30.0);
-    let lo_limit = mm(70.0 - 180.0 + 30.0);
-    // Find voxel z-centres nearest to the limits
-    let nearest = fom::centre_of_slice_closest_to(z_half_width, z_voxel_size);
-    let hi_centre = nearest(hi_limit);
-    let lo_centre = nearest(lo_limit);
-    // If centre is beyond the limit, move inwards by one voxel
-    let hi = if hi_centre <= hi_limit { hi_centre } else { nearest(hi_centre - z_voxel_size) };
-    let lo = if lo_centre >= lo_limit { lo_centre } else { nearest(lo_centre + z_voxel_size) };
-    // z-indices of first and last slices to be used
-    let index_of = |z| fom::position_to_index(z, z_half_width, z_voxel_size);
-    let   pos_of = |z| fom::index_to_position(z, z_half_width, z_voxel_size);
-    let hi_index = index_of(hi);
-    let lo_index = index_of(lo);
-
-    // Annotate each voxel value with its 3D position
-    let all_voxels = image.values_with_positions();
-    // Ignore voxels which lie outside of the lung insert
-    let lung = ROI::CylinderZ((mm(0.0), mm(0.0)), mm(30.0/2.0));
-    let filter = lung.contains_fn();
-    let lung_voxels: Vec<_> = fom::in_roi(filter, &all_voxels).collect();
-
-    // For each z-slice divide mean within ROI, by 37mm background mean
-    let bg_37 = bg_37.unwrap();
-    let aocs = (lo_index..=hi_index)
-        .map(|i| { fom::mean_in_region(ROI::DiscZ((mm(0.0), mm(0.0), pos_of(i)), mm(30.0/2.0)), &lung_voxels) })
-        .map(|v| 100.0 * v / bg_37)
-        .collect::<Vec<_>>();
-
-    println!("\nAOCs: {:.1?}", aocs);
+    let phantom = config::read_config(&args.phantom)?;
+    let foms = files.par_iter()
+        .map(|path| phantom_foms(&read_image(path)?, &phantom)
+             .map_err(|e| format!("{}: {e}", path.display()).into()))
+        .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()
+        .map_err(|e| e as Box<dyn Error>)?;
+
+    match args.format {
+        Format::Table => write_tables(&mut out, &files, &foms)?,
+        Format::Csv   => write_csv   (&mut out, &rows(&files, &foms))?,
+        Format::Json  => { serde_json::to_writer_pretty(&mut out, &rows(&files, &foms))?; writeln!(out)? },
+    }
+    // Dropping a BufWriter would silently discard any error in writing its tail
+    out.flush()?;
     Ok(())
 }
 
+fn read_image(path: &Path) -> Result<Image, Box<dyn Error + Send + Sync>> {
+    Ok(Image::from_file(path)
+       .map_err(|e| format!("Cannot read image {}: {e}", path.display()))?)
+}
 
-fn jaszczak_foms(image: &Image) -> Result<(), Box<dyn Error>> {
-
-    // The 6 hot spheres
-    let ring_r = mm(54.0); // displacement of centre of sphere from centre of body
-    let sphere_z = mm(34.0);
-    let spheres = vec![ // (position, diameter, activity)
-        (0, mm( 9.5), 4.0),
-        (1, mm(12.7), 4.0),
-        (2, mm(15.9), 4.0),
-        (3, mm(19.1), 4.0),
-        (4, mm(25.4), 4.0),
-        (5, mm(31.8), 4.0),
-    ];
+/// Replace any directories with the image files (`.raw`, `.nii`, `.nii.gz`,
+/// `.hv`, `.mhd`, `.mha`) they contain, sorted by name
+fn expand_directories(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            let mut images = std::fs::read_dir(input)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            images.retain(|p| ImageFormat::recognize(p).is_some());
+            images.sort();
+            if images.is_empty() { return Err(format!("No image files in {}", input.display()).into()) }
+            files.extend(images);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
 
-    // x-y centres of the background ROIs
-    let bg_xys = [
-        ( 73.4, -32.0),
-        ( 67.4,  35.2),
-        (  1.5,  77.8),
-        (-65.0,  38.2),
-        (-69.6, -32.4),
-        (- 2.4, -78.4),
-        (  0.0,   0.0),
-    ].into_iter().map(|(x,y)| (mm(x), mm(y))).collect::<Vec<_>>();
+/// Iteration and subset encoded in `mlem`'s `NN-MM.raw` output file names
+fn iteration_and_subset(path: &Path) -> Option<(usize, usize)> {
+    let name = path.file_name()?.to_str()?;
+    let stem = name.split('.').next()?;
+    let (iteration, subset) = stem.rsplit_once('-')?;
+    Some((iteration.parse().ok()?, subset.parse().ok()?))
+}
 
-    let bg_zs = [5.0, 15.0, 25.0, 35.0, 45.0, 55.0, 65.0, 75.0].into_iter().map(mm).collect::<Vec<_>>();
-    let bg_activity = 1.0;
+/// One sphere or region in one image. Regions have a name but no diameter.
+#[derive(Serialize)]
+struct Row {
+    image: String,
+    iteration: Option<usize>,
+    subset: Option<usize>,
+    region: Option<String>,
+    diameter_mm: Option<f32>,
+    activity: Intensityf32,
+    crc: Ratiof32,
+    bg_variability: Ratiof32,
+    snr: Ratiof32,
+    aocs: Option<Vec<Ratiof32>>,
+}
 
-    // Calculate the contrasts and background variabilities
-    let foms = sphere_foms(image, ring_r, sphere_z, &spheres, &bg_zs, &bg_xys, bg_activity)?;
+fn rows(files: &[PathBuf], foms: &[PhantomFoms]) -> Vec<Row> {
+    files.iter().zip(foms)
+        .flat_map(|(path, PhantomFoms { spheres, regions, aocs })| {
+            let (iteration, subset) = iteration_and_subset(path).unzip();
+            let spheres = spheres.iter().map(move |(sphere, fom)| Row {
+                image: path.display().to_string(),
+                iteration, subset,
+                region: None,
+                diameter_mm: Some(mm_(fom.r) * 2.0),
+                activity: sphere.a,
+                crc: fom.crc,
+                bg_variability: fom.bg_variability,
+                snr: fom.snr,
+                aocs: aocs.clone(),
+            });
+            let regions = regions.iter().map(move |(name, activity, fom)| Row {
+                image: path.display().to_string(),
+                iteration, subset,
+                region: Some(name.clone()),
+                diameter_mm: None,
+                activity: *activity,
+                crc: fom.crc,
+                bg_variability: fom.bg_variability,
+                snr: fom.snr,
+                aocs: aocs.clone(),
+            });
+            spheres.chain(regions)
+        })
+        .collect()
+}
 
-    println!("Sphere diameter / mm    CRC %   bg var %    SNR %");
-    for &fom::FOM{ r, crc, bg_variability, snr } in foms.iter() {
-        println!("{:20.1} {:8.1} {:8.1} {:10.1}", mm_(r) * 2.0, crc, bg_variability, snr);
+/// AOCs are written in a single field, separated by `;`
+fn write_csv(out: &mut impl Write, rows: &[Row]) -> std::io::Result<()> {
+    fn opt(o: Option<impl ToString>) -> String { o.map_or(String::new(), |x| x.to_string()) }
+    writeln!(out, "image,iteration,subset,region,diameter_mm,activity,crc,bg_variability,snr,aocs")?;
+    for Row { image, iteration, subset, region, diameter_mm, activity, crc, bg_variability, snr, aocs } in rows {
+        let image = csv_field(image);
+        let region = region.as_deref().map_or(String::new(), csv_field);
+        let aocs = aocs.iter().flatten().map(f32::to_string).collect::<Vec<_>>().join(";");
+        writeln!(out, "{image},{},{},{region},{},{activity},{crc},{bg_variability},{snr},{aocs}",
+                 opt(*iteration), opt(*subset), opt(*diameter_mm))?;
     }
-
     Ok(())
 }
 
-/// Place FOM sphere in Nth/6 angular position, with given diameter and activity
-fn sphere(from_centre: Length, sphere_position: u16, diameter: Length, activity: Intensityf32) -> Sphere {
-    let r = from_centre; // 114.4 / 2.0; // Radial displacement from centre
-    let radians = std::f32::consts::TAU * sphere_position as f32 / 6.0;
-    Sphere{x:r * radians.cos(), y:r * radians.sin(), r: diameter / 2.0, a: activity}
+/// Quote `field` if it contains CSV delimiters
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) { format!("\"{}\"", field.replace('"', "\"\"")) }
+    else { field.to_string() }
 }
 
-// TODO this duplicates a lot of the functionality of Image::foms. It's here
-// largely because of the idiosyncrasy of the NEMA7 requirements.
-fn contrast_and_variability(sphere: Sphere,
-                            foreground_z: Length,
-                            background_xys: &[(Length, Length)],
-                            background_zs : &[Length],
-                            bg_activity: f32,
-                            voxels: &[PointValue],
-) -> Option<fom::FOM> {
-    // Inspect single foreground ROI
-    let Sphere { x, y, r, a: sphere_activity } = sphere;
-    let in_sphere: Vec<_> = fom::in_roi(ROI::DiscZ((x, y, foreground_z), r).contains_fn(), voxels)
-        .map(|(_,v)| v)
-        .collect();
-    let (sphere_mean, sphere_sigma) = fom::mu_and_sigma(&in_sphere)?;
-    // Inspect multiple background ROIs
-    let mut bg_means = vec![];
-    for (x,y) in background_xys {
-        for z in background_zs {
-            bg_means.push(fom::mean_in_region(ROI::DiscZ((*x, *y,*z), r), voxels));
+fn write_tables(out: &mut impl Write, files: &[PathBuf], foms: &[PhantomFoms]) -> std::io::Result<()> {
+    for (n, (path, PhantomFoms { spheres, regions, aocs })) in files.iter().zip(foms).enumerate() {
+        if files.len() > 1 {
+            if n > 0 { writeln!(out)? }
+            writeln!(out, "{}", path.display())?;
+        }
+        if !spheres.is_empty() {
+            writeln!(out, "Sphere diameter / mm    CRC %   bg var %    SNR %")?;
+            for (_, FOM{ r, crc, bg_variability, snr }) in spheres {
+                writeln!(out, "{:20.1} {:8.1} {:8.1} {:10.1}", mm_(*r) * 2.0, crc, bg_variability, snr)?;
+            }
+        }
+        if !regions.is_empty() {
+            writeln!(out, "{:>20}    CRC %   bg var %    SNR %", "Region")?;
+            for (name, _, RegionFom { crc, bg_variability, snr }) in regions {
+                writeln!(out, "{name:>20} {crc:8.1} {bg_variability:8.1} {snr:10.1}")?;
+            }
+        }
+        if let Some(aocs) = aocs {
+            writeln!(out, "\nAOCs: {:.1?}", aocs)?;
         }
     }
-    // Calculate background variability
-    let (bg_mean, bg_sd) = fom::mu_and_sigma(&bg_means)?;
-    let bg_variability = 100.0 * bg_sd / bg_mean;
-    // Calculate contrast
-    let crc = fom::crc(sphere_mean, sphere_activity, bg_mean, bg_activity);
-    let snr = 100.0 * (sphere_mean - bg_mean) / sphere_sigma;
-    let snr = if sphere_activity > bg_activity { snr } else { -snr };
-    Some(fom::FOM{ r, crc, bg_variability, snr })
+    Ok(())
 }
 
-/// Make an f32 that is Ord. Panic if NaN
-fn not_nan(f: f32) -> NotNan<f32> { NotNan::new(f).unwrap() }
-
-/// Discard voxels which do not lie inside any of the ROIs
-fn discard_irrelevant_voxels(
-    sphere_rois           : &[ROI],
-    background_roi_centres: &[(Length, Length, Length)],
-    voxels                : &[PointValue],
-
-) -> Vec<PointValue> {
-
-    let max_roi_radius: Length = sphere_rois.iter()
-        .map(ROI::r)
-        .max_by_key(|&f| not_nan(mm_(f)))
-        .unwrap();
-
-    // Background ROIs corresponding to biggest sphere
-    let bg_rois = background_roi_centres.iter()
-        .map(|&(x,y,z)| ROI::DiscZ((x,y,z), max_roi_radius))
-        .collect::<Vec<_>>();
+// --- NEMA NU 2 spatial resolution ------------------------------------------------------
+
+/// One point source in one image
+#[derive(Serialize)]
+struct ResolutionRow {
+    image: String,
+    iteration: Option<usize>,
+    subset: Option<usize>,
+    x_mm: f32,
+    y_mm: f32,
+    z_mm: f32,
+    peak: Intensityf32,
+    radial_fwhm_mm: Option<f32>,
+    radial_fwtm_mm: Option<f32>,
+    tangential_fwhm_mm: Option<f32>,
+    tangential_fwtm_mm: Option<f32>,
+    axial_fwhm_mm: Option<f32>,
+    axial_fwtm_mm: Option<f32>,
+}
 
-    // Individual functions checking whether point lies in a single background ROI
-    let in_roi_checkers: Vec<InRoiFn> = bg_rois.iter()
-        .chain(sphere_rois)
-        .map(ROI::contains_fn)
+fn resolution_foms(args: &Cli, files: &[PathBuf], out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let rows = files.par_iter()
+        .map(|path| {
+            let image = read_image(path)?;
+            let (iteration, subset) = iteration_and_subset(path).unzip();
+            let sources = resolution::find_point_sources(&image, args.threshold, args.min_separation);
+            if sources.is_empty() { return Err(format!("No point sources found in {}", path.display()).into()) }
+            Ok(sources.into_iter()
+               .map(|source| {
+                   let Resolution { source, radial, tangential, axial } = resolution::resolution(&image, source);
+                   let mm = |w: Option<Length>| w.map(mm_);
+                   ResolutionRow {
+                       image: path.display().to_string(),
+                       iteration, subset,
+                       x_mm: mm_(source.position.x),
+                       y_mm: mm_(source.position.y),
+                       z_mm: mm_(source.position.z),
+                       peak: source.peak,
+                       radial_fwhm_mm:     mm(radial    .fwhm), radial_fwtm_mm:     mm(radial    .fwtm),
+                       tangential_fwhm_mm: mm(tangential.fwhm), tangential_fwtm_mm: mm(tangential.fwtm),
+                       axial_fwhm_mm:      mm(axial     .fwhm), axial_fwtm_mm:      mm(axial     .fwtm),
+                   }
+               })
+               .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()
+        .map_err(|e| e as Box<dyn Error>)?
+        .into_iter()
+        .flatten()
         .collect::<Vec<_>>();
 
-    // Combined function checking whether a point lies in any of the ROIs
-    let in_some_roi = |p| in_roi_checkers.iter().any(|in_this_roi| in_this_roi(p));
-
-    // Discard voxels which do not lie inside any of the ROIs
-    voxels.iter().cloned()
-        .filter(|(p, _)| in_some_roi(*p))
-        .collect::<Vec<_>>()
+    let opt = |o: Option<f32>| o.map_or("-".to_string(), |w| format!("{w:.2}"));
+    match args.format {
+        Format::Table => {
+            for (n, path) in files.iter().enumerate() {
+                let image = path.display().to_string();
+                if files.len() > 1 {
+                    if n > 0 { writeln!(out)? }
+                    writeln!(out, "{image}")?;
+                }
+                writeln!(out, "{:^24}  {:^15}  {:^15}  {:^15}", "Source (x y z) / mm", "Radial / mm", "Tangential / mm", "Axial / mm")?;
+                writeln!(out, "{:24}  {:^15}  {:^15}  {:^15}", "", "FWHM   FWTM", "FWHM   FWTM", "FWHM   FWTM")?;
+                for r in rows.iter().filter(|r| r.image == image) {
+                    writeln!(out, "{:8.1}{:8.1}{:8.1}  {:>7} {:>7}  {:>7} {:>7}  {:>7} {:>7}",
+                             r.x_mm, r.y_mm, r.z_mm,
+                             opt(r.radial_fwhm_mm),     opt(r.radial_fwtm_mm),
+                             opt(r.tangential_fwhm_mm), opt(r.tangential_fwtm_mm),
+                             opt(r.axial_fwhm_mm),      opt(r.axial_fwtm_mm))?;
+                }
+            }
+        },
+        Format::Csv => {
+            let opt = |o: Option<f32>| o.map_or(String::new(), |w| w.to_string());
+            let opt_n = |o: Option<usize>| o.map_or(String::new(), |n| n.to_string());
+            writeln!(out, "image,iteration,subset,x_mm,y_mm,z_mm,peak,radial_fwhm_mm,radial_fwtm_mm,\
+                           tangential_fwhm_mm,tangential_fwtm_mm,axial_fwhm_mm,axial_fwtm_mm")?;
+            for r in &rows {
+                writeln!(out, "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                         csv_field(&r.image), opt_n(r.iteration), opt_n(r.subset), r.x_mm, r.y_mm, r.z_mm, r.peak,
+                         opt(r.radial_fwhm_mm),     opt(r.radial_fwtm_mm),
+                         opt(r.tangential_fwhm_mm), opt(r.tangential_fwtm_mm),
+                         opt(r.axial_fwhm_mm),      opt(r.axial_fwtm_mm))?;
+            }
+        },
+        Format::Json => { serde_json::to_writer_pretty(&mut *out, &rows)?; writeln!(out)? },
+    }
+    Ok(())
 }
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use units::{Length, mm_, todo::{Intensityf32, Ratiof32}};
+
+use petalo::{
+    config::fom as config,
+    fom::{FOM, PhantomFoms, RegionFom, phantom_foms, resolution::{self, Resolution}},
+    image::Image,
+    io::ImageFormat,
+};
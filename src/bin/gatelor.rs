@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use itertools::Itertools;
+
+use petalo::{
+    io::{gate::{self, Coincidence}, hdf5::{ExtendedLor, set_lor_version}},
+    utils::group_digits,
+};
+
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "gatelor", about = "Convert GATE coincidence output into an HDF5 LOR dataset")]
+pub struct Cli {
+    /// GATE coincidence files: binary if their extension is `.bin`, ASCII otherwise
+    pub inputs: Vec<PathBuf>,
+
+    /// HDF5 output file for the LORs
+    #[clap(short, long)]
+    pub out: PathBuf,
+
+    /// Chunk size in output HDF5 file
+    #[clap(short, long, default_value = "1000000")]
+    pub chunk_size: usize,
+
+    /// Number of volume ids in each single of binary files (6 for a cylindricalPET)
+    #[clap(short, long, default_value = "6")]
+    pub volume_ids: usize,
+}
+
+type Coincidences = Box<dyn Iterator<Item = Result<Coincidence, Box<dyn Error>>>>;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Cli { inputs, out, chunk_size, volume_ids } = Cli::parse();
+    let chunk_size = chunk_size.max(1);
+
+    let file = hdf5::File::create(&out)?;
+    let dataset = file
+        .create_group("reco_info")?
+        .new_dataset::<ExtendedLor>()
+        .chunk(chunk_size)
+        .shape(0..)
+        .create("lors")?;
+    set_lor_version(&dataset, 2)?;
+
+    let mut written = 0;
+    for input in &inputs {
+        let coincidences: Coincidences = if is_binary(input) {
+            Box::new(gate::read_binary(input, volume_ids)?)
+        } else {
+            Box::new(gate::read_ascii(input)?)
+        };
+        let before = written;
+        for chunk in &coincidences.chunks(chunk_size) {
+            let lors = chunk
+                .map(|coincidence| coincidence.map(|c| ExtendedLor::from(&c)))
+                .collect::<Result<Vec<_>, _>>()?;
+            dataset.resize(written + lors.len())?;
+            dataset.write_slice(&lors, written..written + lors.len())?;
+            written += lors.len();
+        }
+        println!("Read {:>12} coincidences from {}", group_digits(written - before), input.display());
+    }
+    println!("Wrote {} LORs to {}", group_digits(written), out.display());
+    Ok(())
+}
+
+fn is_binary(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bin"))
+}
@@ -0,0 +1,98 @@
+// ----------------------------------- CLI -----------------------------------
+use clap::Parser;
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "imagecompare", about = "Compare images with a reference (e.g. ground truth from `imageprimaries`)")]
+pub struct Cli {
+
+    /// Reference image
+    pub reference: PathBuf,
+
+    /// Images to compare with the reference. Several noise realisations of the
+    /// same reconstruction can be combined into bias and variance maps
+    #[clap(required = true)]
+    pub images: Vec<PathBuf>,
+
+    /// Scale each image to match the total activity of the reference
+    #[clap(short, long)]
+    pub scale: bool,
+
+    /// TOML file of named ROIs (`[[region]]` tables) in which to report metrics,
+    /// in addition to the whole image
+    #[clap(short, long)]
+    pub rois: Option<PathBuf>,
+
+    /// FWHM of the Gaussian window used for SSIM [default: 3.5 voxels]
+    #[clap(long)]
+    pub ssim_fwhm: Option<Length>,
+
+    /// Write voxelwise bias (mean of images minus reference) to this file
+    #[clap(long)]
+    pub bias_map: Option<PathBuf>,
+
+    /// Write voxelwise sample variance of images to this file
+    #[clap(long)]
+    pub variance_map: Option<PathBuf>,
+}
+
+// --------------------------------------------------------------------------------
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let read = |path: &PathBuf| Image::from_file(path)
+        .map_err(|e| format!("Cannot read image {}: {e}", path.display()));
+
+    let reference = read(&args.reference)?;
+    let mut rois = vec![("all".to_string(), None)];
+    if let Some(path) = &args.rois {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read ROIs from {}: {e}", path.display()))?;
+        let regions: Regions = toml::from_str(&text)?;
+        rois.extend(regions.rois()?.into_iter().map(|(name, roi)| (name, Some(roi))));
+    }
+    let ssim_fwhm = args.ssim_fwhm.unwrap_or_else(|| {
+        let v = reference.fov.voxel_size;
+        3.5 * v.x.max(v.y).max(v.z)
+    });
+
+    let images = args.images.iter()
+        .map(|path| {
+            let image = read(path)?;
+            if !same_fov(&image.fov, &reference.fov) {
+                eprintln!("Resampling {} onto the FOV of the reference", path.display());
+            }
+            let image = compare::align(&image, &reference);
+            Ok(if args.scale { compare::scale_to_total(&image, &reference) } else { image })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    println!("{:>30} {:>20} {:>12} {:>8} {:>8} {:>8} {:>8}",
+             "image", "roi", "rmse", "nrmse", "ssim", "bias %", "corr");
+    for (path, image) in args.images.iter().zip(&images) {
+        let name = path.file_name().map_or(path.display().to_string(), |n| n.to_string_lossy().into());
+        for (roi_name, roi) in &rois {
+            let Metrics { rmse, nrmse, ssim, bias, correlation } =
+                compare::compare(image, &reference, roi.as_ref(), ssim_fwhm)
+                .map_err(|e| format!("{name}, ROI `{roi_name}`: {e}"))?;
+            println!("{name:>30} {roi_name:>20} {rmse:12.4e} {nrmse:8.4} {ssim:8.4} {:8.2} {correlation:8.4}",
+                     100.0 * bias);
+        }
+    }
+
+    if args.bias_map.is_some() || args.variance_map.is_some() {
+        let (bias, variance) = compare::bias_and_variance(&images, &reference)?;
+        if let Some(path) = &args.bias_map     { bias    .write_to_file(path)?; }
+        if let Some(path) = &args.variance_map { variance.write_to_file(path)?; }
+    }
+    Ok(())
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{error::Error, path::PathBuf};
+
+use units::Length;
+
+use petalo::{
+    config::roi::Regions,
+    image::{Image, compare::{self, Metrics}, resample::same_fov},
+};
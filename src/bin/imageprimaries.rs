@@ -3,7 +3,7 @@ use indicatif::{ProgressBar, ProgressStyle};
 
 // ----------------------------------- CLI -----------------------------------
 use clap::Parser;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use petalo::utils::parse_triplet;
 
@@ -15,7 +15,7 @@ pub struct Cli {
     #[clap()]
     pub input_files: Vec<PathBuf>,
 
-    /// Image output file
+    /// Image output file, in the format indicated by its extension (see README)
     #[clap(short, long, default_value = "primaries.raw")]
     pub out_file: String,
 
@@ -39,6 +39,7 @@ use units::{
 use petalo::{
     FOV,
     image::Image,
+    io::raw::{ImageKind, Metadata},
     io::hdf5::mc::{read_primaries, Primary},
 };
 type L = Lengthf32;
@@ -123,7 +124,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         format!("Wrote image with phisical size {} x {} x {} and {} x {} x {} voxels to {}",
                                                 xe,  ye,  ze,    xn,  yn,  zn,    out_file);
     progress.finish_with_message(message.clone());
-    petalo::io::raw::Image3D::from(&image).write_to_file(out_file)?;
+    let metadata = Metadata { units: "counts".into(), ..Metadata::new(ImageKind::Activity) };
+    image.write_to_file_with(Path::new(&out_file), &metadata)?;
     println!("{}", message);
     Ok(())
 }
@@ -1,16 +1,19 @@
 use clap::Parser;
-use petalo::io;
-use petalo::io::hdf5::Hdf5Lor;
-use petalo::config::mlem::Bounds;
+use petalo::io::hdf5::{
+    ExtendedLor, Hdf5Lor,
+    join::{common_lor_version, join},
+    mc::{Primary, Vertex},
+    sensors::{Qtot, SensorHit},
+};
 
 
 #[derive(clap::Parser, Debug, Clone)]
 #[clap(name = "joinlorhdf", about = "Combine datasets from separate HDF5 files into a single one")]
 pub struct Cli {
-    /// HDF5 input file with (t1 t2 x1 y1 z1 x2 y2 z2)
+    /// HDF5 input files containing the dataset
     pub inputs: Vec<String>,
 
-    /// HDF5 output file for LORs found in input file
+    /// HDF5 output file for the combined dataset
     #[clap(short, long)]
     pub outfile: String,
 
@@ -22,33 +25,52 @@ pub struct Cli {
     #[clap(short, long, default_value = "lors")]
     pub dataset: String,
 
+    /// Type of the rows in the dataset [default: the LOR record version of
+    /// the inputs, which must all have the same one]
+    #[clap(short, long, value_enum)]
+    pub r#type: Option<Type>,
+
+    /// Number of rows read and written at a time, and chunk size of the output
+    #[clap(short, long, default_value = "100000")]
+    pub chunk_size: usize,
+
+    /// Offset the event ids in each file, to keep them unique across files
+    #[clap(short = 'e', long)]
+    pub offset_event_ids: bool,
+
     // TODO allow using different group/dataset in output
 }
 
-
-type Data = Hdf5Lor; // TODO: add CLI switches for selecting type
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum Type { Lor, ExtendedLor, Vertex, Primary, SensorHit, Qtot }
 
 
 fn main() -> hdf5::Result<()> {
     let args = Cli::parse();
-    let mut joined = Vec::<Data>::new();
-
-    // ----- read data from separate files -------------------------------------------
-    for filename in args.inputs {
-        println!("Reading data from {}", filename);
-        let path = format!("{}/{}", args.group, args.dataset);
-        let data = io::hdf5::read_dataset::<Data>(&filename, &path, Bounds::none())?;
-        joined.extend_from_slice(data.as_slice().unwrap());
-    }
+    let Cli { inputs, outfile, group, dataset, r#type, chunk_size, offset_event_ids } = args;
+    let path = format!("{group}/{dataset}");
 
-    // --- write combined data to single file ----------------------------------------
-    let outname = args.outfile;
-    println!("Writing data to {}", outname);
-    hdf5::File::create(outname)?
-        .create_group(&args.group)?
-        .new_dataset_builder()
-        .with_data(&joined)
-        .create(args.dataset.as_str())?;
+    let r#type = match r#type {
+        Some(r#type) => r#type,
+        None => match common_lor_version(&inputs, &path)? {
+            1 => Type::Lor,
+            2 => Type::ExtendedLor,
+            v => return Err(format!("Unknown LOR record version {v} in {path}").into()),
+        },
+    };
+    if offset_event_ids && matches!(r#type, Type::Lor) {
+        return Err("Version 1 LORs carry no event ids to be offset: use `--type extended-lor`".into())
+    }
+    let offset = offset_event_ids;
+    let n = match r#type {
+        Type::Lor         => join::<Hdf5Lor    >(&inputs, &path, &outfile, chunk_size, offset)?,
+        Type::ExtendedLor => join::<ExtendedLor>(&inputs, &path, &outfile, chunk_size, offset)?,
+        Type::Vertex      => join::<Vertex     >(&inputs, &path, &outfile, chunk_size, offset)?,
+        Type::Primary     => join::<Primary    >(&inputs, &path, &outfile, chunk_size, offset)?,
+        Type::SensorHit   => join::<SensorHit  >(&inputs, &path, &outfile, chunk_size, offset)?,
+        Type::Qtot        => join::<Qtot       >(&inputs, &path, &outfile, chunk_size, offset)?,
+    };
+    println!("Wrote {} rows to {outfile}", petalo::utils::group_digits(n));
 
     Ok(())
 }
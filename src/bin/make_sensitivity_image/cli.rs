@@ -1,12 +1,24 @@
 #[derive(clap::Parser, Debug, Clone)]
-#[clap(name = "make_sensitivity_image", about = "Create sensitivity image from a density image")]
+#[clap(name = "make_sensitivity_image", about = "Create sensitivity image from a density or attenuation (μ) image")]
 pub struct Cli {
 
-    /// The density image to use in the forward projection
+    /// The density image to use in the forward projection, in any format
+    /// recognized by its extension (raw, NIfTI-1, Interfile, MetaImage)
     #[clap(short, long)]
     pub input: PathBuf,
 
-    /// Where to write the resulting sensitivity image
+    /// The input is a μ map (e.g. derived from CT), rather than a density
+    /// image. Implied by raw images whose header marks them as μ maps
+    #[clap(long)]
+    pub mu: bool,
+
+    /// Units of the μ map. Required unless it is a raw image whose header
+    /// records them
+    #[clap(long, value_enum)]
+    pub mu_units: Option<MuUnits>,
+
+    /// Where to write the resulting sensitivity image, in the format indicated
+    /// by its extension [default: sensitivity.raw]
     #[clap(short, long)]
     pub output: Option<PathBuf>,
 
@@ -18,7 +30,8 @@ pub struct Cli {
     #[clap(long, short='r')]
     pub r_min: Length,
 
-    /// Conversion from density to attenuation coefficient in cm^2 / g
+    /// Conversion from density to attenuation coefficient in cm^2 / g.
+    /// Ignored if the input is a μ map
     #[clap(long, default_value = "0.095")]
     pub rho_to_mu: Lengthf32,
 
@@ -34,6 +47,14 @@ pub struct Cli {
     pub detector_type: DetectorType,
 }
 
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuUnits {
+    /// cm⁻¹, as used by STIR and CASToR
+    #[value(name = "cm-1")] PerCm,
+    /// mm⁻¹, as used by petalo
+    #[value(name = "mm-1")] PerMm,
+}
+
 #[derive(clap::Parser, Debug, Clone)]
 pub enum DetectorType {
 
@@ -5,7 +5,7 @@ use cli::*;
 
 fn main() -> Result<(), Box<dyn Error>> {
 
-    let Cli { input, output, detector_length, r_min, detector_type, rho_to_mu, n_threads, output_image_dims } = Cli::parse();
+    let Cli { input, output, mu, mu_units, detector_length, r_min, detector_type, rho_to_mu, n_threads, output_image_dims } = Cli::parse();
 
     // Interpret rho_to_mu as converting from [rho in g/cm^3] to [mu in cm^-1]
     let rho_to_mu: AreaPerMass = {
@@ -32,10 +32,15 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     report_time("Startup");
 
-    let density = Image::from_raw_file(&input)?;
-    report_time(&format!("Read density image {:?}", input));
-    // Convert from [density in kg/m^3] to [mu in mm^-1]
-    let attenuation = density_image_into_attenuation_image(density, rho_to_mu);
+    let (image, metadata) = read_input(&input)?;
+    let attenuation = if mu || metadata.kind == ImageKind::Attenuation {
+        report_time(&format!("Read μ image {:?}", input));
+        attenuation_image_in_mm(image, &metadata, mu_units, &input)?
+    } else {
+        report_time(&format!("Read density image {:?}", input));
+        // Convert from [density in kg/m^3] to [mu in mm^-1]
+        density_image_into_attenuation_image(image, rho_to_mu)
+    };
 
     // TOF should not be used as LOR attenuation is independent of decay point
     let parameters = Siddon::notof().data();
@@ -68,11 +73,47 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let outfile = output.or_else(|| Some("sensitivity.raw".into())).unwrap();
     std::fs::create_dir_all(PathBuf::from(&outfile).parent().unwrap())?; // TODO turn into utility with cleaner interface
-    sensitivity.write_to_raw_file(&outfile)?;
+    sensitivity.write_to_file_with(&outfile, &Metadata::new(ImageKind::Sensitivity))?;
     report_time(&format!("Wrote sensitivity image to {:?}", outfile));
     Ok(())
 }
 
+/// Read the input image, along with its metadata, which is only recorded in v2
+/// raw images
+fn read_input(path: &Path) -> Result<(Image, Metadata), Box<dyn Error>> {
+    Ok(match ImageFormat::from_path(path) {
+        ImageFormat::Raw => raw::read_image(path)?,
+        _ => (Image::from_file(path)?, Metadata::default()),
+    })
+}
+
+/// Convert a μ image to mm^-1, from the units recorded in its header or, if
+/// there are none, given by `mu_units`. Fails if the image is known to be
+/// something else, or its units are unknown or inconsistent.
+fn attenuation_image_in_mm(mut image: Image, metadata: &Metadata, mu_units: Option<MuUnits>, path: &Path) -> Result<Image, Box<dyn Error>> {
+    let Metadata { kind, units, .. } = metadata;
+    if ![ImageKind::Unknown, ImageKind::Attenuation].contains(kind) {
+        return Err(format!("{} is not a μ image: its header says {kind:?}", path.display()).into())
+    }
+    let recorded = match units.as_str() {
+        ""      => None,
+        "mm^-1" => Some(MuUnits::PerMm),
+        "cm^-1" => Some(MuUnits::PerCm),
+        other   => return Err(format!("μ image {} is in {other}: expected mm^-1 or cm^-1", path.display()).into()),
+    };
+    let units = match (recorded, mu_units) {
+        (Some(recorded), Some(given)) if recorded != given =>
+            return Err(format!("μ image {} is in {units}, not the units given by --mu-units", path.display()).into()),
+        (Some(units), _) | (None, Some(units)) => units,
+        (None, None) =>
+            return Err(format!("The units of μ image {} are unknown: specify them with --mu-units", path.display()).into()),
+    };
+    if units == MuUnits::PerCm {
+        for voxel in &mut image.data { *voxel /= 10.0 }
+    }
+    Ok(image)
+}
+
 /// TODO Just trying an ugly hack for normalizing the image. Do something sensible instead!
 fn normalize(data: &mut ImageData, n: usize) { for e in data.iter_mut() { *e /= n as f32 } }
 
@@ -97,13 +138,14 @@ fn density_image_into_attenuation_image(density: Image, rho_to_mu: AreaPerMass)
 use std::{
     error::Error,
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use petalo::{
     utils::group_digits,
     FOV,
     image::{Image, ImageData},
+    io::{ImageFormat, raw::{self, ImageKind, Metadata}},
     projectors::{Projector, Siddon}, discrete::Discretize,
 };
 
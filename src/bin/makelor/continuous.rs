@@ -1,16 +1,18 @@
 #[allow(nonstandard_style)]
-pub (super) fn lor_from_first_vertices(vertices: &[Vertex]) -> Option<Hdf5Lor> {
+pub (super) fn lor_from_first_vertices(vertices: &[Vertex]) -> Option<ExtendedLor> {
     let mut in_lxe = vertices_in_scintillator(vertices);
-    let Vertex{x:x2, y:y2, z:z2, t:t2, pre_KE: E2, ..} = in_lxe.find(|v| v.track_id == 2)?;
+    let Vertex{x:x2, y:y2, z:z2, t:t2, pre_KE: E2, event_id, ..} = in_lxe.find(|v| v.track_id == 2)?;
     let Vertex{x:x1, y:y1, z:z1, t:t1, pre_KE: E1, ..} = in_lxe.find(|v| v.track_id == 1)?;
-    Some(Hdf5Lor {
+    Some(ExtendedLor {
+        event_id, t1, t2,
         dt: t2 - t1,                   x1, y1, z1,   x2, y2, z2,
-        q1: f32::NAN, q2: f32::NAN,        E1,           E2,
+        E1, E2, truth: truth_from_vertices(vertices) as u8,
+        ..ExtendedLor::unknown()
     })
 }
 
 #[allow(nonstandard_style)]
-pub (super) fn lor_from_barycentre_of_vertices(vertices: &[Vertex]) -> Option<Hdf5Lor> {
+pub (super) fn lor_from_barycentre_of_vertices(vertices: &[Vertex]) -> Option<ExtendedLor> {
     let (a,b): (Vec<_>, Vec<_>) = vertices
         .iter()
         .filter   (|v| v.volume_id == 0 && v.parent_id <= 2)
@@ -19,29 +21,42 @@ pub (super) fn lor_from_barycentre_of_vertices(vertices: &[Vertex]) -> Option<Hd
     let Barycentre { x: x1, y: y1, z: z1, t: t1, E: E1 } = vertex_barycentre(&a)?;
     let Barycentre { x: x2, y: y2, z: z2, t: t2, E: E2 } = vertex_barycentre(&b)?;
 
-    let nan = f32::NAN; let q1 = nan; let q2 = nan;
-
-    Some(Hdf5Lor {
+    Some(ExtendedLor {
+        event_id: vertices.first()?.event_id,
+        t1: ns_(t1), t2: ns_(t2),
         dt: ns_(t2 - t1),
         x1: mm_(x1), y1: mm_(y1), z1: mm_(z1),
         x2: mm_(x2), y2: mm_(y2), z2: mm_(z2),
-        q1, q2, E1, E2,
+        E1, E2, truth: truth_from_vertices(vertices) as u8,
+        ..ExtendedLor::unknown()
     })
 }
 
-pub (super) fn lor_from_hits(xyzs: &SensorMap) -> impl Fn(&[QT]) -> Option<Hdf5Lor> + '_ {
+/// True, if neither gamma interacted before reaching the scintillator
+pub (super) fn truth_from_vertices(vertices: &[Vertex]) -> Truth {
+    let first = |track_id| vertices.iter().find(|v| v.track_id == track_id);
+    match (first(1), first(2)) {
+        (Some(a), Some(b)) if a.volume_id == 0 && b.volume_id == 0 => Truth::True,
+        (Some(_), Some(_))                                         => Truth::Scatter,
+        _                                                          => Truth::Unknown,
+    }
+}
+
+pub (super) fn lor_from_hits(xyzs: &SensorMap) -> impl Fn(&[QT]) -> Option<ExtendedLor> + '_ {
     move |hits| {
         let (cluster_a, cluster_b) = group_into_clusters(hits, xyzs)?;
         //println!("{} + {} = {} ", cluster_a.len(), cluster_b.len(), hits.len());
         let (p1, t1) = cluster_xyzt(&cluster_a, xyzs)?;
         let (p2, t2) = cluster_xyzt(&cluster_b, xyzs)?;
         //println!("{:?} {:?}", xyzt_a, xyzt_b);
-        Some(Hdf5Lor {
+        Some(ExtendedLor {
+            event_id: hits.first()?.event_id,
+            t1: ns_(t1), t2: ns_(t2),
             dt: ns_(t2 - t1),
             x1: mm_(p1.x), y1: mm_(p1.y), z1: mm_(p1.z),
             x2: mm_(p2.x), y2: mm_(p2.y), z2: mm_(p2.z),
             // TODO qs and Es missing
-            q1: f32::NAN, q2: f32::NAN,   E1: f32::NAN, E2: f32::NAN,
+            ..ExtendedLor::unknown()
         })
     }
 }
@@ -69,7 +84,7 @@ pub (super) fn lor_from_hits_dbscan(
     xyzs: &SensorMap,
     min_points: usize,
     tolerance: Length
-) -> impl Fn(&[QT]) -> Option<Hdf5Lor> + '_ {
+) -> impl Fn(&[QT]) -> Option<ExtendedLor> + '_ {
     use linfa_clustering::AppxDbscan;
     use linfa::traits::Transformer;
     move |hits| {
@@ -99,12 +114,13 @@ pub (super) fn lor_from_hits_dbscan(
         }
         let a = cluster_centroid(cluster[0].clone())?;
         let b = cluster_centroid(cluster[1].clone())?;
-        Some(Hdf5Lor {
+        Some(ExtendedLor {
+            event_id: hits.first()?.event_id,
             dt: 0.0, // TODO dt missing
             x1: a[0], y1: a[1], z1: a[2],
             x2: b[0], y2: b[1], z2: b[2],
             // TODO qs and Es missing
-            q1: f32::NAN, q2: f32::NAN,   E1: f32::NAN, E2: f32::NAN,
+            ..ExtendedLor::unknown()
         })
     }
 }
@@ -221,7 +237,7 @@ fn vertex_barycentre(vertices: &[&Vertex]) -> Option<Barycentre> {
 // ----- Imports -----------------------------------------------------------------------------------------
 use petalo::{
     Point,
-    io::hdf5::{Hdf5Lor, mc::Vertex},
+    io::hdf5::{ExtendedLor, Truth, mc::Vertex},
 };
 use crate::{QT, SensorMap, vertices_in_scintillator};
 use units::{
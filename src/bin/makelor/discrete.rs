@@ -1,5 +1,5 @@
 #[allow(nonstandard_style)]
-pub (crate) fn lor_from_discretized_vertices(d: &Reco) -> impl Fn(&[Vertex]) -> Option<Hdf5Lor> + Send + Sync {
+pub (crate) fn lor_from_discretized_vertices(d: &Reco) -> impl Fn(&[Vertex]) -> Option<ExtendedLor> + Send + Sync {
     let &Reco::Discrete { r_min, dr, dz, da, adjust } = d else {
         panic!("lor_from_discretized_vertices called with variant other than Reco::Discrete")
     };
@@ -32,7 +32,16 @@ pub (crate) fn lor_from_discretized_vertices(d: &Reco) -> impl Fn(&[Vertex]) ->
         let (E1, (x1, y1, z1)) = box_with_higest_total_energy_centre_doi(&vs1, discretize)?;
         let (E2, (x2, y2, z2)) = box_with_higest_total_energy_centre_doi(&vs2, discretize)?;
 
-        Some(Hdf5Lor { dt: 0.0, x1, y1, z1, x2, y2, z2, q1: f32::NAN, q2: f32::NAN, E1, E2 })
+        let doi         = |x: f32, y: f32| x.hypot(y) - mm_(r_min);
+        let crystal_id  = |x, y, z| discretize.crystal_id(mm(x), mm(y), mm(z));
+        Some(ExtendedLor {
+            event_id: vertices.first()?.event_id,
+            dt: 0.0, x1, y1, z1, x2, y2, z2, E1, E2,
+            doi1: doi(x1, y1), crystal1: crystal_id(x1, y1, z1),
+            doi2: doi(x2, y2), crystal2: crystal_id(x2, y2, z2),
+            truth: truth_from_vertices(vertices) as u8,
+            ..ExtendedLor::unknown()
+        })
 
     }
 }
@@ -73,10 +82,10 @@ fn box_with_higest_total_energy_centre_doi(vertices: &[Vertex], discretize: Disc
 
 
 // ----- Imports -----------------------------------------------------------------------------------------
-use crate::{Reco, vertices_in_scintillator};
+use crate::{Reco, vertices_in_scintillator, continuous::truth_from_vertices};
 use petalo::{
     discrete::Discretize,
-    io::hdf5::{Hdf5Lor, mc::Vertex},
+    io::hdf5::{ExtendedLor, mc::Vertex},
 };
 use units::{mm, mm_, keV, keV_, Length};
 
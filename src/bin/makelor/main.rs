@@ -34,14 +34,15 @@ fn main() -> hdf5::Result<()> {
     let file = hdf5::File::create(&args.out)?;
     let dataset = file
         .create_group("reco_info")? // TODO rethink all the HDF% group names
-        .new_dataset::<Hdf5Lor>()
+        .new_dataset::<ExtendedLor>()
         .chunk(chunk_size)
         .shape(0..)
         .create("lors")?;
+    io::hdf5::set_lor_version(&dataset, 2)?;
 
     let mut n_signal: u64 = 0;
     let mut n_noise       = 0;
-    let sigma = units::ratio_(args.fwhm) / 2.35;
+    let sigma = units::ratio_(args.fwhm) / FWHM_PER_SIGMA;
     let cut = 511.0 * (1.0 - 3.0 * sigma);
     let mut classify = |e: f32| {
         match e {
@@ -84,12 +85,12 @@ fn compose_steps<'p, T, E, G, M>(
     extract_rows_from_table: E,
     group_by_event         : G,
     make_one_lor           : M,
-) ->  Box<dyn Iterator<Item = Hdf5Lor> + 'p>
+) ->  Box<dyn Iterator<Item = ExtendedLor> + 'p>
 where
     T: Send + 'p,
     E: Fn(&Path)  -> hdf5::Result<Vec<T>> + Send + Sync + 'p,
     G: Fn(Vec<T>) -> Vec<Vec<T>>          + Send + Sync + 'p,
-    M: Fn(  &[T]) -> Option<Hdf5Lor>      + Send + Sync + 'p,
+    M: Fn(  &[T]) -> Option<ExtendedLor>  + Send + Sync + 'p,
 {
 
     // files
@@ -159,9 +160,10 @@ use clap::Parser;
 use itertools::Itertools;
 use petalo::{
     config::mlem::Bounds,
+    utils::FWHM_PER_SIGMA,
     io::{self,
          hdf5::{
-             Hdf5Lor,
+             ExtendedLor,
              sensors::{QT, SensorMap},
              mc::Vertex
          }
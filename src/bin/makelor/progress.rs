@@ -56,7 +56,7 @@ impl Progress {
         data.n_events_read += groups.len() as u64;
     }
 
-    pub (super) fn lor(&self, _: &Hdf5Lor) {
+    pub (super) fn lor(&self, _: &ExtendedLor) {
         let mut data = self.0.lock().unwrap();
         data.n_lors_made += 1;
     }
@@ -86,4 +86,4 @@ use std::{
 };
 use hdf5::Result;
 use indicatif::{ProgressBar, ProgressStyle};
-use petalo::{utils::group_digits, io::hdf5::Hdf5Lor};
+use petalo::{utils::group_digits, io::hdf5::ExtendedLor};
@@ -1,5 +1,6 @@
 use petalo::{
-    config::mlem::AttenuationCorrection as AC,
+    config::mlem::{AttenuationCorrection as AC, ApplyFilter, Multibed},
+    multibed,
     projectors::{Projector, Siddon},
 };
 // ----------------------------------- CLI -----------------------------------
@@ -26,6 +27,12 @@ pub struct Cli {
     #[clap(short = 'k', long)]
     pub scattergram_threads: Option<usize>,
 
+    /// Extension of the output images, which determines their format: `nii`
+    /// or `nii.gz` (NIfTI-1), `hv` (Interfile), `mhd` or `mha` (MetaImage),
+    /// anything else for raw
+    #[clap(short, long, default_value = "raw")]
+    pub extension: String,
+
 }
 
 // --------------------------------------------------------------------------------
@@ -33,21 +40,23 @@ pub struct Cli {
 use std::error::Error;
 use std::path::PathBuf;
 use std::fs::create_dir_all;
+use std::time::Instant;
 
-use units::{Length, mm_};
+use units::mm_;
 use petalo::{
     FOV,
-    image::Image,
-    io,
-    mlem::Osem,
-    utils::timing::Progress
+    config::mlem::{Output, OutputFormat, Streaming},
+    image::{Image, filter::Filter, resample::{Resampling, covers, same_fov}},
+    io::{self, hdf5::{results::{Record, Results}, stream::LorStream}, raw::{ImageKind, Metadata}},
+    mlem::{Osem, log_likelihood, log_likelihood_chunks},
+    utils::{group_digits, timing::Progress},
 };
 
 
 fn main() -> Result<(), Box<dyn Error>> {
 
     let args = Cli::parse();
-    let config = config::mlem::read_config_file(args.config_file.clone());
+    let mut config = config::mlem::read_config_file(args.config_file.clone());
     unsafe { petalo::mlem::N_MLEM_THREADS = args.mlem_threads; }
 
     // Set up progress reporting and timing
@@ -59,72 +68,278 @@ fn main() -> Result<(), Box<dyn Error>> {
     create_dir_all(&args.output_directory)
         .unwrap_or_else(|_| panic!("Cannot write in output directory `{}`", args.output_directory.display()));
     // Copy config file to output directory, in order to preserve metadata
-    std::fs::copy(
-        &args.config_file,
-        args.output_directory.join("mlem-config.toml")
-    )?;
+    if config.output.format == OutputFormat::Images {
+        std::fs::copy(
+            &args.config_file,
+            args.output_directory.join("mlem-config.toml")
+        )?;
+    }
     // Show configuration being run
     println!("Configuration:\n{config}");
 
     // Define field of view extent and voxelization
-    let fov = FOV::new(config.fov.size, config.fov.nvoxels);
+    let fov = config.fov.fov();
 
-    let scattergram = config.scatter_correction.as_ref().and_then(Into::into);
     progress.done_with_message("Startup");
 
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.mlem_threads).build()?;
+    println!("MLEM: Using up to {} threads.", args.mlem_threads);
+    let parameters = Siddon::new(config.tof).data();
+    // Filter either between iterations (sieve) or only the images written out
+    let (sieve, output_filter) = match config.filter {
+        Some(filter) if filter.apply == ApplyFilter::Iterations => (Some(filter.filter()), None),
+        Some(filter)                                           => (None, Some(filter.filter())),
+        None                                                   => (None, None),
+    };
+    // Record the configuration in each image (or the HDF5 file), for provenance
+    let config_text = std::fs::read_to_string(&args.config_file)?;
+    let mut writer = Writer::new(&args, &config, config_text, output_filter);
+    let n_images = config.iterations.number * config.iterations.subsets;
+    let scattergram_threads = args.scattergram_threads.unwrap_or(args.mlem_threads);
+
+    if let Some(multibed) = config.multibed.clone() {
+        if config.streaming.is_some() { return Err("[streaming] is not available with [multibed]".into()) }
+        let Multibed { mode, beds } = multibed;
+        let bed_fovs = beds.iter().map(|bed| multibed::bed_fov(fov, bed.offset)).collect::<Vec<_>>();
+        let whole_body = multibed::whole_body_fov(&bed_fovs)?;
+        // `[attenuation_correction]` provides defaults for all beds
+        let (default_sensitivity_image, resampling) = match &config.attenuation_correction {
+            Some(AC { sensitivity_image, resampling }) =>
+                ((!sensitivity_image.as_os_str().is_empty()).then_some(sensitivity_image.clone()), *resampling),
+            None => (None, Resampling::default()),
+        };
+        let mut corrections = vec![];
+        let mut lors = vec![];
+        for (bed, &bed_fov) in beds.iter().zip(&bed_fovs) {
+            let correction = match bed.sensitivity_image.as_ref().or(default_sensitivity_image.as_ref()) {
+                Some(path) => {
+                    let mut image = Image::from_file(path)
+                        .map_err(|e| format!("Cannot read sensitivity image {}: {e}", path.display()))?;
+                    image.fov = multibed::bed_fov(image.fov, bed.offset);
+                    Some(sensitivity_image_on_fov(image, bed_fov, resampling)?)
+                },
+                None => None,
+            };
+            corrections.push(correction);
+            progress.startln(&format!("Loading LORs of bed at {:.1?} from {}", bed.offset, bed.file.display()));
+            config.input.file = bed.file.clone();
+            config.input.files.clear();
+            let scattergram = config.scatter_correction.as_ref().and_then(Into::into);
+            let mut bed_lors = io::hdf5::read_lors(&config, scattergram, scattergram_threads)?;
+            multibed::shift_lors(&mut bed_lors, bed.offset);
+            lors.push(bed_lors);
+            progress.done_with_message("Loaded LORs from file");
+        }
+        let sensitivities = bed_fovs.iter().zip(&corrections)
+            .map(|(&fov, correction)| multibed::sensitivity(fov, correction.as_ref()))
+            .collect::<Vec<_>>();
+
+        match mode {
+            multibed::Mode::Joint => {
+                let correction = multibed::joint_correction(&sensitivities, whole_body);
+                let correction = apply_support_image(Some(correction), whole_body, &config)?;
+                let lors = multibed::interleave(lors);
+                writer.start(whole_body, correction.as_ref())?;
+                pool.install(|| -> Result<(), String> {
+                    for (image, osem) in petalo::mlem::mlem::<Siddon>(parameters, whole_body, &lors, correction.clone(), config.iterations.subsets, sieve)
+                        .take(n_images) {
+                            let likelihood = || Ok(log_likelihood::<Siddon>(parameters, &image, &lors, correction.as_ref()));
+                            writer.write(&image, osem, likelihood, &mut progress)?;
+                        }
+                    Ok(())
+                })?;
+            },
+            multibed::Mode::Separate => {
+                let corrections = corrections.into_iter().zip(&bed_fovs)
+                    .map(|(correction, &fov)| apply_support_image(correction, fov, &config))
+                    .collect::<Result<Vec<_>, _>>()?;
+                writer.start(whole_body, None)?;
+                pool.install(|| -> Result<(), String> {
+                    let mut reconstructions = bed_fovs.iter().zip(&lors).zip(corrections.clone())
+                        .map(|((&fov, lors), correction)|
+                             petalo::mlem::mlem::<Siddon>(parameters, fov, lors, correction, config.iterations.subsets, sieve))
+                        .collect::<Vec<_>>();
+                    for _ in 0..n_images {
+                        let (images, osems): (Vec<_>, Vec<_>) = reconstructions.iter_mut()
+                            .map(|r| r.next().unwrap())
+                            .unzip();
+                        // The beds are independent reconstructions: sum their log-likelihoods
+                        let likelihood = || Ok(images.iter().zip(&lors).zip(&corrections)
+                            .map(|((image, lors), correction)| log_likelihood::<Siddon>(parameters, image, lors, correction.as_ref()))
+                            .sum());
+                        let image = multibed::stitch(&images, &sensitivities, whole_body);
+                        writer.write(&image, osems[0], likelihood, &mut progress)?;
+                    }
+                    Ok(())
+                })?;
+            },
+        }
+        return Ok(())
+    }
+
     let sensitivity_image =
-        if let Some(AC { sensitivity_image: path }) = config.attenuation_correction.as_ref() {
-            let image = Image::from_raw_file(path)
+        if let Some(AC { sensitivity_image: path, resampling }) = config.attenuation_correction.as_ref() {
+            let image = Image::from_file(path)
                 .unwrap_or_else(|_| panic!("Cannot read sensitivity image {:?}", path.display()));
-            assert_image_sizes_match(&image, config.fov.nvoxels, config.fov.size);
             progress.done_with_message("Loaded sensitivity image");
-            Some(image)
+            Some(sensitivity_image_on_fov(image, fov, *resampling)?)
         } else { None };
+    let sensitivity_image = apply_support_image(sensitivity_image, fov, &config)?;
+
+    // Re-read the LORs from file in every sub-iteration, rather than holding them in memory
+    if let Some(Streaming { chunk_size, seed }) = config.streaming {
+        let mut stream = LorStream::new(&config, chunk_size, seed)?;
+        if let Some(cut) = stream.pre_smearing_energy_cut() {
+            println!("Applying pre-cut at {cut} keV, final cut at {:?}", config.input.energy);
+        }
+        stream.report_files(|file, n| println!("Reading {:>12} LORs from {}", group_digits(n), file.display()));
+        if let Some(scattergram) = config.scatter_correction.as_ref().and_then(Into::into) {
+            progress.startln("Filling scattergram from LORs streamed from file");
+            stream.fill_scattergram(scattergram, scattergram_threads)?;
+            progress.done_with_message("Filled scattergram");
+        }
+        writer.start(fov, sensitivity_image.as_ref())?;
+        pool.install(|| -> Result<(), String> {
+            // `mlem_streaming` needs the LORs themselves: keep the first error
+            // in reading them, ending the sub-iteration, and stop there
+            let error = std::cell::RefCell::new(None);
+            let read_error = |e: hdf5::Error| format!("Cannot read LORs from file: {e}");
+            let subset_lors = |subset, n_subsets| stream.subset(subset, n_subsets)
+                .map_while(|lors| lors.map_err(|e| { error.borrow_mut().get_or_insert(read_error(e)); }).ok());
+            for (image, osem) in
+                petalo::mlem::mlem_streaming::<Siddon, _, _>(parameters, fov, subset_lors, sensitivity_image.clone(), config.iterations.subsets, sieve)
+                .take(n_images) {
+                    if let Some(e) = error.take() { return Err(e) }
+                    let likelihood = || {
+                        let mut failure = None;
+                        let lors = stream.chunks()
+                            .map_while(|lors| lors.map_err(|e| { failure.get_or_insert(read_error(e)); }).ok());
+                        let likelihood = log_likelihood_chunks::<Siddon, _>(parameters, &image, lors, sensitivity_image.as_ref());
+                        match failure { Some(e) => Err(e), None => Ok(likelihood) }
+                    };
+                    writer.write(&image, osem, likelihood, &mut progress)?;
+                }
+            Ok(())
+        })?;
+        return Ok(())
+    }
 
     progress.startln("Loading LORs from file");
-    let scattergram_threads = args.scattergram_threads.unwrap_or(args.mlem_threads);
+    let scattergram = config.scatter_correction.as_ref().and_then(Into::into);
     let measured_lors = io::hdf5::read_lors(&config, scattergram, scattergram_threads)?;
     progress.done_with_message("Loaded LORs from file");
 
-    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.mlem_threads).build()?;
-    println!("MLEM: Using up to {} threads.", args.mlem_threads);
-    let parameters = Siddon::new(config.tof).data();
-    pool.install(|| {
-        for (image, Osem{iteration, subset, ..}) in
-            (petalo::mlem::mlem::<Siddon>(parameters, fov, &measured_lors, sensitivity_image, config.iterations.subsets))
-            .take(config.iterations.number * config.iterations.subsets) {
-                progress.done_with_message(&format!("Iteration {iteration:2}-{subset:02}"));
-                let path = PathBuf::from(format!("{}{iteration:02}-{subset:02}.raw", args.output_directory.display()));
-                petalo::io::raw::Image3D::from(&image).write_to_file(&path).unwrap();
-                progress.done_with_message("                               Wrote raw bin");
-                // TODO: step_by for print every
+    writer.start(fov, sensitivity_image.as_ref())?;
+    pool.install(|| -> Result<(), String> {
+        for (image, osem) in
+            (petalo::mlem::mlem::<Siddon>(parameters, fov, &measured_lors, sensitivity_image.clone(), config.iterations.subsets, sieve))
+            .take(n_images) {
+                let likelihood = || Ok(log_likelihood::<Siddon>(parameters, &image, &measured_lors, sensitivity_image.as_ref()));
+                writer.write(&image, osem, likelihood, &mut progress)?;
             }
-    });
+        Ok(())
+    })?;
 
     Ok(())
 }
 
+/// Writes the selected images either to individual image files, or all of them
+/// to a single HDF5 file
+struct Writer<'a> {
+    args: &'a Cli,
+    output: Output,
+    config_text: String,
+    filter: Option<Filter>,
+    hdf5: Option<Results>,
+    start: Instant,
+    previous: Instant,
+}
+
+impl<'a> Writer<'a> {
+
+    fn new(args: &'a Cli, config: &config::mlem::Config, config_text: String, filter: Option<Filter>) -> Self {
+        let now = Instant::now();
+        Self { args, output: config.output.clone(), config_text, filter, hdf5: None, start: now, previous: now }
+    }
+
+    /// Call just before starting the reconstruction: starts the clock and, in
+    /// HDF5 mode, creates the output file
+    fn start(&mut self, fov: FOV, sensitivity: Option<&Image>) -> Result<(), Box<dyn Error>> {
+        if self.output.format == OutputFormat::Hdf5 {
+            let path = self.args.output_directory.join("mlem.h5");
+            self.hdf5 = Some(Results::create(&path, fov, &self.config_text, sensitivity)
+                             .map_err(|e| format!("Cannot create {}: {e}", path.display()))?);
+        }
+        self.start = Instant::now();
+        self.previous = self.start;
+        Ok(())
+    }
+
+    fn write(
+        &mut self,
+        image: &Image,
+        osem: Osem,
+        log_likelihood: impl FnOnce() -> Result<f64, String>,
+        progress: &mut Progress,
+    ) -> Result<(), String> {
+        let Osem { iteration, subset, n_subsets } = osem;
+        progress.done_with_message(&format!("Iteration {iteration:2}-{subset:02}"));
+        let duration = self.previous.elapsed().as_secs_f64();
+        let n = (iteration - 1) * n_subsets + subset;
+        if self.output.saves(n) {
+            let filtered;
+            let image = if let Some(filter) = self.filter { filtered = filter.apply(image); &filtered } else { image };
+            if let Some(results) = self.hdf5.as_mut() {
+                let log_likelihood = log_likelihood()?;
+                let elapsed = self.start.elapsed().as_secs_f64();
+                results.write(image, Record { osem, duration, elapsed, log_likelihood }).map_err(|e| e.to_string())?;
+            } else {
+                let path = PathBuf::from(format!("{}{iteration:02}-{subset:02}.{}", self.args.output_directory.display(), self.args.extension));
+                let metadata = Metadata {
+                    kind: ImageKind::Activity,
+                    units: String::new(),
+                    iteration: Some((iteration, subset)),
+                    config: Some(self.config_text.clone()),
+                };
+                image.write_to_file_with(&path, &metadata).map_err(|e| e.to_string())?;
+            }
+            progress.done_with_message("                               Wrote image");
+        }
+        self.previous = Instant::now();
+        Ok(())
+    }
+}
+
+/// Voxels outside the support image get zero sensitivity, and are therefore
+/// never reconstructed
+fn apply_support_image(sensitivity: Option<Image>, fov: FOV, config: &config::mlem::Config) -> Result<Option<Image>, Box<dyn Error>> {
+    let Some(path) = config.fov.support.as_ref().and_then(|s| s.image.as_ref()) else { return Ok(sensitivity) };
+    let mask = Image::from_file(path)
+        .map_err(|e| format!("Cannot read support image {}: {e}", path.display()))?
+        .resample(fov, Resampling::Nearest);
+    let mut sensitivity = sensitivity.unwrap_or_else(|| Image::ones(fov));
+    for (s, &m) in sensitivity.data.iter_mut().zip(&mask.data) {
+        if m <= 0.0 { *s = 0.0 }
+    }
+    Ok(Some(sensitivity))
+}
 
-type FovSize = (Length, Length, Length);
-type NVoxels = (usize , usize , usize );
-
-/// Panic if the image size does not match the specified values
-fn assert_image_sizes_match(image: &Image, nvoxels: NVoxels, fov_size: FovSize) {
-    use float_eq::float_eq;
-    let size = image.fov.half_width;
-    let (idx, idy, idz) = (size[0]*2.0, size[1]*2.0, size[2]*2.0);
-    let [inx, iny, inz] = image.fov.n;
-    let (enx, eny, enz) = nvoxels;
-    let (edx, edy, edz) = fov_size;
-    // Unwrap uom, to make float_eq! work
-    let ids = [mm_(idx), mm_(idy), mm_(idz)];
-    let eds = [mm_(edx), mm_(edy), mm_(edz)];
-    if ! ((enx, eny, enz) == (inx, iny, inz) && float_eq!(eds, ids, ulps_all <= 1)) {
-        // TODO enable use of density images with different
-        // pixelizations as long as they cover the whole FOV.
-        println!("Mismatch sensitivity image and output image size:");
-        println!("Sensitivity image: {:3} x {:3} x {:3} pixels, {:3} x {:3} x {:3} mm", inx,iny,inz, mm_(idx),mm_(idy),mm_(idz));
-        println!("     Output image: {:3} x {:3} x {:3} pixels, {:3} x {:3} x {:3} mm", enx,eny,enz, mm_(edx),mm_(edy),mm_(edz));
-        panic!("For now, the sensitivity image must match the dimensions of the output image exactly.");
+/// Resample the sensitivity image onto the reconstruction FOV, if their
+/// voxelizations differ. Fails if the sensitivity image does not cover the
+/// whole FOV.
+fn sensitivity_image_on_fov(image: Image, fov: FOV, resampling: Resampling) -> Result<Image, String> {
+    if same_fov(&image.fov, &fov) { return Ok(image) }
+    let describe = |fov: &FOV| {
+        let [nx, ny, nz] = fov.n;
+        let d = |i: usize| mm_(fov.half_width[i]) * 2.0;
+        format!("{nx:3} x {ny:3} x {nz:3} voxels, {:3} x {:3} x {:3} mm", d(0), d(1), d(2))
+    };
+    if !covers(&image.fov, &fov) {
+        return Err(format!("Sensitivity image does not cover the whole FOV:\n\
+                            Sensitivity image: {}\n     Output image: {}",
+                           describe(&image.fov), describe(&fov)))
     }
+    println!("Resampling sensitivity image ({}) onto FOV ({}) using {resampling:?}",
+             describe(&image.fov), describe(&fov));
+    Ok(image.resample(fov, resampling))
 }
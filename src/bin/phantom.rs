@@ -0,0 +1,94 @@
+// ----------------------------------- CLI -----------------------------------
+use clap::Parser;
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "phantom", about = "Create activity and density images of analytic phantoms")]
+pub struct Cli {
+
+    /// Image dimensions (e.g. '300 mm  300 mm  180 mm  100 100 60')
+    #[clap(short, long, value_parser = clap::value_parser!(FOV))]
+    pub fov: FOV,
+
+    /// Where to write the activity image
+    #[clap(short, long)]
+    pub activity: Option<PathBuf>,
+
+    /// Where to write the density image (g/cm³)
+    #[clap(short, long)]
+    pub density: Option<PathBuf>,
+
+    /// Number of sampling points per voxel, along each axis
+    #[clap(short, long, default_value = "3")]
+    pub supersample: usize,
+
+    #[clap(subcommand)]
+    pub phantom: PhantomKind,
+}
+
+#[derive(clap::Parser, Debug, Clone)]
+pub enum PhantomKind {
+
+    /// NEMA NU 2 image quality phantom
+    NemaIq,
+
+    /// Deluxe Jaszczak phantom
+    Jaszczak,
+
+    /// Derenzo hot-rod phantom
+    Derenzo,
+
+    /// Phantom described in a TOML file
+    Toml {
+        /// TOML file containing `[[component]]`s
+        file: PathBuf,
+    },
+}
+
+// --------------------------------------------------------------------------------
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Cli { fov, activity, density, supersample, phantom } = Cli::parse();
+    if activity.is_none() && density.is_none() {
+        return Err("Nothing to do: specify at least one of --activity and --density".into());
+    }
+    let mut progress = Progress::new();
+
+    let phantom = match phantom {
+        PhantomKind::NemaIq   => Phantom::nema_iq(),
+        PhantomKind::Jaszczak => Phantom::jaszczak(),
+        PhantomKind::Derenzo  => Phantom::derenzo(),
+        PhantomKind::Toml { file } => Phantom::try_from(&config::phantom::read_config_file(file)?)?,
+    };
+
+    if let Some(path) = activity {
+        let image = phantom.activity_image(fov, supersample);
+        write(&image, &path, ImageKind::Activity)?;
+        progress.done_with_message(&format!("Wrote activity image to {}", path.display()));
+    }
+    if let Some(path) = density {
+        let image = phantom.density_image(fov, supersample);
+        write(&image, &path, ImageKind::Density)?;
+        progress.done_with_message(&format!("Wrote density image to {}", path.display()));
+    }
+    Ok(())
+}
+
+fn write(image: &Image, path: &Path, kind: ImageKind) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir)?; }
+    image.write_to_file_with(path, &Metadata::new(kind))
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use petalo::{
+    FOV,
+    config,
+    image::Image,
+    io::raw::{ImageKind, Metadata},
+    phantom::Phantom,
+    utils::timing::Progress,
+};
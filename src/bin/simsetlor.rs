@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use clap::Parser;
+use itertools::Itertools;
+
+use petalo::{
+    io::{self, hdf5::{ExtendedLor, set_lor_version}},
+    utils::group_digits,
+};
+
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "simsetlor", about = "Convert SimSET standard history files into an HDF5 LOR dataset")]
+pub struct Cli {
+    /// SimSET standard history files
+    pub inputs: Vec<PathBuf>,
+
+    /// HDF5 output file for the LORs
+    #[clap(short, long)]
+    pub out: PathBuf,
+
+    /// Chunk size in output HDF5 file
+    #[clap(short, long, default_value = "1000000")]
+    pub chunk_size: usize,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let Cli { inputs, out, chunk_size } = Cli::parse();
+    let chunk_size = chunk_size.max(1);
+
+    let file = hdf5::File::create(&out)?;
+    let dataset = file
+        .create_group("reco_info")?
+        .new_dataset::<ExtendedLor>()
+        .chunk(chunk_size)
+        .shape(0..)
+        .create("lors")?;
+    set_lor_version(&dataset, 2)?;
+
+    // Events are numbered consecutively across all input files
+    let mut n_events: u32 = 0;
+    let mut written = 0;
+    for input in &inputs {
+        let first = n_events;
+        for chunk in &simset::standard_events(input)?.chunks(chunk_size) {
+            let mut lors = vec![];
+            for event in chunk {
+                let event = event.map_err(|e| format!("{}: {e}", input.display()))?;
+                lors.extend(io::simset::lor(&event, n_events));
+                n_events += 1;
+            }
+            dataset.resize(written + lors.len())?;
+            dataset.write_slice(&lors, written..written + lors.len())?;
+            written += lors.len();
+        }
+        println!("Read {:>12} decays from {}", group_digits(n_events - first), input.display());
+    }
+    println!("Wrote {} LORs to {}", group_digits(written), out.display());
+    Ok(())
+}
@@ -0,0 +1,158 @@
+// ----------------------------------- CLI -----------------------------------
+use clap::Parser;
+
+#[derive(clap::Parser, Debug, Clone)]
+#[clap(name = "simulate", about = "Generate LORs by forward-projecting an activity image")]
+pub struct Cli {
+
+    /// Activity image from which decays are sampled
+    #[clap(short, long)]
+    pub input: PathBuf,
+
+    /// HDF5 output file for the simulated LORs
+    #[clap(short, long)]
+    pub out: PathBuf,
+
+    /// Attenuation (μ) image, in mm⁻¹. No attenuation if not supplied
+    #[clap(short, long)]
+    pub attenuation: Option<PathBuf>,
+
+    /// Number of decays to generate
+    #[clap(short, long)]
+    pub n_decays: usize,
+
+    /// Seed for the random number generator
+    #[clap(short, long, default_value = "0")]
+    pub seed: u64,
+
+    /// Axial length of detector
+    #[clap(long, short = 'l')]
+    pub detector_length: Length,
+
+    /// Inner radius of scintillator
+    #[clap(long, short = 'r')]
+    pub r_min: Length,
+
+    /// Sigma of Gaussian smearing of TOF (eg '200 ps'). No smearing if not supplied
+    #[clap(long)]
+    pub tof: Option<Time>,
+
+    /// FWHM of Gaussian smearing of energies (eg '15 %'). No smearing if not supplied
+    #[clap(long)]
+    pub energy_fwhm: Option<Ratio>,
+
+    /// Maximum number of rayon threads
+    #[clap(short = 'j', long, default_value = "4")]
+    pub threads: usize,
+
+    /// Chunk size in output HDF5 file
+    #[clap(short = 'c', long, default_value = "1000000")]
+    pub chunk_size: usize,
+
+    #[clap(subcommand)]
+    pub detector_type: DetectorType,
+}
+
+#[derive(clap::Parser, Debug, Clone)]
+pub enum DetectorType {
+
+    /// Continuous scintillator
+    Continuous,
+
+    /// Discretized scintillator
+    Discrete {
+
+        /// Radial size of elements = thickness of scintillator
+        #[clap(long)]
+        dr: Length,
+
+        /// Axial width of scintillator elements
+        #[clap(long)]
+        dz: Length,
+
+        /// Azimuthal width of scintillator elements at `r_min + dr/2`?
+        #[clap(long)]
+        da: Length,
+
+        /// How to adjust the ends of the LORs in the element
+        #[clap(long)]
+        adjust: Adjust,
+    },
+}
+
+// --------------------------------------------------------------------------------
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Cli::parse();
+    let mut progress = Progress::new();
+
+    // Make sure that the output can be written before starting the simulation
+    std::fs::create_dir_all(args.out.parent().unwrap_or(Path::new(".")))?;
+
+    let activity = Image::from_file(&args.input)
+        .map_err(|e| format!("Cannot read activity image {}: {e}", args.input.display()))?;
+    let attenuation = args.attenuation.as_ref()
+        .map(|path| Image::from_file(path)
+             .map_err(|e| format!("Cannot read attenuation image {}: {e}", path.display())))
+        .transpose()?;
+    progress.done_with_message("Read images");
+
+    let Cli { detector_length: length, r_min, .. } = args;
+    let detector = match args.detector_type {
+        DetectorType::Continuous => Detector::Cylinder { length, r_min },
+        DetectorType::Discrete { dr, dz, da, adjust } =>
+            Detector::Discrete { length, discretize: Discretize { r_min, dr, dz, da, adjust } },
+    };
+    let simulation = Simulation {
+        activity: &activity,
+        attenuation: attenuation.as_ref(),
+        detector,
+        smearing: Smearing { tof: args.tof, energy: args.energy_fwhm },
+    };
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    progress.start(&format!("Simulating {} decays", group_digits(args.n_decays)));
+    let lors = pool.install(|| simulation.run(args.n_decays, args.seed));
+    progress.done_with_message(&format!("{} LORs detected", group_digits(lors.len())));
+
+    // --- write lors to hdf5 in chunks ----------------------------------------------
+    let file = hdf5::File::create(&args.out)?;
+    let dataset = file
+        .create_group("reco_info")?
+        .new_dataset::<Hdf5Lor>()
+        .chunk(args.chunk_size)
+        .shape(0..)
+        .create("lors")?;
+    for chunk in lors.chunks(args.chunk_size) {
+        let old_size = dataset.shape()[0];
+        dataset.resize(old_size + chunk.len())?;
+        dataset.write_slice(chunk, old_size..)?;
+    }
+
+    // --- Store discretization parameters in HDF5 -----------------------------------
+    if let Detector::Discrete { discretize: Discretize { r_min, dr, dz, da, .. }, .. } = detector {
+        dataset.new_attr_builder().with_data(&[mm_(r_min)]).create("r_min")?;
+        dataset.new_attr_builder().with_data(&[mm_(dr   )]).create("dr")?;
+        dataset.new_attr_builder().with_data(&[mm_(da   )]).create("da")?;
+        dataset.new_attr_builder().with_data(&[mm_(dz   )]).create("dz")?;
+    }
+    dataset.new_attr_builder().with_data(&[args.seed]).create("seed")?;
+    progress.done_with_message(&format!("Wrote LORs to {}", args.out.display()));
+    Ok(())
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use units::{Length, Ratio, Time, mm_};
+
+use petalo::{
+    discrete::{Adjust, Discretize},
+    image::Image,
+    io::hdf5::Hdf5Lor,
+    simulate::{Detector, Simulation, Smearing},
+    utils::{group_digits, timing::Progress},
+};
@@ -1 +1,84 @@
+//! Parsers of TOML configuration files, and helpers for reading `uom`
+//! quantities from them
+
 pub mod mlem;
+pub mod phantom;
+pub mod fom;
+pub mod roi;
+
+pub (crate) fn deserialize_uom<'d, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Option::<&str>::deserialize(deserializer)?
+        .map(str::parse::<T>)
+        .unwrap()
+        .map_err(de::Error::custom)
+}
+
+fn _deserialize_uom_3d_opt<'d, D, T>(deserializer: D) -> Result<Option<(T, T, T)>, D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Option::<(&str, &str, &str)>::deserialize(deserializer)?
+        .map(|(x,y,z)| tr_tup_res((x.parse(), y.parse(), z.parse())))
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+pub (crate) fn deserialize_uom_3d<'d, D, T>(deserializer: D) -> Result<(T, T, T), D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Option::<(&str, &str, &str)>::deserialize(deserializer)?
+        .map(|(x,y,z)| tr_tup_res((x.parse(), y.parse(), z.parse())))
+        .unwrap()
+        .map_err(de::Error::custom)
+}
+
+
+/// Transpose 3-tuple of `Result`
+///
+/// `Ok` if all elements `Ok`; if any element is an `Err` return the first one.
+///
+/// # Examples
+/// `(Ok(a),  Ok(b),  Ok(c)) -> Ok((a, b, c))`
+/// `(Ok(a), Err(b),  Ok(c)) -> Err(b)`
+/// `(Ok(a), Err(b), Err(c)) -> Err(b)`
+fn tr_tup_res<O, E>((x,y,z): (Result<O, E>, Result<O, E>, Result<O, E>)) -> Result<(O, O, O), E> {
+    Ok((x?, y?, z?))
+}
+
+pub (crate) fn deserialize_uom_2d<'d, D, T>(deserializer: D) -> Result<(T, T), D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    let (x, y) = <(&str, &str)>::deserialize(deserializer)?;
+    Ok((x.parse().map_err(de::Error::custom)?,
+        y.parse().map_err(de::Error::custom)?))
+}
+
+pub (crate) fn deserialize_uom_2d_opt<'d, D, T>(deserializer: D) -> Result<Option<(T, T)>, D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Option::<(&str, &str)>::deserialize(deserializer)?
+        .map(|(x, y)| Ok::<_, T::Err>((x.parse()?, y.parse()?)))
+        .transpose()
+        .map_err(de::Error::custom)
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, de};
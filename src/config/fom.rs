@@ -0,0 +1,288 @@
+//! Configuration file parser for phantom descriptions used by `foms`
+//!
+//! Describes where `foms` should look for hot and cold spheres and background
+//! ROIs, and the true activities which they contain:
+//!
+//! ```toml
+//! [spheres]
+//! ring_r = "57.2 mm"   # distance of sphere centres from the axis
+//! z      = "0 mm"      # axial position of sphere centres
+//! activity = 4.0       # default activity of spheres
+//!
+//! [[spheres.sphere]]
+//! position = 0         # angular position, in units of a turn / spheres.n_positions
+//! diameter = "37 mm"
+//! activity = 0.0       # optional: overrides spheres.activity (cold sphere)
+//!
+//! [background]
+//! activity = 1.0
+//! xy = [["0 mm", "-86 mm"], ["60 mm", "-82 mm"]]  # centres of background ROIs ...
+//! z  = ["-10 mm", "0 mm", "10 mm"]                # ... placed in each of these slices
+//! # Optional: background ROIs of any kind (see `config::roi`), for the regions
+//! roi = [ { cylinder_z = { centre = ["0 mm", "-86 mm"], r = "15 mm" } } ]
+//!
+//! # Optional: NEMA NU 2 7.4.2 accuracy of corrections in a cold insert
+//! [aoc]
+//! centre = ["0 mm", "0 mm"]
+//! r      = "15 mm"
+//! z      = ["-80 mm", "40 mm"]  # slices whose centres lie in this range
+//!
+//! # Optional: any number of ROIs of any kind (see `config::roi`), with their
+//! # true activities
+//! [[region]]
+//! name     = "lesion"
+//! activity = 8.0
+//! roi      = { ellipsoid = { centre = ["30 mm", "0 mm", "10 mm"], semi_axes = ["5 mm", "5 mm", "8 mm"] } }
+//! ```
+//!
+//! The sphere, AOC and `xy`/`z` background ROIs are discs of voxels in a single
+//! z-slice. The background ROIs associated with each sphere have the same
+//! radius as the sphere. The FOMs of the regions are relative to the
+//! background `roi`s, with voxels weighted by the fraction of their volume
+//! lying inside each ROI. Phantoms may consist of only spheres or only
+//! regions.
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub spheres: Option<Spheres>,
+    pub background: Background,
+    pub aoc: Option<Aoc>,
+    #[serde(default)]
+    pub region: Vec<Region>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Spheres {
+
+    /// Distance of sphere centres from the axis
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub ring_r: Length,
+
+    /// Axial position of sphere centres
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub z: Length,
+
+    /// Number of equally-spaced angular positions around the ring
+    #[serde(default = "six")]
+    pub n_positions: u16,
+
+    /// Activity of spheres which do not specify their own
+    pub activity: Intensityf32,
+
+    pub sphere: Vec<Sphere>,
+}
+
+fn six() -> u16 { 6 }
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Sphere {
+    pub position: u16,
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub diameter: Length,
+    pub activity: Option<Intensityf32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Background {
+    pub activity: Intensityf32,
+    #[serde(default, deserialize_with = "deserialize_uom_2d_vec")]
+    pub xy: Vec<(Length, Length)>,
+    #[serde(default, deserialize_with = "deserialize_uom_vec")]
+    pub z: Vec<Length>,
+    /// Background ROIs for the regions
+    #[serde(default)]
+    pub roi: Vec<Roi>,
+}
+
+/// A ROI of any kind, with its true activity
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    pub activity: Intensityf32,
+    pub roi: Roi,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Aoc {
+    #[serde(deserialize_with = "deserialize_uom_2d")]
+    pub centre: (Length, Length),
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub r: Length,
+    #[serde(deserialize_with = "deserialize_uom_2d")]
+    pub z: (Length, Length),
+}
+
+impl Config {
+
+    /// The FOM spheres described by this configuration
+    pub fn spheres(&self) -> Vec<fom::Sphere> {
+        let Some(Spheres { ring_r, n_positions, activity, ref sphere, .. }) = self.spheres else { return vec![] };
+        sphere.iter()
+            .map(|s| {
+                let angle = std::f32::consts::TAU * s.position as f32 / n_positions as f32;
+                fom::Sphere {
+                    x: ring_r * angle.cos(),
+                    y: ring_r * angle.sin(),
+                    r: s.diameter / 2.0,
+                    a: s.activity.unwrap_or(activity),
+                }
+            })
+            .collect()
+    }
+
+    /// The regions' names, ROIs and activities, and the background ROIs
+    /// against which they are measured. Fails if any ROI is invalid or its
+    /// mask cannot be read, or if there are regions but no background ROIs.
+    pub fn regions(&self) -> Result<(Vec<(String, ROI, Intensityf32)>, Vec<ROI>), Box<dyn Error>> {
+        if self.region.is_empty() { return Ok((vec![], vec![])) }
+        if self.background.roi.is_empty() { return Err("[[region]]s require background ROIs (`background.roi`)".into()) }
+        let regions = self.region.iter()
+            .map(|Region { name, activity, roi }| {
+                Ok((name.clone(), roi.roi().map_err(|e| format!("Region `{name}`: {e}"))?, *activity))
+            })
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        let background = self.background.roi.iter()
+            .enumerate()
+            .map(|(n, roi)| roi.roi().map_err(|e| format!("Background ROI {n}: {e}").into()))
+            .collect::<Result<_, Box<dyn Error>>>()?;
+        Ok((regions, background))
+    }
+
+}
+
+/// Description of the NEMA NU 2 image quality phantom, bundled with `foms`
+pub const NEMA7: &str = include_str!("../../phantoms/nema7.toml");
+
+/// Description of the deluxe Jaszczak phantom, bundled with `foms`
+pub const JASZCZAK: &str = include_str!("../../phantoms/jaszczak.toml");
+
+/// Parse one of the bundled phantom descriptions by name, or read the
+/// description from the file with the given path
+pub fn read_config(name_or_path: &str) -> Result<Config, Box<dyn Error>> {
+    let text = match name_or_path {
+        "nema7"    => NEMA7.to_string(),
+        "jaszczak" => JASZCZAK.to_string(),
+        path => fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read phantom description `{path}`: {e}"))?,
+    };
+    Ok(toml::from_str(&text)?)
+}
+
+fn deserialize_uom_vec<'d, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Vec::<&str>::deserialize(deserializer)?
+        .into_iter()
+        .map(str::parse::<T>)
+        .collect::<Result<_,_>>()
+        .map_err(de::Error::custom)
+}
+
+fn deserialize_uom_2d_vec<'d, D, T>(deserializer: D) -> Result<Vec<(T, T)>, D::Error>
+where
+    D: Deserializer<'d>,
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    Vec::<(&str, &str)>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(x, y)| Ok((x.parse()?, y.parse()?)))
+        .collect::<Result<_, T::Err>>()
+        .map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use units::{mm, mm_};
+
+    #[test]
+    fn bundled_nema7() {
+        let config = read_config("nema7").unwrap();
+        let spheres = config.spheres.unwrap();
+        assert_eq!(spheres.ring_r, mm(57.2));
+        assert_eq!(spheres.sphere.len(), 6);
+        assert_eq!(config.background.xy.len(), 13);
+        assert_eq!(config.background.z.len(), 5);
+        let aoc = config.aoc.unwrap();
+        assert_eq!(aoc.z, (mm(-80.0), mm(40.0)));
+    }
+
+    #[test]
+    fn bundled_jaszczak() {
+        let config = read_config("jaszczak").unwrap();
+        assert_eq!(config.spheres.unwrap().z, mm(34.0));
+        assert_eq!(config.background.xy.len(), 7);
+        assert_eq!(config.background.z.len(), 8);
+        assert!(config.aoc.is_none());
+    }
+
+    #[test]
+    fn sphere_positions_and_activities() {
+        let config: Config = toml::from_str(r#"
+            [spheres]
+            ring_r = "100 mm"
+            z = "0 mm"
+            n_positions = 4
+            activity = 4.0
+            sphere = [
+              { position = 0, diameter = "10 mm" },
+              { position = 1, diameter = "20 mm", activity = 0.0 },
+            ]
+
+            [background]
+            activity = 1.0
+            xy = [["0 mm", "0 mm"]]
+            z = ["0 mm"]
+        "#).unwrap();
+        let spheres = config.spheres();
+        let [hot, cold] = spheres.as_slice() else { panic!("Expected 2 spheres") };
+        assert_eq!((hot.x, hot.r, hot.a), (mm(100.0), mm(5.0), 4.0));
+        assert!(mm_(cold.x).abs() < 1e-4);
+        assert_eq!((cold.y, cold.r, cold.a), (mm(100.0), mm(10.0), 0.0));
+    }
+
+    #[test]
+    fn regions_of_any_kind() {
+        let config: Config = toml::from_str(r#"
+            [background]
+            activity = 1.0
+            roi = [ { cylinder_z = { centre = ["0 mm", "50 mm"], r = "10 mm" } } ]
+
+            [[region]]
+            name = "box"
+            activity = 3.0
+            roi = { cuboid = { centre = ["0 mm", "0 mm", "0 mm"], size = ["10 mm", "10 mm", "10 mm"] } }
+        "#).unwrap();
+        assert!(config.spheres().is_empty());
+        let (regions, background) = config.regions().unwrap();
+        assert_eq!(background.len(), 1);
+        let [(name, fom::ROI::Cuboid(..), activity)] = regions.as_slice() else { panic!("Expected one cuboid") };
+        assert_eq!((name.as_str(), *activity), ("box", 3.0));
+
+        let no_background: Config = toml::from_str(r#"
+            background = { activity = 1.0 }
+            region = [ { name = "a", activity = 2.0, roi = { sphere = { centre = ["0 mm", "0 mm", "0 mm"], r = "5 mm" } } } ]
+        "#).unwrap();
+        assert!(no_background.regions().is_err());
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{error::Error, fs, str::FromStr};
+
+use serde::{Deserialize, Deserializer, de};
+
+use units::{Length, todo::Intensityf32};
+
+use super::{deserialize_uom, deserialize_uom_2d, roi::Roi};
+use crate::fom::{self, ROI};
@@ -4,7 +4,12 @@ use std::{fs, fmt::{Display, Debug}};
 use std::str::FromStr;
 use std::path::PathBuf;
 
-use serde::{Deserialize, Deserializer, de};
+use serde::Deserialize;
+#[cfg(test)]
+use serde::{Deserializer, de};
+
+use super::{deserialize_uom, deserialize_uom_3d};
+use crate::{FOV, Point, image::resample::Resampling, multibed};
 
 use units::{Length, Ratio, Time, pcnt_};
 
@@ -21,55 +26,6 @@ where
         .map_err(de::Error::custom)
 }
 
-fn deserialize_uom<'d, D, T>(deserializer: D) -> Result<T, D::Error>
-where
-    D: Deserializer<'d>,
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
-{
-    Option::<&str>::deserialize(deserializer)?
-        .map(str::parse::<T>)
-        .unwrap()
-        .map_err(de::Error::custom)
-}
-
-fn _deserialize_uom_3d_opt<'d, D, T>(deserializer: D) -> Result<Option<(T, T, T)>, D::Error>
-where
-    D: Deserializer<'d>,
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
-{
-    Option::<(&str, &str, &str)>::deserialize(deserializer)?
-        .map(|(x,y,z)| tr_tup_res((x.parse(), y.parse(), z.parse())))
-        .transpose()
-        .map_err(de::Error::custom)
-}
-
-fn deserialize_uom_3d<'d, D, T>(deserializer: D) -> Result<(T, T, T), D::Error>
-where
-    D: Deserializer<'d>,
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
-{
-    Option::<(&str, &str, &str)>::deserialize(deserializer)?
-        .map(|(x,y,z)| tr_tup_res((x.parse(), y.parse(), z.parse())))
-        .unwrap()
-        .map_err(de::Error::custom)
-}
-
-
-/// Transpose 3-tuple of `Result`
-///
-/// `Ok` if all elements `Ok`; if any element is an `Err` return the first one.
-///
-/// # Examples
-/// `(Ok(a),  Ok(b),  Ok(c)) -> Ok((a, b, c))`
-/// `(Ok(a), Err(b),  Ok(c)) -> Err(b)`
-/// `(Ok(a), Err(b), Err(c)) -> Err(b)`
-fn tr_tup_res<O, E>((x,y,z): (Result<O, E>, Result<O, E>, Result<O, E>)) -> Result<(O, O, O), E> {
-    Ok((x?, y?, z?))
-}
-
 #[derive(Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
@@ -99,6 +55,105 @@ pub struct Config {
     pub detector_full_axial_length: Option<DetectorLength>,
 
     pub scatter_correction: Option<Scatter>,
+
+    /// Filter to apply to images, between iterations or only on output
+    pub filter: Option<Filter>,
+
+    /// Acquisition split into several bed positions
+    pub multibed: Option<Multibed>,
+
+    /// Format of the output, and which (sub-)iterations to save
+    #[serde(default)]
+    pub output: Output,
+
+    /// Re-read the LORs from file in every sub-iteration, one chunk at a time,
+    /// rather than holding them all in memory
+    pub streaming: Option<Streaming>,
+}
+
+/// LORs are selected and smeared afresh every time they are read, with random
+/// numbers seeded by `seed` and the event's position in the input dataset, so
+/// every reading gives the same LORs.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Streaming {
+
+    /// Number of events read from file, and processed in parallel, at a time
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+
+    #[serde(default)]
+    pub seed: u64,
+
+}
+
+fn default_chunk_size() -> usize { 1_000_000 }
+
+/// Images are numbered consecutively from 1, counting OSEM sub-iterations.
+/// If neither `save_every` nor `save` is given, all images are saved.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+
+    #[serde(default)]
+    pub format: OutputFormat,
+
+    /// Save every `save_every`th image
+    pub save_every: Option<usize>,
+
+    /// Numbers of images to save, in addition to those selected by `save_every`
+    #[serde(default)]
+    pub save: Vec<usize>,
+
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// One image file per saved (sub-)iteration
+    #[default]
+    Images,
+    /// A single HDF5 file with all saved images, timings, log-likelihoods, the
+    /// sensitivity image and the configuration
+    Hdf5,
+}
+
+impl Output {
+    /// Should the `n`th image be saved?
+    pub fn saves(&self, n: usize) -> bool {
+        if self.save_every.is_none() && self.save.is_empty() { return true }
+        self.save_every.is_some_and(|every| every > 0 && n % every == 0) || self.save.contains(&n)
+    }
+}
+
+/// Each bed is read from its own file, with the dataset and cuts of `[input]`,
+/// and shifted axially by its offset
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Multibed {
+
+    #[serde(default)]
+    pub mode: multibed::Mode,
+
+    pub beds: Vec<Bed>,
+
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Bed {
+
+    /// HDF5 file containing the LORs of this bed position
+    pub file: PathBuf,
+
+    /// Axial position of the scanner centre relative to the patient
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub offset: Length,
+
+    /// Sensitivity image of this bed, in scanner coordinates [default: that of
+    /// `[attenuation_correction]`, if any]
+    pub sensitivity_image: Option<PathBuf>,
+
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -106,9 +161,14 @@ pub struct Config {
 pub struct Input {
 
     /// HDF5 file containing reconstructed LORs from which to reconstruct image
-    #[serde(default = "mandatory")]
+    #[serde(default)]
     pub file: PathBuf,
 
+    /// Alternatively to `file`: several files, or glob patterns matching them,
+    /// whose datasets are read one after another, as a single dataset
+    #[serde(default)]
+    pub files: Vec<String>,
+
     /// The dataset location inside the input file
     #[serde(default = "mandatory")]
     pub dataset: String,
@@ -133,6 +193,31 @@ pub struct Bounds<T> {
 
 }
 
+impl Input {
+    /// The input files, in the order in which they are to be read: `file`, or
+    /// all the files given or matched by the patterns in `files`. Matches of
+    /// each pattern are sorted by name.
+    pub fn files(&self) -> Result<Vec<PathBuf>, String> {
+        match (self.file.as_os_str().is_empty(), self.files.is_empty()) {
+            (true , true ) => return Err("[input] needs `file` or `files`".into()),
+            (false, false) => return Err("[input] accepts `file` or `files`, not both".into()),
+            (false, true ) => return Ok(vec![self.file.clone()]),
+            (true , false) => {},
+        }
+        let mut files = vec![];
+        for pattern in &self.files {
+            let mut matches = glob::glob(pattern)
+                .map_err(|e| format!("Bad input file pattern `{pattern}`: {e}"))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            if matches.is_empty() { return Err(format!("No input files match `{pattern}`")) }
+            matches.sort();
+            files.extend(matches);
+        }
+        Ok(files)
+    }
+}
+
 impl<T> Bounds<T> {
     pub fn new(min: Option<T>, max: Option<T>) -> Self { Self { min, max } }
     pub fn none() -> Self { Self::new(None, None) }
@@ -170,6 +255,50 @@ pub struct Fov {
     #[serde(deserialize_with = "deserialize_uom_3d")]
     pub size: (Length, Length, Length),
 
+    /// Position of the centre of the FOV
+    #[serde(default = "origin")]
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub centre: (Length, Length, Length),
+
+    /// Only reconstruct voxels inside this region
+    pub support: Option<Support>,
+
+}
+
+fn origin() -> (Length, Length, Length) { let zero = units::mm(0.0); (zero, zero, zero) }
+
+impl Fov {
+    /// The FOV described by this configuration. An image support mask, if any,
+    /// must be applied separately.
+    pub fn fov(&self) -> FOV {
+        let (x, y, z) = self.centre;
+        let fov = FOV::new(self.size, self.nvoxels).with_centre(Point::new(x, y, z));
+        match self.support.as_ref().and_then(|s| s.cylinder) {
+            Some(SupportCylinder { r }) => fov.with_support(r),
+            None                        => fov,
+        }
+    }
+}
+
+/// Voxels outside the support are not reconstructed. If both are given, only
+/// voxels inside both the cylinder and the image mask are reconstructed
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Support {
+
+    /// Cylinder around the central z-axis of the FOV
+    pub cylinder: Option<SupportCylinder>,
+
+    /// Image of any voxelization: voxels with positive values are inside
+    pub image: Option<PathBuf>,
+
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct SupportCylinder {
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub r: Length,
 }
 
 #[derive(Deserialize, Debug, Clone, Copy, Default)]
@@ -194,6 +323,11 @@ pub struct AttenuationCorrection {
     #[serde(default)]
     pub sensitivity_image: PathBuf,
 
+    /// How to resample the sensitivity image, if its voxelization differs from
+    /// that of the reconstruction FOV
+    #[serde(default)]
+    pub resampling: Resampling,
+
 }
 
 #[derive(Deserialize, Debug)]
@@ -246,6 +380,81 @@ pub struct DetectorLength {
     pub dz: Length,
 }
 
+/// The `[filter]` section, which must specify exactly one kind of filter
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    filter: crate::image::filter::Filter,
+    pub apply: ApplyFilter,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct RawFilter {
+
+    gaussian: Option<GaussianFilter>,
+
+    median: Option<MedianFilter>,
+
+    bilateral: Option<BilateralFilter>,
+
+    /// Whether to filter the image between iterations or only the images
+    /// which are written out
+    #[serde(default)]
+    apply: ApplyFilter,
+
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyFilter {
+    /// Filter only the images written to disk: iterations proceed unfiltered
+    #[default]
+    Output,
+    /// Feed the filtered image into the next iteration (sieve)
+    Iterations,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct GaussianFilter {
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub fwhm: Length,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct MedianFilter {
+    pub radius: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct BilateralFilter {
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub fwhm: Length,
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub range: Ratio,
+}
+
+impl Filter {
+    /// The image filter described by this section
+    pub fn filter(&self) -> crate::image::filter::Filter { self.filter }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let RawFilter { gaussian, median, bilateral, apply } = RawFilter::deserialize(deserializer)?;
+        use crate::image::filter::Filter as F;
+        let filter = match (gaussian, median, bilateral) {
+            (Some(GaussianFilter { fwhm }), None, None) => F::Gaussian { fwhm },
+            (None, Some(MedianFilter { radius }), None) => F::Median { radius },
+            (None, None, Some(BilateralFilter { fwhm, range })) => F::Bilateral { fwhm, range },
+            _ => return Err(serde::de::Error::custom("[filter] must specify exactly one of `gaussian`, `median` or `bilateral`")),
+        };
+        Ok(Self { filter, apply })
+    }
+}
+
 pub fn read_config_file(path: PathBuf) -> Config {
     let config: String = fs::read_to_string(&path)
         .unwrap_or_else(|_| panic!("Couldn't read config file `{:?}`", path));
@@ -266,7 +475,7 @@ fn mandatory<T: Default>() -> T { T::default() }
 mod tests {
     use super::*;
 
-    use units::{cm, mm, ps, ratio};
+    use units::{cm, mm, mm_, pcnt, ps, ratio};
 
     // ----- Test an example on-disk config file -----------------------------------------
     #[test]
@@ -355,6 +564,31 @@ mod tests {
         assert_eq!(input.events.max, None);
     }
 
+    #[test]
+    fn config_input_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        for name in ["b-1.h5", "a-2.h5", "a-1.h5", "other.txt"] {
+            std::fs::File::create(dir.path().join(name))?;
+        }
+        let input = parse::<Config>(&format!(r#"
+            [input]
+            files   = ["{0}/b-1.h5", "{0}/a-*.h5"]
+            dataset = "some/dataset"
+        "#, dir.path().display())).input;
+        let names = input.files()?.iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["b-1.h5", "a-1.h5", "a-2.h5"]);
+
+        let input = parse::<Config>(&format!(r#"
+            [input]
+            files = ["{}/nothing-*.h5"]
+        "#, dir.path().display())).input;
+        assert!(input.files().is_err());
+        assert!(parse::<Config>("[input]").input.files().is_err());
+        Ok(())
+    }
+
     #[test]
     fn config_input_file_energy_cut() {
         let input = parse::<Config>(r#"
@@ -426,8 +660,75 @@ mod tests {
                "#).fov;
         assert_eq!(fov.nvoxels, (    10   ,    20    ,    30   ));
         assert_eq!(fov.size   , (mm(123.0), mm(456.0), cm(78.0)));
+        assert_eq!(fov.centre , (mm(  0.0), mm(  0.0), mm( 0.0)));
+        assert!(fov.support.is_none());
+    }
 
+    #[test]
+    fn config_fov_centre_and_support() {
+        let fov = parse::<Config>(r#"
+                     [fov]
+                     nvoxels = [  10    ,   20    ,  30    ]
+                     size    = ["123 mm", "456 mm", "78 cm"]
+                     centre  = [  "1 mm",   "2 mm",  "3 cm"]
+                     support = { cylinder = { r = "100 mm" }, image = "mask.raw" }
+               "#).fov;
+        assert_eq!(fov.centre, (mm(1.0), mm(2.0), cm(3.0)));
+        let support = fov.support.as_ref().unwrap();
+        assert_eq!(support.image, Some(PathBuf::from("mask.raw")));
+        let fov = fov.fov();
+        assert_eq!(fov.support, Some(mm(100.0)));
+        float_eq::assert_float_eq!(mm_(fov.centre.z), 30.0, ulps <= 4);
+    }
+    // ----- Test multi-bed parameters ---------------------------------------------------
+    #[test]
+    fn config_multibed() {
+        let multibed = parse::<Config>(r#"
+                     [multibed]
+                     mode = "joint"
+                     beds = [ { file = "bed0.h5", offset = "-20 cm" },
+                              { file = "bed1.h5", offset =   "0 cm", sensitivity_image = "sens1.raw" } ]
+               "#).multibed.unwrap();
+        assert_eq!(multibed.mode, multibed::Mode::Joint);
+        assert_eq!(multibed.beds.len(), 2);
+        assert_eq!(multibed.beds[0].offset, cm(-20.0));
+        assert_eq!(multibed.beds[1].sensitivity_image, Some(PathBuf::from("sens1.raw")));
+        assert!(parse::<Config>("").multibed.is_none());
     }
+
+    // ----- Test output parameters ------------------------------------------------------
+    #[test]
+    fn config_output() {
+        let output = parse::<Config>(r#"
+                     [output]
+                     format = "hdf5"
+                     save_every = 5
+                     save = [1, 2]
+               "#).output;
+        assert_eq!(output.format, OutputFormat::Hdf5);
+        let saved = (1..=12).filter(|&n| output.saves(n)).collect::<Vec<_>>();
+        assert_eq!(saved, vec![1, 2, 5, 10]);
+        let default = parse::<Config>("").output;
+        assert_eq!(default.format, OutputFormat::Images);
+        assert!((1..=12).all(|n| default.saves(n)));
+    }
+
+    // ----- Test streaming parameters ---------------------------------------------------
+    #[test]
+    fn config_streaming() {
+        let streaming = parse::<Config>(r#"
+                     [streaming]
+                     chunk_size = 5000
+                     seed = 42
+               "#).streaming.unwrap();
+        assert_eq!(streaming.chunk_size, 5000);
+        assert_eq!(streaming.seed, 42);
+        let streaming = parse::<Config>("[streaming]").streaming.unwrap();
+        assert_eq!(streaming.chunk_size, 1_000_000);
+        assert_eq!(streaming.seed, 0);
+        assert!(parse::<Config>("").streaming.is_none());
+    }
+
     // ----- Test TOF parameters ---------------------------------------------------------
     #[test]
     fn config_tof() {
@@ -463,6 +764,17 @@ mod tests {
                       sensitivity_image = "some/sensitivity_image.raw"
                "#).attenuation_correction.unwrap();
         assert_eq!(corr.sensitivity_image, PathBuf::from_str("some/sensitivity_image.raw").unwrap());
+        assert_eq!(corr.resampling, Resampling::Trilinear);
+    }
+
+    #[test]
+    fn config_attenuation_correction_resampling() {
+        let corr = parse::<Config>(r#"
+                      [attenuation_correction]
+                      sensitivity_image = "some/sensitivity_image.raw"
+                      resampling = "overlap"
+               "#).attenuation_correction.unwrap();
+        assert_eq!(corr.resampling, Resampling::Overlap);
     }
 
     #[test]
@@ -508,6 +820,46 @@ mod tests {
         assert_eq!(  z.bins  ,    97   );
         assert_eq!(  z.length, cm(38.0));
     }
+    // ----- Test filter parameters ----------------------------------------------------
+    #[test]
+    fn config_filter() {
+        use crate::image::filter::Filter as F;
+        let filter = parse::<Config>(r#"
+                 [filter]
+                 gaussian.fwhm = "3 mm"
+              "#).filter.unwrap();
+        assert_eq!(filter.apply, ApplyFilter::Output);
+        let F::Gaussian { fwhm } = filter.filter() else { panic!("Expected Gaussian filter") };
+        assert_eq!(fwhm, mm(3.0));
+
+        let filter = parse::<Config>(r#"
+                 [filter]
+                 median.radius = 2
+                 apply = "iterations"
+              "#).filter.unwrap();
+        assert_eq!(filter.apply, ApplyFilter::Iterations);
+        let F::Median { radius } = filter.filter() else { panic!("Expected median filter") };
+        assert_eq!(radius, 2);
+
+        let filter = parse::<Config>(r#"
+                 [filter]
+                 bilateral = { fwhm = "4 mm", range = "10 %" }
+              "#).filter.unwrap();
+        let F::Bilateral { fwhm, range } = filter.filter() else { panic!("Expected bilateral filter") };
+        assert_eq!(fwhm, mm(4.0));
+        assert_eq!(range, pcnt(10.0));
+    }
+
+    #[test]
+    fn config_filter_ambiguous() {
+        let ambiguous = toml::from_str::<Config>(r#"
+                 [filter]
+                 gaussian.fwhm = "3 mm"
+                 median.radius = 1
+              "#);
+        assert!(ambiguous.is_err());
+        assert!(toml::from_str::<Config>("[filter]\napply = \"iterations\"\n").is_err());
+    }
     // -----------------------------------------------------------------------------------
     // The tests that follow should be read in order: they tell the story of why
     // and how we need to jump through a number of hoops in order to parse uom
@@ -646,8 +998,9 @@ impl Display for Config {
         }
 
         f.write_str("\n\n[attenuation_correction]\n")?;
-        if let Some(AttenuationCorrection { sensitivity_image }) = &self.attenuation_correction {
-            f.write_fmt(format_args!("{}" , sensitivity_image.display()))?;
+        if let Some(AttenuationCorrection { sensitivity_image, resampling }) = &self.attenuation_correction {
+            f.write_fmt(format_args!("{}\n" , sensitivity_image.display()))?;
+            f.write_fmt(format_args!("resampling = {resampling:?}"))?;
         } else {
             f.write_str("OFF")?;
         }
@@ -673,6 +1026,31 @@ impl Display for Config {
             f.write_str("OFF")?;
         }
 
+        f.write_str("\n\n[filter]\n")?;
+        if let Some(filter) = &self.filter {
+            f.write_fmt(format_args!("{:?} applied to {:?}", filter.filter(), filter.apply))?;
+        } else {
+            f.write_str("OFF")?;
+        }
+
+        let Output { format, save_every, save } = &self.output;
+        f.write_fmt(format_args!("\n\n[output]\nformat = {format:?}"))?;
+        if let Some(every) = save_every { f.write_fmt(format_args!("\nsave_every = {every}"))? }
+        if !save.is_empty()             { f.write_fmt(format_args!("\nsave = {save:?}"))? }
+
+        if let Some(Streaming { chunk_size, seed }) = &self.streaming {
+            use crate::utils::group_digits as g;
+            f.write_fmt(format_args!("\n\n[streaming]\nchunk_size = {}\nseed = {seed}", g(chunk_size)))?;
+        }
+
+        if let Some(Multibed { mode, beds }) = &self.multibed {
+            f.write_fmt(format_args!("\n\n[multibed]\nmode = {mode:?}"))?;
+            for Bed { file, offset, sensitivity_image } in beds {
+                f.write_fmt(format_args!("\nbed {:>9.1?}: {}", offset, file.display()))?;
+                if let Some(path) = sensitivity_image { f.write_fmt(format_args!(", sensitivity {}", path.display()))? }
+            }
+        }
+
         f.write_str("\n")
 
     }
@@ -680,7 +1058,8 @@ impl Display for Config {
 
 impl Display for Input {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("file    = {}\n", self.file.display()))?;
+        if self.files.is_empty() { f.write_fmt(format_args!("file    = {}\n", self.file.display()))?; }
+        else                     { f.write_fmt(format_args!("files   = {:?}\n", self.files))?; }
         f.write_fmt(format_args!("dataset = {}\n", self.dataset))?;
         f.write_fmt(format_args!("energy  : {}\n", self.energy))?;
         f.write_fmt(format_args!("charge  : {}\n", self.charge))?;
@@ -711,7 +1090,11 @@ impl Display for Iterations {
 
 impl Display for Fov {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("nvoxels = {:?}\nsize = {:?}", self.nvoxels, self.size))?;
+        f.write_fmt(format_args!("nvoxels = {:?}\nsize = {:?}\ncentre = {:?}\n", self.nvoxels, self.size, self.centre))?;
+        if let Some(Support { cylinder, image }) = &self.support {
+            if let Some(SupportCylinder { r }) = cylinder { f.write_fmt(format_args!("support cylinder r = {r:?}\n"))? }
+            if let Some(image)                 = image    { f.write_fmt(format_args!("support image = {}\n", image.display()))? }
+        }
         let (nx, ny, nz) = self.nvoxels;
         let (dx, dy, dz) = self.size;
         let voxel_size = (dx/nx as f32, dy/ny as f32, dz/nz as f32);
@@ -0,0 +1,193 @@
+//! Configuration file parser for user-defined phantoms
+//!
+//! A phantom is a sequence of components, each of which is one of the ROI
+//! shapes used in FOM calculations, optionally restricted to an axial range,
+//! and filled with some activity and density:
+//!
+//! ```toml
+//! [[component]]
+//! cylinder_z = { centre = ["0 mm", "0 mm"], r = "100 mm" }
+//! z = ["-90 mm", "90 mm"]
+//! activity = 1.0
+//! density = 1.0
+//!
+//! [[component]]
+//! sphere = { centre = ["50 mm", "0 mm", "0 mm"], r = "10 mm" }
+//! activity = 4.0
+//! density = 1.0
+//! ```
+//!
+//! Any of the shapes described in [`super::roi`] can be used via `roi`:
+//!
+//! ```toml
+//! [[component]]
+//! roi = { ellipsoid = { centre = ["0 mm", "0 mm", "0 mm"], semi_axes = ["50 mm", "30 mm", "20 mm"] } }
+//! activity = 2.0
+//! ```
+//!
+//! Later components overwrite earlier ones wherever they overlap.
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub component: Vec<Component>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Component {
+
+    pub sphere: Option<Sphere>,
+
+    pub cylinder_x: Option<Cylinder>,
+
+    pub cylinder_y: Option<Cylinder>,
+
+    pub cylinder_z: Option<Cylinder>,
+
+    pub roi: Option<Roi>,
+
+    /// Restrict the shape to `z_min <= z < z_max`
+    #[serde(default, deserialize_with = "deserialize_uom_2d_opt")]
+    pub z: Option<(Length, Length)>,
+
+    /// Activity inside the shape
+    #[serde(default)]
+    pub activity: Intensityf32,
+
+    /// Density inside the shape, in g/cm³
+    #[serde(default)]
+    pub density: f32,
+}
+
+impl Config {
+    /// Check that every component specifies exactly one shape, and a non-empty
+    /// axial range, if any
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (n, component) in self.component.iter().enumerate() {
+            component.validate().map_err(|e| format!("Phantom component {n}: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Component {
+    /// The shape of this component. Fails unless exactly one shape has been
+    /// specified, or if a mask image cannot be read.
+    pub fn roi(&self) -> Result<ROI, Box<dyn Error>> {
+        self.shape()?.roi()
+    }
+
+    fn shape(&self) -> Result<Roi, String> {
+        Ok(match (self.sphere, self.cylinder_x, self.cylinder_y, self.cylinder_z, &self.roi) {
+            (Some(s), None, None, None, None) => Roi { sphere:     Some(s), ..Roi::default() },
+            (None, Some(c), None, None, None) => Roi { cylinder_x: Some(c), ..Roi::default() },
+            (None, None, Some(c), None, None) => Roi { cylinder_y: Some(c), ..Roi::default() },
+            (None, None, None, Some(c), None) => Roi { cylinder_z: Some(c), ..Roi::default() },
+            (None, None, None, None, Some(roi)) => roi.clone(),
+            _ => return Err("must specify exactly one of `sphere`, `cylinder_x`, `cylinder_y`, `cylinder_z` or `roi`".into()),
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.shape()?;
+        if let Some((lo, hi)) = self.z {
+            if lo >= hi { return Err(format!("empty z range [{} mm, {} mm)", mm_(lo), mm_(hi))) }
+        }
+        Ok(())
+    }
+}
+
+/// Read and validate a phantom description
+pub fn read_config_file(path: PathBuf) -> Result<Config, Box<dyn Error>> {
+    let config = fs::read_to_string(&path)
+        .map_err(|e| format!("Couldn't read config file `{}`: {e}", path.display()))?;
+    let config: Config = toml::from_str(&config)
+        .map_err(|e| format!("Couldn't parse config file `{}`: {e}", path.display()))?;
+    config.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use units::mm;
+
+    #[test]
+    fn parse_components() {
+        let config: Config = toml::from_str(r#"
+            [[component]]
+            cylinder_z = { centre = ["0 mm", "0 mm"], r = "100 mm" }
+            z = ["-90 mm", "90 mm"]
+            activity = 1.0
+            density = 1.0
+
+            [[component]]
+            sphere = { centre = ["50 mm", "0 mm", "20 mm"], r = "10 mm" }
+            activity = 4.0
+        "#).unwrap();
+        let [body, sphere] = config.component.as_slice() else { panic!("Expected 2 components") };
+
+        let ROI::CylinderZ((x, y), r) = body.roi().unwrap() else { panic!("Expected z-cylinder") };
+        assert_eq!((x, y, r), (mm(0.0), mm(0.0), mm(100.0)));
+        assert_eq!(body.z, Some((mm(-90.0), mm(90.0))));
+        assert_eq!((body.activity, body.density), (1.0, 1.0));
+
+        let ROI::Sphere((x, y, z), r) = sphere.roi().unwrap() else { panic!("Expected sphere") };
+        assert_eq!((x, y, z, r), (mm(50.0), mm(0.0), mm(20.0), mm(10.0)));
+        assert_eq!(sphere.z, None);
+        assert_eq!((sphere.activity, sphere.density), (4.0, 0.0));
+    }
+
+    #[test]
+    fn component_with_general_roi() {
+        let config: Config = toml::from_str(r#"
+            [[component]]
+            roi = { ellipsoid = { centre = ["0 mm", "0 mm", "0 mm"], semi_axes = ["50 mm", "30 mm", "20 mm"] } }
+            activity = 2.0
+        "#).unwrap();
+        let ROI::Ellipsoid(_, (a, b, c)) = config.component[0].roi().unwrap() else { panic!("Expected ellipsoid") };
+        assert_eq!((a, b, c), (mm(50.0), mm(30.0), mm(20.0)));
+    }
+
+    #[test]
+    fn component_with_two_shapes() {
+        let config: Config = toml::from_str(r#"
+            [[component]]
+            cylinder_z = { centre = ["0 mm", "0 mm"], r = "10 cm" }
+            sphere = { centre = ["50 mm", "0 mm", "2 cm"], r = "10 mm" }
+        "#).unwrap();
+        assert!(config.component[0].roi().is_err());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn component_with_inverted_z_range() {
+        let config: Config = toml::from_str(r#"
+            [[component]]
+            cylinder_z = { centre = ["0 mm", "0 mm"], r = "10 cm" }
+            z = ["90 mm", "-90 mm"]
+        "#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn component_without_radius() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(&file, r#"
+            [[component]]
+            sphere = { centre = ["0 mm", "0 mm", "0 mm"] }
+        "#).unwrap();
+        assert!(read_config_file(file.path().into()).is_err());
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{error::Error, fs, path::PathBuf};
+
+use serde::Deserialize;
+
+use units::{Length, mm_, todo::Intensityf32};
+
+use super::{deserialize_uom_2d_opt, roi::{Cylinder, Roi, Sphere}};
+use crate::fom::ROI;
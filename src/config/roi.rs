@@ -0,0 +1,229 @@
+//! Configuration file parser for ROIs
+//!
+//! Each ROI is a table with a single entry, whose key is the kind of ROI:
+//!
+//! ```toml
+//! roi = { sphere            = { centre = ["0 mm", "0 mm", "0 mm"], r = "10 mm" } }
+//! roi = { cylinder_z        = { centre = ["0 mm", "0 mm"], r = "10 mm" } }  # also cylinder_x, cylinder_y
+//! roi = { finite_cylinder_z = { centre = ["0 mm", "0 mm"], r = "10 mm", z = ["-5 mm", "5 mm"] } }
+//! roi = { cuboid            = { centre = ["0 mm", "0 mm", "0 mm"], size = ["10 mm", "20 mm", "30 mm"] } }
+//! roi = { ellipsoid         = { centre = ["0 mm", "0 mm", "0 mm"], semi_axes = ["10 mm", "20 mm", "30 mm"] } }
+//! roi = { mask              = { file = "labels.raw", label = 3 } }
+//! roi = { union        = [ { sphere = { ... } }, { cuboid = { ... } } ] }
+//! roi = { intersection = [ { ... }, { ... } ] }
+//! roi = { difference   = [ { ... }, { ... } ] }  # first minus second
+//! ```
+//!
+//! A file containing several named ROIs (as used by `imagecompare`) has one
+//! `[[region]]` table per ROI:
+//!
+//! ```toml
+//! [[region]]
+//! name = "hot sphere"
+//! roi = { sphere = { centre = ["0 mm", "0 mm", "0 mm"], r = "10 mm" } }
+//! ```
+
+/// Exactly one of the fields must be given
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Roi {
+    pub sphere: Option<Sphere>,
+    pub cylinder_x: Option<Cylinder>,
+    pub cylinder_y: Option<Cylinder>,
+    pub cylinder_z: Option<Cylinder>,
+    pub finite_cylinder_z: Option<FiniteCylinder>,
+    pub cuboid: Option<Cuboid>,
+    pub ellipsoid: Option<Ellipsoid>,
+    pub mask: Option<Mask>,
+    pub union: Option<Vec<Roi>>,
+    pub intersection: Option<Vec<Roi>>,
+    /// Exactly two ROIs: points in the first but not in the second
+    pub difference: Option<Vec<Roi>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Sphere {
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub centre: (Length, Length, Length),
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub r: Length,
+}
+
+/// Infinite cylinder, whose centre is given by the two coordinates
+/// perpendicular to its axis, in order (eg `(x, y)` for `cylinder_z`)
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Cylinder {
+    #[serde(deserialize_with = "deserialize_uom_2d")]
+    pub centre: (Length, Length),
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub r: Length,
+}
+
+/// z-cylinder restricted to `z_min <= z < z_max`
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct FiniteCylinder {
+    #[serde(deserialize_with = "deserialize_uom_2d")]
+    pub centre: (Length, Length),
+    #[serde(deserialize_with = "deserialize_uom")]
+    pub r: Length,
+    #[serde(deserialize_with = "deserialize_uom_2d")]
+    pub z: (Length, Length),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Cuboid {
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub centre: (Length, Length, Length),
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub size: (Length, Length, Length),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Ellipsoid {
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub centre: (Length, Length, Length),
+    #[serde(deserialize_with = "deserialize_uom_3d")]
+    pub semi_axes: (Length, Length, Length),
+}
+
+/// Voxels of the label image (raw or NIfTI) in `file`, whose value is `label`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Mask {
+    pub file: PathBuf,
+    pub label: Intensityf32,
+}
+
+/// A list of named ROIs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Regions {
+    pub region: Vec<Region>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    pub roi: Roi,
+}
+
+impl Regions {
+    /// Named `fom::ROI`s, in the order in which they appear in the file
+    pub fn rois(&self) -> Result<Vec<(String, ROI)>, Box<dyn Error>> {
+        self.region.iter()
+            .map(|Region { name, roi }| Ok((name.clone(), roi.roi().map_err(|e| format!("ROI `{name}`: {e}"))?)))
+            .collect()
+    }
+}
+
+impl Roi {
+    /// Convert to a `fom::ROI`. Fails unless exactly one kind of ROI has been
+    /// specified, or if a mask image cannot be read.
+    pub fn roi(&self) -> Result<ROI, Box<dyn Error>> {
+        let all = |rois: &[Roi]| rois.iter().map(Roi::roi).collect::<Result<Vec<_>, _>>();
+        let Roi { sphere, cylinder_x, cylinder_y, cylinder_z, finite_cylinder_z, cuboid, ellipsoid,
+                  mask, union, intersection, difference } = self;
+        let mut rois = vec![];
+        if let Some(Sphere   { centre, r }) = sphere     { rois.push(ROI::Sphere   (*centre, *r)) }
+        if let Some(Cylinder { centre, r }) = cylinder_x { rois.push(ROI::CylinderX(*centre, *r)) }
+        if let Some(Cylinder { centre, r }) = cylinder_y { rois.push(ROI::CylinderY(*centre, *r)) }
+        if let Some(Cylinder { centre, r }) = cylinder_z { rois.push(ROI::CylinderZ(*centre, *r)) }
+        if let Some(FiniteCylinder { centre, r, z }) = finite_cylinder_z { rois.push(ROI::FiniteCylinderZ(*centre, *r, *z)) }
+        if let Some(Cuboid    { centre, size      }) = cuboid    { rois.push(ROI::Cuboid   (*centre, *size)) }
+        if let Some(Ellipsoid { centre, semi_axes }) = ellipsoid { rois.push(ROI::Ellipsoid(*centre, *semi_axes)) }
+        if let Some(Mask { file, label }) = mask {
+            let labels = Image::from_file(file)
+                .map_err(|e| format!("Cannot read mask image {}: {e}", file.display()))?;
+            rois.push(ROI::Mask(fom::Mask::new(labels, *label)))
+        }
+        if let Some(union)        = union        { rois.push(ROI::Union       (all(union)?)) }
+        if let Some(intersection) = intersection { rois.push(ROI::Intersection(all(intersection)?)) }
+        if let Some(difference)   = difference   {
+            let [a, b]: [ROI; 2] = all(difference)?.try_into()
+                .map_err(|_| "`difference` requires exactly 2 ROIs")?;
+            rois.push(a - b)
+        }
+        match <[ROI; 1]>::try_from(rois) {
+            Ok([roi]) => Ok(roi),
+            Err(rois) => Err(format!("ROI must be of exactly one kind, found {}", rois.len()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use units::mm;
+
+    #[derive(Deserialize)]
+    struct Wrapper { roi: Roi }
+
+    fn parse(toml: &str) -> ROI { toml::from_str::<Wrapper>(toml).unwrap().roi.roi().unwrap() }
+
+    #[test]
+    fn parse_primitives() {
+        let ROI::Cuboid(c, s) = parse(r#"roi = { cuboid = { centre = ["1 mm", "2 mm", "3 mm"], size = ["4 mm", "5 mm", "6 mm"] } }"#)
+        else { panic!("Expected cuboid") };
+        assert_eq!((c, s), ((mm(1.0), mm(2.0), mm(3.0)), (mm(4.0), mm(5.0), mm(6.0))));
+
+        let ROI::FiniteCylinderZ(c, r, z) = parse(r#"roi = { finite_cylinder_z = { centre = ["1 mm", "2 mm"], r = "3 mm", z = ["-4 mm", "5 mm"] } }"#)
+        else { panic!("Expected finite cylinder") };
+        assert_eq!((c, r, z), ((mm(1.0), mm(2.0)), mm(3.0), (mm(-4.0), mm(5.0))));
+    }
+
+    #[test]
+    fn exactly_one_kind() {
+        let both = r#"roi = { sphere = { centre = ["0 mm", "0 mm", "0 mm"], r = "1 mm" }, cylinder_z = { centre = ["0 mm", "0 mm"], r = "1 mm" } }"#;
+        assert!(toml::from_str::<Wrapper>(both).unwrap().roi.roi().is_err());
+        assert!(toml::from_str::<Wrapper>("roi = {}").unwrap().roi.roi().is_err());
+    }
+
+    #[test]
+    fn parse_combinations() {
+        let roi = parse(r#"
+            roi = { difference = [
+                      { union = [ { sphere = { centre = ["-10 mm", "0 mm", "0 mm"], r = "5 mm" } },
+                                  { sphere = { centre = [ "10 mm", "0 mm", "0 mm"], r = "5 mm" } } ] },
+                      { cuboid = { centre = ["10 mm", "0 mm", "0 mm"], size = ["2 mm", "2 mm", "2 mm"] } },
+                    ] }
+        "#);
+        let p = |x| Point::new(mm(x), mm(0.0), mm(0.0));
+        assert!( roi.contains(p(-10.0)));
+        assert!( roi.contains(p( 13.0)));
+        assert!(!roi.contains(p( 10.0)));
+        assert!(!roi.contains(p(  0.0)));
+    }
+
+    #[test]
+    fn parse_named_regions() {
+        let regions: Regions = toml::from_str(r#"
+            [[region]]
+            name = "hot"
+            roi = { sphere = { centre = ["0 mm", "0 mm", "0 mm"], r = "5 mm" } }
+
+            [[region]]
+            name = "background"
+            roi = { cylinder_z = { centre = ["0 mm", "0 mm"], r = "50 mm" } }
+        "#).unwrap();
+        let names = regions.rois().unwrap().into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(names, ["hot", "background"]);
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{error::Error, path::PathBuf};
+
+use serde::Deserialize;
+
+use units::{Length, todo::Intensityf32};
+
+use super::{deserialize_uom, deserialize_uom_2d, deserialize_uom_3d};
+use crate::{fom::{self, ROI}, image::Image};
+#[cfg(test)]
+use crate::Point;
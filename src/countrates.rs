@@ -0,0 +1,297 @@
+//! NEMA NU 2 scatter fraction, randoms fraction and Noise Equivalent Count
+//! Rate (NECR), from LORs of acquisitions of the 70 cm long scatter phantom
+//! with its off-centre line source.
+//!
+//! The LORs of each acquisition are histogrammed into a single-slice
+//! (rebinned) sinogram. In each projection angle, all bins further than
+//! `r_max` (12 cm) from the centre of the phantom are discarded, and the
+//! projection is shifted so that its maximum (the line source) lies in the
+//! centre. The shifted projections are summed, and in the resulting profile,
+//!
+//! + all counts are `total`;
+//!
+//! + counts outside the ±`window` (20 mm) band around the peak, plus the area
+//!   under the straight line joining the profile values at its edges, are
+//!   `scatter_plus_randoms`.
+//!
+//! The scatter fraction is measured in the acquisition with the lowest count
+//! rate, where randoms are assumed to be negligible. In the other
+//! acquisitions, the scatters are inferred from this scatter fraction and the
+//! measured trues, and the randoms are whatever remains of
+//! `scatter_plus_randoms`.
+
+/// Sinogram of LORs projected onto the transverse plane: `phi` in `[0, π)`,
+/// signed distance from the z-axis `s` in `[-s_max, s_max)`
+#[derive(Clone, Debug)]
+pub struct Sinogram {
+    pub n_phi: usize,
+    pub n_s: usize,
+    pub s_max: Length,
+    pub data: Vec<f64>,
+}
+
+impl Sinogram {
+
+    pub fn new(n_phi: usize, n_s: usize, s_max: Length) -> Self {
+        Self { n_phi, n_s, s_max, data: vec![0.0; n_phi * n_s] }
+    }
+
+    pub fn bin_width(&self) -> Length { 2.0 * self.s_max / self.n_s as f32 }
+
+    /// `phi` and `s` of the projection of the LOR through `p1` and `p2`
+    /// onto the transverse plane. `None` for LORs parallel to the z-axis.
+    pub fn phi_and_s((x1, y1): (Length, Length), (x2, y2): (Length, Length)) -> Option<(Angle, Length)> {
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let norm = (dx*dx + dy*dy).sqrt();
+        if norm == mm(0.0) { return None }
+        let s = (dx * y1 - dy * x1) / norm;
+        let phi = dy.atan2(dx);
+        // Fold phi into [0, π): the same line with the opposite orientation
+        // has the opposite s
+        Some(if phi < turn(0.0) { (phi + turn(0.5), -s) }
+             else if phi >= turn(0.5) { (phi - turn(0.5), -s) }
+             else { (phi, s) })
+    }
+
+    /// Index into `data` of the bin containing the projection of the LOR,
+    /// if it lies within the sinogram
+    pub fn index(&self, p1: (Length, Length), p2: (Length, Length)) -> Option<usize> {
+        let (phi, s) = Self::phi_and_s(p1, p2)?;
+        let i_phi = ((turn_(phi) * 2.0 * self.n_phi as f32) as usize).min(self.n_phi - 1);
+        let i_s = ratio_((s + self.s_max) / self.bin_width()).floor();
+        if i_s < 0.0 || i_s >= self.n_s as f32 { return None }
+        Some(i_phi * self.n_s + i_s as usize)
+    }
+
+    pub fn fill(&mut self, lor: &Hdf5Lor) {
+        let &Hdf5Lor { x1, y1, x2, y2, .. } = lor;
+        if let Some(i) = self.index((mm(x1), mm(y1)), (mm(x2), mm(y2))) {
+            self.data[i] += 1.0;
+        }
+    }
+
+    /// Sum of projections, each shifted so that its maximum lies in the
+    /// central bin, after discarding bins further than `r_max` from the axis.
+    /// Bin `n_s` of the result corresponds to the peak.
+    pub fn summed_projection(&self, r_max: Length) -> Vec<f64> {
+        let n = self.n_s;
+        let centre = |i: usize| (i as f32 + 0.5) * self.bin_width() - self.s_max;
+        let mut sum = vec![0.0; 2 * n + 1];
+        for row in self.data.chunks(n) {
+            let masked: Vec<f64> = row.iter().enumerate()
+                .map(|(i, &v)| if centre(i).abs() <= r_max { v } else { 0.0 })
+                .collect();
+            let peak = masked.iter().enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(0, |(i, _)| i);
+            for (i, v) in masked.into_iter().enumerate() {
+                sum[n + i - peak] += v;
+            }
+        }
+        sum
+    }
+
+    /// Apply the NEMA strip method to this sinogram. A window wider than the
+    /// summed projection is clipped to it.
+    pub fn strip_counts(&self, r_max: Length, window: Length) -> StripCounts {
+        let profile = self.summed_projection(r_max);
+        let peak = self.n_s;
+        let w = ratio_(window / self.bin_width()).round() as usize;
+        let (lo, hi) = (peak.saturating_sub(w), (peak + w).min(profile.len() - 1));
+        let outside: f64 = profile[..lo].iter().chain(&profile[hi+1..]).sum();
+        // Area under the straight line joining the edges of the window
+        let under_peak = (profile[lo] + profile[hi]) / 2.0 * (hi - lo + 1) as f64;
+        StripCounts {
+            total: profile.iter().sum(),
+            scatter_plus_randoms: outside + under_peak,
+        }
+    }
+}
+
+/// Counts found by the NEMA strip method
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripCounts {
+    pub total: f64,
+    pub scatter_plus_randoms: f64,
+}
+
+impl StripCounts {
+    pub fn trues(&self) -> f64 { self.total - self.scatter_plus_randoms }
+}
+
+/// Count-rate performance of a single acquisition. Rates are in counts per
+/// second; fractions are ratios (not percentages).
+#[derive(Clone, Copy, Debug)]
+pub struct CountRates {
+    pub total: f64,
+    pub trues: f64,
+    pub scatters: f64,
+    pub randoms: f64,
+    pub scatter_fraction: f64,
+    pub randoms_fraction: f64,
+    pub necr: f64,
+}
+
+/// Count rates of each of the `acquisitions` (strip counts and acquisition
+/// duration). The scatter fraction is taken from the acquisition with the
+/// lowest total count rate; it is returned alongside the rates.
+pub fn count_rates(acquisitions: &[(StripCounts, Time)]) -> Option<(f64, Vec<CountRates>)> {
+    let seconds = |t: Time| ns_(t) as f64 * 1e-9;
+    let rate = |(c, t): &(StripCounts, Time)| c.total / seconds(*t);
+    let lowest = acquisitions.iter()
+        .min_by(|a, b| rate(a).total_cmp(&rate(b)))?.0;
+    let sf = lowest.scatter_plus_randoms / lowest.total;
+    let rates = acquisitions.iter()
+        .map(|&(counts, duration)| {
+            let t = seconds(duration);
+            let trues = counts.trues();
+            let scatters = (sf / (1.0 - sf) * trues).min(counts.scatter_plus_randoms);
+            let randoms = counts.scatter_plus_randoms - scatters;
+            CountRates {
+                total: counts.total / t,
+                trues: trues / t,
+                scatters: scatters / t,
+                randoms: randoms / t,
+                scatter_fraction: scatters / (trues + scatters),
+                randoms_fraction: randoms / counts.total,
+                necr: trues * trues / (counts.total * t),
+            }
+        })
+        .collect();
+    Some((sf, rates))
+}
+
+/// Populations of LORs according to MC truth: the LORs' own classification
+/// where they have one (LOR record version 2); otherwise it is inferred from
+/// the energies of the photons (as in the scattergram): LORs in which either
+/// photon has lost energy are scatters, and randoms cannot be identified. LORs
+/// with neither classification nor energies are ignored.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TruthCounts {
+    pub trues: usize,
+    pub scatters: usize,
+    pub randoms: usize,
+}
+
+impl TruthCounts {
+
+    /// Count `lor` if its projection lies within `r_max` of the z-axis
+    pub fn fill(&mut self, lor: &ExtendedLor, r_max: Length) {
+        let &ExtendedLor { x1, y1, x2, y2, E1: e1, E2: e2, .. } = lor;
+        let Some((_, s)) = Sinogram::phi_and_s((mm(x1), mm(y1)), (mm(x2), mm(y2))) else { return };
+        if s.abs() > r_max { return }
+        match lor.truth() {
+            Truth::True    => self.trues    += 1,
+            Truth::Scatter => self.scatters += 1,
+            Truth::Random  => self.randoms  += 1,
+            Truth::Unknown if e1.is_nan() || e2.is_nan() => {},
+            Truth::Unknown => if e1.min(e2) < 510.0 { self.scatters += 1 } else { self.trues += 1 },
+        }
+    }
+
+    pub fn scatter_fraction(&self) -> f64 {
+        self.scatters as f64 / (self.trues + self.scatters) as f64
+    }
+
+    pub fn randoms_fraction(&self) -> f64 {
+        self.randoms as f64 / (self.trues + self.scatters + self.randoms) as f64
+    }
+}
+
+#[cfg(test)]
+mod test_countrates {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use units::{mm_, ns};
+
+    /// LOR through the point `(x, y)` at angle `phi`, with given energies
+    fn lor_through((x, y): (f32, f32), phi: f32, e: f32) -> Hdf5Lor {
+        let (c, s) = (phi.cos() * 400.0, phi.sin() * 400.0);
+        Hdf5Lor { dt: 0.0, x1: x - c, y1: y - s, z1: 0.0, x2: x + c, y2: y + s, z2: 0.0,
+                  q1: f32::NAN, q2: f32::NAN, E1: e, E2: e }
+    }
+
+    #[test]
+    fn phi_and_s_fold() {
+        let (phi_a, s_a) = Sinogram::phi_and_s((mm(-10.0), mm(5.0)), (mm(10.0), mm(5.0))).unwrap();
+        let (phi_b, s_b) = Sinogram::phi_and_s((mm(10.0), mm(5.0)), (mm(-10.0), mm(5.0))).unwrap();
+        assert_float_eq!(turn_(phi_a), turn_(phi_b), abs <= 1e-6);
+        assert_float_eq!(mm_(s_a), mm_(s_b), abs <= 1e-4);
+        assert_float_eq!(mm_(s_a).abs(), 5.0, abs <= 1e-4);
+    }
+
+    /// Line source 45 mm below the axis, plus a uniform background of
+    /// scatters within 100 mm of the axis
+    fn acquisition(n_true: usize, n_scatter: usize, seed: u64) -> (Sinogram, TruthCounts) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut sinogram = Sinogram::new(90, 150, mm(150.0));
+        let mut truth = TruthCounts::default();
+        let source = (0.0, -45.0);
+        let lors = (0..n_true).map(|_| lor_through(source, rng.gen_range(0.0..std::f32::consts::PI), 511.0))
+            .collect::<Vec<_>>().into_iter()
+            .chain((0..n_scatter).map(|_| {
+                let p = (rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0));
+                lor_through(p, rng.gen_range(0.0..std::f32::consts::PI), 400.0)
+            }).collect::<Vec<_>>());
+        for lor in lors {
+            sinogram.fill(&lor);
+            truth.fill(&ExtendedLor::from(lor), mm(120.0));
+        }
+        (sinogram, truth)
+    }
+
+    #[test]
+    fn strip_method_finds_scatter_fraction() {
+        let (sinogram, truth) = acquisition(50_000, 25_000, 1);
+        let counts = sinogram.strip_counts(mm(120.0), mm(20.0));
+        let estimated = counts.scatter_plus_randoms / counts.total;
+        // The scatters under the peak are only estimated
+        assert_float_eq!(estimated, truth.scatter_fraction(), abs <= 0.02);
+    }
+
+    #[test]
+    fn truth_classification_preferred_to_energies() {
+        let mut truth = TruthCounts::default();
+        let lor = |e, t: Truth| ExtendedLor { truth: t as u8, ..ExtendedLor::from(lor_through((0.0, 10.0), 0.3, e)) };
+        truth.fill(&lor(511.0, Truth::Unknown), mm(120.0));
+        truth.fill(&lor(400.0, Truth::Unknown), mm(120.0));
+        truth.fill(&lor(511.0, Truth::Scatter), mm(120.0));
+        truth.fill(&lor(511.0, Truth::Random ), mm(120.0));
+        truth.fill(&lor(400.0, Truth::True   ), mm(120.0));
+        truth.fill(&lor(f32::NAN, Truth::Unknown), mm(120.0));
+        assert_eq!((truth.trues, truth.scatters, truth.randoms), (2, 2, 1));
+        assert_float_eq!(truth.scatter_fraction(), 0.5, abs <= 1e-12);
+        assert_float_eq!(truth.randoms_fraction(), 0.2, abs <= 1e-12);
+    }
+
+    #[test]
+    fn window_wider_than_profile() {
+        let mut sinogram = Sinogram::new(90, 10, mm(50.0));
+        for i in 0..100 { sinogram.fill(&lor_through((0.0, -20.0), i as f32 * 0.03, 511.0)) }
+        let counts = sinogram.strip_counts(mm(50.0), mm(200.0));
+        assert_float_eq!(counts.total, 100.0, abs <= 1e-9);
+        assert!(counts.scatter_plus_randoms >= 0.0 && counts.trues() >= 0.0, "{counts:?}");
+    }
+
+    #[test]
+    fn randoms_inferred_from_low_activity_scatter_fraction() {
+        let low  = StripCounts { total: 1000.0, scatter_plus_randoms: 300.0 };
+        let high = StripCounts { total: 5000.0, scatter_plus_randoms: 2000.0 };
+        let (sf, rates) = count_rates(&[(high, ns(1e9)), (low, ns(1e9))]).unwrap();
+        assert_float_eq!(sf, 0.3, abs <= 1e-12);
+        let high = rates[0];
+        // trues = 3000; scatters = 0.3/0.7 * 3000
+        assert_float_eq!(high.trues, 3000.0, rmax <= 1e-6);
+        assert_float_eq!(high.scatters, 3000.0 * 3.0 / 7.0, rmax <= 1e-6);
+        assert_float_eq!(high.scatter_fraction, 0.3, rmax <= 1e-6);
+        assert_float_eq!(high.randoms, 2000.0 - 3000.0 * 3.0 / 7.0, rmax <= 1e-6);
+        assert_float_eq!(high.necr, 3000.0 * 3000.0 / 5000.0, rmax <= 1e-6);
+        assert_float_eq!(rates[1].randoms, 0.0, abs <= 1e-9);
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use units::{Angle, Length, Time, mm, ns_, ratio_, turn, turn_};
+
+use crate::io::hdf5::{ExtendedLor, Hdf5Lor, Truth};
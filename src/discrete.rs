@@ -59,6 +59,31 @@ impl Discretize {
         Indices {n_phi: n_phi as i32, n_z: n_z as i32}
     }
 
+    /// Identifier of the element containing `(x, y, z)`: `n_z * n_azimuthal +
+    /// n_phi`, where `n_phi` in `[0, n_azimuthal)` counts elements anticlockwise
+    /// from the x-axis, and `n_z` counts them axially, from the one at `z = 0`.
+    pub fn crystal_id(self, x: Length, y: Length, z: Length) -> i32 {
+        let n_azimuthal = self.help().n_azimuthal as i32;
+        let Indices { n_phi, n_z } = self.cell_indices(x, y, z);
+        n_z * n_azimuthal + n_phi.rem_euclid(n_azimuthal)
+    }
+
+    /// Centre of the element with the given `crystal_id`
+    pub fn crystal_centre(self, id: i32) -> TripleLength {
+        let n_azimuthal = self.help().n_azimuthal as i32;
+        self.indices_to_centre(Indices { n_z: id.div_euclid(n_azimuthal), n_phi: id.rem_euclid(n_azimuthal) })
+    }
+
+    /// Number of elements around the circumference
+    pub fn n_azimuthal(self) -> u32 { self.help().n_azimuthal }
+
+    /// Number of rings of elements in a detector with axial length
+    /// `scintillator_length`: always odd, so that there is one at `z = 0`
+    pub fn n_axial(self, scintillator_length: Length) -> u32 {
+        let n_half_axial = ratio_(scintillator_length / 2.0 / self.dz).round() as u32;
+        2 * n_half_axial + 1
+    }
+
     pub fn indices_to_centre(self, Indices { n_z, n_phi }: Indices) -> TripleLength {
         let Discretize { dr, dz, .. } = self;
         let HelpDiscretize { d_azimuthal, n_radial, .. } = self.help();
@@ -76,43 +101,41 @@ impl Discretize {
     /// + to the centre of the element, if `self.smear` is `false`
     /// + to a random point in the element, otherwise
     pub fn make_adjust_fn(self) -> Arc<dyn Fn(TripleLength) -> TripleLength + Send + Sync> {
+        match self.adjust {
+            Adjust::No => Arc::new(|(x,y,z)| (x,y,z)),
+            _          => Arc::new(move |p| self.adjust_with_rng(p, &mut rand::thread_rng())),
+        }
+    }
+
+    /// Adjust the position of an `xyz`-point within a scintillator element,
+    /// according to `self.adjust`, drawing any random numbers from `rng`.
+    pub fn adjust_with_rng(self, (x, y, z): TripleLength, rng: &mut impl Rng) -> TripleLength {
         let Discretize { dr, dz, .. } = self;
         let HelpDiscretize { n_radial, d_azimuthal, .. } = self.help();
-
+        let mut smear = |x| smear_with(x, rng);
         match self.adjust {
-            Adjust::No => Arc::new(|(x,y,z)| (x,y,z)),
+            Adjust::No => (x, y, z),
             Adjust::Random => { // Move to random position in box
-                let n_radial = ratio_(n_radial);
-                Arc::new(move |(x, y, z)| {
-                    let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
-                    let z          = smear(n_z   as f32) * dz;
-                    let phi: Angle = smear(n_phi as f32) * d_azimuthal;
-                    let r          = smear(n_radial)     * dr;
-                    let x = r * phi.cos();
-                    let y = r * phi.sin();
-                    (x, y, z)
-                })},
+                let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
+                let z          = smear(n_z   as f32)     * dz;
+                let phi: Angle = smear(n_phi as f32)     * d_azimuthal;
+                let r          = smear(ratio_(n_radial)) * dr;
+                (r * phi.cos(), r * phi.sin(), z)
+            },
             Adjust::Centre => { // Move to centre of element
                 let r = n_radial * dr;
-                Arc::new(move |(x, y, z)| {
-                    let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
-                    let z          = n_z   as f32 * dz;
-                    let phi: Angle = n_phi as f32 * d_azimuthal;
-                    let x = r * phi.cos();
-                    let y = r * phi.sin();
-                    (x, y, z)
-                })
+                let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
+                let z          = n_z   as f32 * dz;
+                let phi: Angle = n_phi as f32 * d_azimuthal;
+                (r * phi.cos(), r * phi.sin(), z)
             },
             Adjust::RandomZPhi => { // Keep r, move z & phi to random position in box
-                Arc::new(move |(x, y, z)| {
-                    let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
-                    let z          = smear(n_z   as f32) * dz;
-                    let phi: Angle = smear(n_phi as f32) * d_azimuthal;
-                    let r          = x.hypot(y);
-                    let x = r * phi.cos();
-                    let y = r * phi.sin();
-                    (x, y, z)
-                })},
+                let Indices {n_phi, n_z} = self.cell_indices(x, y, z);
+                let z          = smear(n_z   as f32) * dz;
+                let phi: Angle = smear(n_phi as f32) * d_azimuthal;
+                let r          = x.hypot(y);
+                (r * phi.cos(), r * phi.sin(), z)
+            },
         }
     }
 
@@ -210,7 +233,10 @@ pub struct Indices {
 }
 
 /// Randomly shift `x` to `[x - 1/2, x + 1/2)`
-fn smear(x: f32) -> f32 { x - 0.5 + rand::random::<f32>() }
+fn smear(x: f32) -> f32 { smear_with(x, &mut rand::thread_rng()) }
+
+/// Randomly shift `x` to `[x - 1/2, x + 1/2)`, using the given RNG
+fn smear_with(x: f32, rng: &mut impl Rng) -> f32 { x - 0.5 + rng.gen::<f32>() }
 
 type TripleLength = (Length, Length, Length);
 type TripleF32 = (f32, f32, f32);
@@ -251,6 +277,24 @@ mod test_discretize {
         }
     }
 
+    #[test]
+    fn crystal_ids_are_unique() {
+        let d = Discretize::from_f32s_in_mm(350.0, 20.0, 6.0, 6.0, Adjust::No);
+        let ids = d.centre_all_elements(mm(60.0))
+            .map(|Point { x, y, z }| d.crystal_id(x, y, z))
+            .collect::<Vec<_>>();
+        let unique = ids.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(unique.len(), ids.len());
+        // The element at phi = 0, z = 0 is number 0
+        assert_eq!(d.crystal_id(mm(360.0), mm(0.0), mm(0.0)), 0);
+        assert_eq!(ids.len() as u32, d.n_azimuthal() * d.n_axial(mm(60.0)));
+        // Element centres are mapped back to their own ids
+        for &id in &ids {
+            let (x, y, z) = d.crystal_centre(id);
+            assert_eq!(d.crystal_id(x, y, z), id);
+        }
+    }
+
     proptest!{
         #[test]
         fn smear_statistics(
@@ -272,6 +316,8 @@ mod test_discretize {
 // ----- Imports ------------------------------------------------------------------------------------------
 use std::sync::Arc;
 
+use rand::Rng;
+
 use crate::Point;
 
 use units::{
@@ -1,10 +1,17 @@
+pub mod resolution;
+
+use std::sync::Arc;
+
+use itertools::iproduct;
+
 use units::{
     Length, Quantity,
-    ratio_,
+    in_base_unit, mm_, ratio_,
     todo::{Intensityf32, Ratiof32},
 };
 use crate::{
-    Point, FOV,
+    Point, Vector, FOV,
+    config,
     image::{Image, ImageData},
     io::raw,
 };
@@ -17,6 +24,8 @@ pub fn load_image(filename: &std::path::Path, fov: FOV) -> BoxErr<Image> {
 }
 
 
+/// Region of interest: a set of points, which can be combined with other ROIs
+/// by union, intersection and difference.
 #[derive(Clone, Debug)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum ROI {
@@ -24,54 +33,178 @@ pub enum ROI {
     CylinderX((Length, Length), Length),
     CylinderY((Length, Length), Length),
     CylinderZ((Length, Length), Length),
+    /// Disc in the z-slice whose centres lie at the given z
     DiscZ((Length, Length, Length), Length),
+    /// z-cylinder restricted to `z_min <= z < z_max`
+    FiniteCylinderZ((Length, Length), Length, (Length, Length)),
+    /// Axis-aligned box: centre and full size along each axis
+    Cuboid((Length, Length, Length), (Length, Length, Length)),
+    /// Axis-aligned ellipsoid: centre and semi-axes
+    Ellipsoid((Length, Length, Length), (Length, Length, Length)),
+    /// Voxels of a label image which have a given label
+    Mask(Mask),
+    Union(Vec<ROI>),
+    Intersection(Vec<ROI>),
+    /// Points in the first ROI, but not in the second
+    Difference(Box<ROI>, Box<ROI>),
+}
+
+/// Points lying in those voxels of a label image, whose value is `label`
+#[derive(Clone)]
+pub struct Mask {
+    pub labels: Arc<Image>,
+    pub label: Intensityf32,
+}
+
+impl std::fmt::Debug for Mask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Mask {{ fov: {:?}, label: {} }}", self.labels.fov, self.label)
+    }
 }
 
+impl Mask {
+    pub fn new(labels: Image, label: Intensityf32) -> Self { Self { labels: Arc::new(labels), label } }
+
+    pub fn contains(&self, p: Point) -> bool {
+        let fov = self.labels.fov;
+        let FOV { n, voxel_size, .. } = fov;
+        let lo = fov.lower_corner();
+        let index = |i: usize, x: Length| {
+            let i_f = ratio_((x - lo[i]) / voxel_size[i]).floor();
+            (i_f >= 0.0 && (i_f as usize) < n[i]).then_some(i_f as usize)
+        };
+        match (index(0, p.x), index(1, p.y), index(2, p.z)) {
+            (Some(i), Some(j), Some(k)) => self.labels[[i, j, k]] == self.label,
+            _ => false,
+        }
+    }
+}
+
+/// Points whose z-coordinate is within this distance of a `DiscZ`'s z, are
+/// considered to lie in its slice
+const DISC_Z_TOLERANCE: Length = in_base_unit!(1e-3);
+
 pub type InRoiFn = Box<dyn Fn(Point) -> bool>;
 
 impl ROI {
 
     pub fn contains_fn(&self) -> InRoiFn {
+        let roi = self.clone();
+        Box::new(move |p: Point| roi.contains(p))
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
         match *self {
-            ROI::Sphere((cx, cy, cz), radius) => Box::new(move |p: Point| {
+            ROI::Sphere((cx, cy, cz), radius) => {
                 let (x,y,z) = (p.x - cx, p.y - cy, p.z - cz);
                 x*x + y*y + z*z < radius * radius
-            }),
+            },
 
-            ROI::CylinderX((cy, cz), radius) => Box::new(move |p: Point| {
+            ROI::CylinderX((cy, cz), radius) => {
                 let (y, z) = (p.y - cy, p.z - cz);
                 y*y + z*z < radius*radius
-            }),
+            },
 
-            ROI::CylinderY((cx, cz), radius) => Box::new(move |p: Point| {
+            ROI::CylinderY((cx, cz), radius) => {
                 let (x, z) = (p.x - cx, p.z - cz);
                 x*x + z*z < radius*radius
-            }),
+            },
 
-            ROI::CylinderZ((cx, cy), radius) => Box::new(move |p: Point| {
+            ROI::CylinderZ((cx, cy), radius) => {
                 let (x, y) = (p.x - cx, p.y - cy);
                 x*x + y*y < radius*radius
-            }),
+            },
+
+            ROI::DiscZ((cx, cy, z), radius) => {
+                let (x, y) = (p.x - cx, p.y - cy);
+                (z - p.z).abs() < DISC_Z_TOLERANCE && x*x + y*y < radius*radius
+            },
+
+            ROI::FiniteCylinderZ((cx, cy), radius, (z_min, z_max)) => {
+                let (x, y) = (p.x - cx, p.y - cy);
+                z_min <= p.z && p.z < z_max && x*x + y*y < radius*radius
+            },
+
+            ROI::Cuboid((cx, cy, cz), (dx, dy, dz)) => {
+                (p.x - cx).abs() < dx / 2.0 &&
+                (p.y - cy).abs() < dy / 2.0 &&
+                (p.z - cz).abs() < dz / 2.0
+            },
 
-            ROI::DiscZ((cx, cy, z), radius) => Box::new(move |p: Point| {
+            ROI::Ellipsoid((cx, cy, cz), (a, b, c)) => {
+                let (x, y, z) = (ratio_((p.x - cx) / a), ratio_((p.y - cy) / b), ratio_((p.z - cz) / c));
+                x*x + y*y + z*z < 1.0
+            },
+
+            ROI::Mask(ref mask) => mask.contains(p),
+
+            ROI::Union       (ref rois) => rois.iter().any(|roi| roi.contains(p)),
+            ROI::Intersection(ref rois) => rois.iter().all(|roi| roi.contains(p)),
+            ROI::Difference(ref a, ref b) => a.contains(p) && !b.contains(p),
+        }
+    }
+
+    /// Like `contains`, for a point `p` which lies in the voxel centred on
+    /// `voxel_centre`: a `DiscZ` covers the whole thickness of its slice.
+    fn contains_in_voxel(&self, p: Point, voxel_centre: Point, voxel_size: Vector) -> bool {
+        match *self {
+            ROI::DiscZ((cx, cy, z), radius) => {
                 let (x, y) = (p.x - cx, p.y - cy);
-                z == p.z && x*x + y*y < radius*radius
-            }),
+                (z - voxel_centre.z).abs() < voxel_size.z / 2.0 && x*x + y*y < radius*radius
+            },
+            ROI::Union       (ref rois) => rois.iter().any(|roi| roi.contains_in_voxel(p, voxel_centre, voxel_size)),
+            ROI::Intersection(ref rois) => rois.iter().all(|roi| roi.contains_in_voxel(p, voxel_centre, voxel_size)),
+            ROI::Difference(ref a, ref b) =>
+                a.contains_in_voxel(p, voxel_centre, voxel_size) && !b.contains_in_voxel(p, voxel_centre, voxel_size),
+            _ => self.contains(p),
         }
     }
 
-    pub fn r(&self) -> Length {
+    /// Fraction of the volume of the voxel centred on `centre` which lies
+    /// inside the ROI, estimated by sampling `n` points along each axis.
+    pub fn voxel_fraction(&self, centre: Point, size: Vector, n: usize) -> f32 {
+        let offset = |i: usize, d: Length| ((i as f32 + 0.5) / n as f32 - 0.5) * d;
+        let inside = iproduct!(0..n, 0..n, 0..n)
+            .filter(|&(i, j, k)| {
+                let p = Point::new(centre.x + offset(i, size.x),
+                                   centre.y + offset(j, size.y),
+                                   centre.z + offset(k, size.z));
+                self.contains_in_voxel(p, centre, size)
+            })
+            .count();
+        inside as f32 / (n * n * n) as f32
+    }
+
+    /// Radius of ROIs which have one
+    pub fn r(&self) -> Option<Length> {
         match *self {
-            ROI::Sphere   (_,r) => r,
-            ROI::CylinderX(_,r) => r,
-            ROI::CylinderY(_,r) => r,
-            ROI::CylinderZ(_,r) => r,
-            ROI::DiscZ    (_,r) => r,
+            ROI::Sphere   (_,r) => Some(r),
+            ROI::CylinderX(_,r) => Some(r),
+            ROI::CylinderY(_,r) => Some(r),
+            ROI::CylinderZ(_,r) => Some(r),
+            ROI::DiscZ    (_,r) => Some(r),
+            ROI::FiniteCylinderZ(_,r,_) => Some(r),
+            _ => None,
         }
     }
 
 }
 
+impl std::ops::BitOr for ROI {
+    type Output = ROI;
+    fn bitor(self, rhs: ROI) -> ROI { ROI::Union(vec![self, rhs]) }
+}
+
+impl std::ops::BitAnd for ROI {
+    type Output = ROI;
+    fn bitand(self, rhs: ROI) -> ROI { ROI::Intersection(vec![self, rhs]) }
+}
+
+impl std::ops::Sub for ROI {
+    type Output = ROI;
+    fn sub(self, rhs: ROI) -> ROI { ROI::Difference(Box::new(self), Box::new(rhs)) }
+}
+
 /// A 3D point with an associated value. Used to represent voxels
 pub type PointValue = (Point, Intensityf32);
 
@@ -88,6 +221,26 @@ impl Image {
         out
     }
 
+    /// Values of voxels which overlap `roi`, along with the fraction of each
+    /// voxel's volume lying inside `roi`, estimated with `n` samples along
+    /// each axis
+    pub fn weighted_values_inside_roi(&self, roi: &ROI, n: usize) -> Vec<(Intensityf32, f32)> {
+        let size = self.fov.voxel_size;
+        self.data.iter().copied()
+            .enumerate()
+            .map(|(index, value)| (value, roi.voxel_fraction(self.fov.voxel_centre1(index), size, n)))
+            .filter(|&(_, w)| w > 0.0)
+            .collect()
+    }
+
+    /// Mean value inside `roi`, with voxels weighted by the fraction of their
+    /// volume which lies inside it
+    pub fn weighted_mean_in_roi(&self, roi: &ROI, n: usize) -> Option<Intensityf32> {
+        let (sum, weights) = self.weighted_values_inside_roi(roi, n).into_iter()
+            .fold((0.0, 0.0), |(s, ws), (v, w)| (s + v * w, ws + w));
+        (weights > 0.0).then(|| sum / weights)
+    }
+
     pub fn values_with_positions(&self) -> Vec<PointValue> {
         self.data.iter().copied()
             .enumerate()
@@ -97,11 +250,12 @@ impl Image {
 
 }
 
-/// Mean of values associated with the voxels contained in the region
-pub fn mean_in_region(roi: ROI, voxels: &[PointValue]) -> f32 {
+/// Mean of values associated with the voxels contained in the region, if it
+/// contains any
+pub fn mean_in_region(roi: ROI, voxels: &[PointValue]) -> Option<f32> {
     let filter = roi.contains_fn();
     let values: Vec<_> = in_roi(filter, voxels).map(|(_,v)| v).collect();
-    mean(&values).unwrap()
+    mean(&values)
 }
 
 /// Iterator which filters out voxels that lie outside given ROI
@@ -136,6 +290,89 @@ pub fn centres_of_slices_closest_to(targets: &[Length], half_width: Length, voxe
         .collect()
 }
 
+#[cfg(test)]
+mod test_roi_algebra {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use units::{mm, mm_};
+
+    fn p(x: f32, y: f32, z: f32) -> Point { Point::new(mm(x), mm(y), mm(z)) }
+
+    #[test]
+    fn primitives() {
+        let cuboid = ROI::Cuboid((mm(0.0), mm(0.0), mm(0.0)), (mm(10.0), mm(20.0), mm(30.0)));
+        assert!( cuboid.contains(p( 4.9,  9.9,  14.9)));
+        assert!(!cuboid.contains(p( 5.1,  0.0,   0.0)));
+        assert!(!cuboid.contains(p( 0.0,  0.0, -15.1)));
+
+        let ellipsoid = ROI::Ellipsoid((mm(1.0), mm(0.0), mm(0.0)), (mm(10.0), mm(5.0), mm(2.0)));
+        assert!( ellipsoid.contains(p(10.9, 0.0, 0.0)));
+        assert!(!ellipsoid.contains(p( 1.0, 5.1, 0.0)));
+        assert!(!ellipsoid.contains(p( 1.0, 0.0, 2.1)));
+
+        let finite = ROI::FiniteCylinderZ((mm(0.0), mm(0.0)), mm(5.0), (mm(-1.0), mm(1.0)));
+        assert!( finite.contains(p(4.9, 0.0, -1.0)));
+        assert!(!finite.contains(p(0.0, 0.0,  1.0)));
+    }
+
+    #[test]
+    fn disc_z_tolerates_rounding() {
+        let z = 0.1_f32 + 0.2;
+        let disc = ROI::DiscZ((mm(0.0), mm(0.0), mm(0.3)), mm(1.0));
+        assert!(disc.contains(p(0.0, 0.0, z)));
+        assert!(!disc.contains(p(0.0, 0.0, 0.31)));
+    }
+
+    #[test]
+    fn combinations() {
+        let a = ROI::Sphere((mm(-1.0), mm(0.0), mm(0.0)), mm(2.0));
+        let b = ROI::Sphere((mm( 1.0), mm(0.0), mm(0.0)), mm(2.0));
+        let (left, centre, right) = (p(-2.5, 0.0, 0.0), p(0.0, 0.0, 0.0), p(2.5, 0.0, 0.0));
+        let union = a.clone() | b.clone();
+        assert!(union.contains(left) && union.contains(centre) && union.contains(right));
+        let intersection = a.clone() & b.clone();
+        assert!(!intersection.contains(left) && intersection.contains(centre) && !intersection.contains(right));
+        let difference = a - b;
+        assert!(difference.contains(left) && !difference.contains(centre) && !difference.contains(right));
+    }
+
+    #[test]
+    fn mask() {
+        let fov = FOV::new((mm(30.0), mm(30.0), mm(30.0)), (3, 3, 3));
+        let mut labels = Image::empty(fov);
+        labels[[2, 1, 0]] = 7.0;
+        let roi = ROI::Mask(Mask::new(labels, 7.0));
+        assert!( roi.contains(p( 10.0,  0.0, -10.0)));
+        assert!(!roi.contains(p(  0.0,  0.0,   0.0)));
+        assert!(!roi.contains(p( 20.0,  0.0, -10.0))); // outside FOV
+    }
+
+    #[test]
+    fn partial_voxel_weights() {
+        let fov = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (20, 20, 20));
+        let r = 7.3;
+        let sphere = ROI::Sphere((mm(0.3), mm(-0.6), mm(0.1)), mm(r));
+        let image = Image::ones(fov);
+        let weights = image.weighted_values_inside_roi(&sphere, 8);
+        let voxel_volume: f32 = (0..3).map(|i| mm_(fov.voxel_size[i])).product();
+        let volume = weights.iter().map(|(_, w)| w).sum::<f32>() * voxel_volume;
+        let expected = 4.0 / 3.0 * std::f32::consts::PI * r * r * r;
+        assert_float_eq!(volume, expected, rmax <= 0.01);
+        assert!(weights.iter().any(|&(_, w)| 0.0 < w && w < 1.0));
+        assert_float_eq!(image.weighted_mean_in_roi(&sphere, 4).unwrap(), 1.0, ulps <= 2);
+    }
+
+    #[test]
+    fn partial_voxel_weights_of_disc_z() {
+        let fov = FOV::new((mm(10.0), mm(10.0), mm(10.0)), (5, 5, 5));
+        // Centre of slice k=2 is 0; disc covers exactly one slice
+        let disc = ROI::DiscZ((mm(0.0), mm(0.0), mm(0.0)), mm(100.0));
+        let weights = Image::ones(fov).weighted_values_inside_roi(&disc, 3);
+        assert_eq!(weights.len(), 25);
+        assert!(weights.iter().all(|&(_, w)| w == 1.0));
+    }
+}
+
 #[cfg(test)]
 mod test_in_roi {
     use super::*;
@@ -386,7 +623,7 @@ mod test_mean {
 }
 
 /// x,y,r of FOM sphere
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct Sphere {
     pub x: Length,
     pub y: Length,
@@ -407,12 +644,17 @@ impl FomConfig {
     }
 }
 
+/// FOMs calculated by `Image::foms`, in the order of the `FomConfig` ROIs
+#[derive(Debug, Clone)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct FOMS {
+    pub background_measured: Intensityf32,
+    pub measured: Vec<Intensityf32>,
     pub crcs: Vec<Ratiof32>,
     pub snrs: Vec<Ratiof32>,
 }
 
+#[derive(Debug, Clone, Copy)]
 #[allow(clippy::upper_case_acronyms)]
 pub struct FOM {
     pub r: Length,
@@ -423,26 +665,302 @@ pub struct FOM {
 
 impl Image {
 
-    pub fn foms(&self, config: &FomConfig, quiet: bool) -> FOMS {
+    pub fn foms(&self, config: &FomConfig) -> FOMS {
         let FomConfig{ rois, background_rois, background_activity} = config;
         let background_measured = background_rois.iter().cloned()
             .map(|roi| mean(&self.values_inside_roi(roi)).unwrap())
             .sum::<Intensityf32>() / background_rois.len() as Intensityf32;
-        if !quiet {println!("    background measured (set): {:4.1} ({})", background_measured, background_activity);}
 
+        let mut measured = vec![];
         let mut crcs = vec![];
         let mut snrs = vec![];
-        if !quiet {print!("    measured roi activities:");}
         for (roi, roi_activity) in rois.iter().cloned() {
             let (roi_measured, roi_sigma) = mu_and_sigma(&self.values_inside_roi(roi)).unwrap();
-            if !quiet {print!("{:9.1} ({})", roi_measured, roi_activity);}
+            measured.push(roi_measured);
             crcs.push(crc(roi_measured, roi_activity, background_measured, *background_activity));
             snrs.push((roi_measured - background_measured) / roi_sigma); // doesn't quite match antea
         }
-        if !quiet {println!();}
-        FOMS{crcs, snrs}
+        FOMS{background_measured, measured, crcs, snrs}
+    }
+
+}
+
+// --- FOMs of phantoms described by config::fom::Config ------------------------------
+
+/// FOMs of the spheres and regions in a phantom, in the order in which they
+/// appear in its description, along with its Accuracy of Corrections, if
+/// requested.
+#[derive(Debug, Clone)]
+pub struct PhantomFoms {
+    pub spheres: Vec<(Sphere, FOM)>,
+    /// Name and true activity of each region, with its FOMs
+    pub regions: Vec<(String, Intensityf32, RegionFom)>,
+    /// NEMA NU 2 7.4.2: one value per slice, as a percentage
+    pub aocs: Option<Vec<Ratiof32>>,
+}
+
+/// Calculate the FOMs of the phantom described by `phantom`, in `image`
+pub fn phantom_foms(image: &Image, phantom: &config::fom::Config) -> BoxErr<PhantomFoms> {
+    let spheres = phantom.spheres();
+    let config::fom::Background { activity: bg_activity, xy: bg_xys, z: bg_zs, .. } = &phantom.background;
+    let foms = match &phantom.spheres {
+        Some(config::fom::Spheres { z, .. }) if !spheres.is_empty() =>
+            sphere_foms(image, &spheres, *z, bg_zs, bg_xys, *bg_activity)?,
+        _ => vec![],
+    };
+    let (regions, background) = phantom.regions()?;
+    let regions = region_foms(image, regions, &background, *bg_activity)?;
+
+    let aocs = if let Some(aoc) = phantom.aoc {
+        // The CRC of the largest sphere is needed for the Accuracy of
+        // Corrections calculation
+        let largest = foms.iter()
+            .max_by(|a, b| a.r.partial_cmp(&b.r).unwrap())
+            .ok_or("AOC calculation requires at least one sphere")?;
+        Some(accuracy_of_corrections(image, aoc, largest.crc)?)
+    } else { None };
+
+    Ok(PhantomFoms { spheres: spheres.into_iter().zip(foms).collect(), regions, aocs })
+}
+
+/// FOMs of a region of any shape
+#[derive(Debug, Clone, Copy)]
+pub struct RegionFom {
+    pub crc: Ratiof32,
+    pub bg_variability: Ratiof32,
+    pub snr: Ratiof32,
+}
+
+/// Number of samples along each axis, used to estimate the fraction of each
+/// voxel lying inside regions
+const REGION_VOXEL_SAMPLES: usize = 4;
+
+/// FOMs of the `regions` (name, ROI and true activity), relative to the mean
+/// of the `background` ROIs, whose true activity is `bg_activity`. Means are
+/// weighted by the fraction of each voxel inside the ROI.
+fn region_foms(
+    image: &Image,
+    regions: Vec<(String, ROI, Intensityf32)>,
+    background: &[ROI],
+    bg_activity: Intensityf32,
+) -> BoxErr<Vec<(String, Intensityf32, RegionFom)>> {
+    if regions.is_empty() { return Ok(vec![]) }
+    let bg_means = background.iter().enumerate()
+        .map(|(n, roi)| image.weighted_mean_in_roi(roi, REGION_VOXEL_SAMPLES)
+             .ok_or_else(|| format!("No voxels in background ROI {n}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (bg_mean, bg_sd) = mu_and_sigma(&bg_means).ok_or("No background ROIs")?;
+    let bg_variability = 100.0 * bg_sd / bg_mean;
+    regions.into_iter()
+        .map(|(name, roi, activity)| {
+            let mean = image.weighted_mean_in_roi(&roi, REGION_VOXEL_SAMPLES)
+                .ok_or_else(|| format!("No voxels in region `{name}`"))?;
+            let crc = crc(mean, activity, bg_mean, bg_activity);
+            // ROIs smaller than a voxel may contain no voxel centres
+            let sigma = mu_and_sigma(&image.values_inside_roi(roi)).map_or(f32::NAN, |(_, sigma)| sigma);
+            let snr = 100.0 * (mean - bg_mean) / sigma;
+            let snr = if activity > bg_activity { snr } else { -snr };
+            Ok((name, activity, RegionFom { crc, bg_variability, snr }))
+        })
+        .collect()
+}
+
+fn sphere_foms(
+    image         : &Image,
+    spheres       : &[Sphere],
+    sphere_z      : Length,
+    background_zs : &[Length],
+    background_xys: &[(Length, Length)],
+    background_a  : Intensityf32,
+) -> BoxErr<Vec<FOM>> {
+    let z_voxel_size = image.fov.voxel_size[2];
+    // Distance of the lower z-edge of the FOV below the origin
+    let z_half_width = -image.fov.lower_corner().z;
+
+    // Ensure that the ROIs are z-aligned with some slice
+    let background_zs = centres_of_slices_closest_to(
+        background_zs, z_half_width, z_voxel_size
+    );
+    let foreground_z = centre_of_slice_closest_to(z_half_width, z_voxel_size)(sphere_z);
+
+    let mut background_roi_centres = vec![];
+    for z in &background_zs {
+        for (cx, cy) in background_xys {
+            background_roi_centres.push((*cx,*cy,*z));
+        }
+    };
+
+    let sphere_rois: Vec<_> = spheres.iter()
+        .map(|Sphere {x,y,r,..}| ROI::DiscZ((*x,*y,foreground_z), *r) )
+        .collect();
+
+    // Annotate each voxel value with its 3D position
+    let all_voxels = image.values_with_positions();
+
+    // Discard voxels which do not lie inside any of the ROIs
+    let relevant_voxels = discard_irrelevant_voxels(&sphere_rois, &background_roi_centres, &all_voxels);
+
+    spheres.iter()
+        .map(|&sphere| contrast_and_variability(sphere, foreground_z,
+                                                background_xys, &background_zs, background_a,
+                                                &relevant_voxels)
+             .map_err(|e| format!("Sphere with diameter {:.1} mm: {e}", mm_(sphere.r) * 2.0).into()))
+        .collect()
+}
+
+// --- NEMA NU 2 7.4.2 ---------------------------------------------------------------
+/// Mean value inside the `aoc` ROI, in each slice whose centre lies within the
+/// ROI's z-range, as a percentage of `reference`
+fn accuracy_of_corrections(image: &Image, aoc: config::fom::Aoc, reference: Intensityf32) -> BoxErr<Vec<Ratiof32>> {
+    let config::fom::Aoc { centre: (x, y), r, z: (lo_limit, hi_limit) } = aoc;
+    let z_voxel_size = image.fov.voxel_size[2];
+    // Distance of the lower z-edge of the FOV below the origin
+    let z_half_width = -image.fov.lower_corner().z;
+    // Find voxel z-centres nearest to the limits
+    let nearest = centre_of_slice_closest_to(z_half_width, z_voxel_size);
+    let hi_centre = nearest(hi_limit);
+    let lo_centre = nearest(lo_limit);
+    // If centre is beyond the limit, move inwards by one voxel
+    let hi = if hi_centre <= hi_limit { hi_centre } else { nearest(hi_centre - z_voxel_size) };
+    let lo = if lo_centre >= lo_limit { lo_centre } else { nearest(lo_centre + z_voxel_size) };
+    // z-indices of first and last slices to be used
+    let index_of = |z| position_to_index(z, z_half_width, z_voxel_size);
+    let   pos_of = |z| index_to_position(z, z_half_width, z_voxel_size);
+    let hi_index = index_of(hi);
+    let lo_index = index_of(lo);
+
+    // Annotate each voxel value with its 3D position
+    let all_voxels = image.values_with_positions();
+    // Ignore voxels which lie outside of the insert
+    let insert = ROI::CylinderZ((x, y), r);
+    let insert_voxels: Vec<_> = in_roi(insert.contains_fn(), &all_voxels).collect();
+
+    // For each z-slice divide mean within ROI, by reference
+    (lo_index..=hi_index)
+        .map(|i| {
+            let disc = ROI::DiscZ((x, y, pos_of(i)), r);
+            let values: Vec<_> = in_roi(disc.contains_fn(), &insert_voxels).map(|(_,v)| v).collect();
+            mean(&values)
+                .map(|v| 100.0 * v / reference)
+                .ok_or_else(|| format!("No voxels in AOC ROI in slice {i}").into())
+        })
+        .collect()
+}
+
+// TODO this duplicates a lot of the functionality of Image::foms. It's here
+// largely because of the idiosyncrasy of the NEMA7 requirements.
+fn contrast_and_variability(sphere: Sphere,
+                            foreground_z: Length,
+                            background_xys: &[(Length, Length)],
+                            background_zs : &[Length],
+                            bg_activity: f32,
+                            voxels: &[PointValue],
+) -> Result<FOM, String> {
+    // Inspect single foreground ROI
+    let Sphere { x, y, r, a: sphere_activity } = sphere;
+    let in_sphere: Vec<_> = in_roi(ROI::DiscZ((x, y, foreground_z), r).contains_fn(), voxels)
+        .map(|(_,v)| v)
+        .collect();
+    let (sphere_mean, sphere_sigma) = mu_and_sigma(&in_sphere).ok_or("No voxels in sphere ROI")?;
+    // Inspect multiple background ROIs
+    let mut bg_means = vec![];
+    for (x,y) in background_xys {
+        for z in background_zs {
+            bg_means.push(mean_in_region(ROI::DiscZ((*x, *y,*z), r), voxels)
+                          .ok_or_else(|| format!("No voxels in background ROI at ({:.1}, {:.1}, {:.1}) mm",
+                                                 mm_(*x), mm_(*y), mm_(*z)))?);
+        }
     }
+    // Calculate background variability
+    let (bg_mean, bg_sd) = mu_and_sigma(&bg_means).ok_or("No background ROIs")?;
+    let bg_variability = 100.0 * bg_sd / bg_mean;
+    // Calculate contrast
+    let crc = crc(sphere_mean, sphere_activity, bg_mean, bg_activity);
+    let snr = 100.0 * (sphere_mean - bg_mean) / sphere_sigma;
+    let snr = if sphere_activity > bg_activity { snr } else { -snr };
+    Ok(FOM{ r, crc, bg_variability, snr })
+}
+
+/// Discard voxels which do not lie inside any of the ROIs
+fn discard_irrelevant_voxels(
+    sphere_rois           : &[ROI],
+    background_roi_centres: &[(Length, Length, Length)],
+    voxels                : &[PointValue],
+
+) -> Vec<PointValue> {
+
+    let max_roi_radius: Length = sphere_rois.iter()
+        .filter_map(ROI::r)
+        .max_by(|a, b| a.partial_cmp(b).unwrap())
+        .unwrap();
+
+    // Background ROIs corresponding to biggest sphere
+    let bg_rois = background_roi_centres.iter()
+        .map(|&(x,y,z)| ROI::DiscZ((x,y,z), max_roi_radius));
+
+    // Any of the ROIs
+    let all_rois = ROI::Union(bg_rois.chain(sphere_rois.iter().cloned()).collect());
 
+    // Discard voxels which do not lie inside any of the ROIs
+    voxels.iter().cloned()
+        .filter(|(p, _)| all_rois.contains(*p))
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+mod test_phantom_foms {
+    use super::*;
+    use units::mm;
+
+    #[test]
+    fn jaszczak_in_noiseless_image() {
+        let fov = FOV::new((mm(240.0), mm(240.0), mm(200.0)), (80, 80, 50));
+        let image = crate::phantom::Phantom::jaszczak().activity_image(fov, 3);
+        let phantom = config::fom::read_config("jaszczak").unwrap();
+        let PhantomFoms { spheres, aocs, .. } = phantom_foms(&image, &phantom).unwrap();
+        assert_eq!(spheres.len(), 6);
+        assert!(aocs.is_none());
+        for (sphere, fom) in &spheres {
+            assert_eq!(mm_(sphere.r), mm_(fom.r));
+            assert!(fom.bg_variability.abs() < 1.0, "{fom:?}");
+        }
+        // Partial volume effects are smallest in the largest sphere
+        let (_, largest) = spheres.iter().max_by(|(a,_), (b,_)| a.r.partial_cmp(&b.r).unwrap()).unwrap();
+        assert!(largest.crc > 70.0 && largest.crc <= 100.0, "{largest:?}");
+    }
+
+    #[test]
+    fn background_roi_outside_image() {
+        let fov = FOV::new((mm(240.0), mm(240.0), mm(200.0)), (80, 80, 50));
+        let image = crate::phantom::Phantom::jaszczak().activity_image(fov, 3);
+        let mut phantom = config::fom::read_config("jaszczak").unwrap();
+        phantom.background.xy.push((mm(500.0), mm(0.0)));
+        let error = phantom_foms(&image, &phantom).unwrap_err().to_string();
+        assert!(error.contains("No voxels in background ROI"), "{error}");
+    }
+
+    #[test]
+    fn regions_in_noiseless_image() {
+        let fov = FOV::new((mm(240.0), mm(240.0), mm(200.0)), (80, 80, 50));
+        let image = crate::phantom::Phantom::jaszczak().activity_image(fov, 3);
+        let phantom: config::fom::Config = toml::from_str(r#"
+            [background]
+            activity = 1.0
+            roi = [ { cuboid = { centre = [ "0 mm", "0 mm", "20 mm"], size = ["30 mm", "30 mm", "10 mm"] } },
+                    { cuboid = { centre = ["60 mm", "0 mm", "20 mm"], size = ["20 mm", "20 mm", "10 mm"] } } ]
+
+            [[region]]
+            name = "largest sphere"
+            activity = 4.0
+            roi = { ellipsoid = { centre = ["27 mm", "-46.77 mm", "34 mm"], semi_axes = ["10 mm", "10 mm", "10 mm"] } }
+        "#).unwrap();
+        let PhantomFoms { spheres, regions, .. } = phantom_foms(&image, &phantom).unwrap();
+        assert!(spheres.is_empty());
+        let [(name, activity, fom)] = regions.as_slice() else { panic!("Expected one region") };
+        assert_eq!((name.as_str(), *activity), ("largest sphere", 4.0));
+        assert!(fom.bg_variability.abs() < 1.0, "{fom:?}");
+        assert!(fom.crc > 70.0 && fom.crc <= 101.0, "{fom:?}");
+    }
 }
 
 /// Calculate hot or cold Contrast Recovery Coefficient as percentage.
@@ -0,0 +1,216 @@
+//! NEMA NU 2 spatial resolution from reconstructed images of point sources
+//!
+//! For each point source, 1D response functions are extracted through the
+//! voxel with the maximum value, along the radial, tangential and axial
+//! directions. Their widths are measured as prescribed by the standard: the
+//! peak value is found by fitting a parabola through the maximum and its two
+//! nearest neighbours; the positions at which the profile crosses the required
+//! fraction of the peak are found by linear interpolation between adjacent
+//! voxels.
+
+/// A point source located in an image
+#[derive(Debug, Clone, Copy)]
+pub struct PointSource {
+    /// 3D index of the voxel with the maximum value
+    pub index: Index3_u,
+    /// Centre of the voxel with the maximum value
+    pub position: Point,
+    pub peak: Intensityf32,
+}
+
+/// Full widths at half and tenth maximum of a 1D response function. `None`
+/// if the profile does not fall below the relevant level before reaching the
+/// edge of the image.
+#[derive(Debug, Clone, Copy)]
+pub struct Width {
+    pub fwhm: Option<Length>,
+    pub fwtm: Option<Length>,
+}
+
+/// Spatial resolution measured at one point source
+#[derive(Debug, Clone, Copy)]
+pub struct Resolution {
+    pub source: PointSource,
+    pub radial: Width,
+    pub tangential: Width,
+    pub axial: Width,
+}
+
+/// Find the point sources in `image`: local maxima whose value is at least
+/// `threshold` times the image's maximum, and which are further than
+/// `min_separation` from any brighter point source. Sorted by decreasing peak
+/// value.
+pub fn find_point_sources(image: &Image, threshold: Ratiof32, min_separation: Length) -> Vec<PointSource> {
+    let [nx, ny, nz] = image.fov.n;
+    let max = image.data.iter().copied().fold(Intensityf32::NEG_INFINITY, Intensityf32::max);
+    let level = threshold * max;
+
+    let is_local_maximum = |[i, j, k]: Index3_u, value: Intensityf32| {
+        let neighbours = |i: usize, n: usize| i.saturating_sub(1)..=(i + 1).min(n - 1);
+        iproduct!(neighbours(i, nx), neighbours(j, ny), neighbours(k, nz))
+            .all(|(a, b, c)| image[[a, b, c]] <= value)
+    };
+
+    let mut candidates: Vec<PointSource> = iproduct!(0..nx, 0..ny, 0..nz)
+        .map(|(i, j, k)| [i, j, k])
+        .filter(|&index| image[index] >= level)
+        .filter(|&index| is_local_maximum(index, image[index]))
+        .map(|index| PointSource { index, position: image.fov.voxel_centre(index), peak: image[index] })
+        .collect();
+    candidates.sort_by(|a, b| b.peak.total_cmp(&a.peak));
+
+    let mut sources: Vec<PointSource> = vec![];
+    for candidate in candidates {
+        if sources.iter().all(|s| (s.position - candidate.position).norm() > min_separation) {
+            sources.push(candidate);
+        }
+    }
+    sources
+}
+
+/// Measure the spatial resolution at `source`.
+///
+/// The radial direction is whichever of x and y is closer to the direction
+/// from the axis to the source (x for a source on the axis); the tangential
+/// direction is the other one.
+pub fn resolution(image: &Image, source: PointSource) -> Resolution {
+    let p = source.position;
+    let (radial_axis, tangential_axis) = if p.x.abs() >= p.y.abs() { (0, 1) } else { (1, 0) };
+    let width = |axis: usize| {
+        let (profile, peak_index) = profile(image, source.index, axis);
+        let voxel_size = image.fov.voxel_size[axis];
+        Width {
+            fwhm: full_width_at(0.5, &profile, peak_index).map(|w| w * voxel_size),
+            fwtm: full_width_at(0.1, &profile, peak_index).map(|w| w * voxel_size),
+        }
+    };
+    Resolution {
+        source,
+        radial:     width(radial_axis),
+        tangential: width(tangential_axis),
+        axial:      width(2),
+    }
+}
+
+/// Values of the voxels on the line parallel to `axis`, passing through the
+/// voxel with 3D index `through`, along with the position of `through` in that
+/// line.
+pub fn profile(image: &Image, through: Index3_u, axis: usize) -> (Vec<Intensityf32>, usize) {
+    let profile = (0..image.fov.n[axis])
+        .map(|i| { let mut index = through; index[axis] = i; image[index] })
+        .collect();
+    (profile, through[axis])
+}
+
+/// Full width at `fraction` of the maximum of `profile`, in units of voxels.
+///
+/// The maximum is found by fitting a parabola through `profile[peak]` and its
+/// two neighbours; the crossing points on either side are found by linear
+/// interpolation between adjacent voxels.
+pub fn full_width_at(fraction: f32, profile: &[Intensityf32], peak: usize) -> Option<f32> {
+    let level = fraction * parabolic_peak(profile, peak);
+    // Linear interpolation between voxels `i` and `j`, which lie on opposite
+    // sides of `level`
+    let crossing = |i: usize, j: usize| {
+        let (yi, yj) = (profile[i], profile[j]);
+        i as f32 + (j as f32 - i as f32) * (yi - level) / (yi - yj)
+    };
+    let left  = (0..peak)                .rev().find(|&i| profile[i] < level).map(|i| crossing(i + 1, i))?;
+    let right = (peak + 1..profile.len())      .find(|&i| profile[i] < level).map(|i| crossing(i - 1, i))?;
+    Some(right - left)
+}
+
+/// Maximum value of the parabola passing through `profile[peak]` and its two
+/// neighbours. Falls back to `profile[peak]` at the edges or on flat tops.
+pub fn parabolic_peak(profile: &[Intensityf32], peak: usize) -> Intensityf32 {
+    let y0 = profile[peak];
+    if peak == 0 || peak + 1 >= profile.len() { return y0 }
+    let (ym, yp) = (profile[peak - 1], profile[peak + 1]);
+    let curvature = ym - 2.0 * y0 + yp;
+    if curvature >= 0.0 { return y0 }
+    y0 - (ym - yp) * (ym - yp) / (8.0 * curvature)
+}
+
+#[cfg(test)]
+mod test_resolution {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use rstest::rstest;
+    use units::{mm, mm_};
+
+    /// Gaussian sampled at integer positions
+    fn gaussian(n: usize, centre: f32, fwhm: f32) -> Vec<f32> {
+        let sigma = fwhm / (8.0 * 2.0_f32.ln()).sqrt();
+        (0..n).map(|i| (-0.5 * ((i as f32 - centre) / sigma).powi(2)).exp()).collect()
+    }
+
+    #[rstest(centre, case(20.0), case(20.25), case(20.5))]
+    fn parabolic_peak_of_gaussian(centre: f32) {
+        let profile = gaussian(41, centre, 5.0);
+        let peak = parabolic_peak(&profile, centre.round() as usize);
+        assert_float_eq!(peak, 1.0, abs <= 0.02);
+    }
+
+    #[rstest(/**/ fwhm, centre,
+             case( 3.0, 20.0 ),
+             case( 5.0, 20.3 ),
+             case(10.0, 19.5 ),
+    )]
+    fn widths_of_gaussian(fwhm: f32, centre: f32) {
+        let profile = gaussian(61, centre, fwhm);
+        let peak = centre.round() as usize;
+        let fwtm = fwhm * (10.0_f32.ln() / 2.0_f32.ln()).sqrt();
+        assert_float_eq!(full_width_at(0.5, &profile, peak).unwrap(), fwhm, rmax <= 0.05);
+        assert_float_eq!(full_width_at(0.1, &profile, peak).unwrap(), fwtm, rmax <= 0.05);
+    }
+
+    #[test]
+    fn width_undefined_at_edge() {
+        let profile = gaussian(10, 1.0, 5.0);
+        assert!(full_width_at(0.5, &profile, 1).is_none());
+    }
+
+    #[test]
+    fn find_and_measure_point_sources() {
+        // 1 mm voxels
+        let fov = FOV::new((mm(61.0), mm(61.0), mm(41.0)), (61, 61, 41));
+        let mut image = Image::empty(fov);
+        // Anisotropic Gaussian blobs: wider along x than y, narrowest along z
+        let blob = |image: &mut Image, [ci, cj, ck]: [f32; 3], peak: f32| {
+            let sigma = |fwhm: f32| fwhm / (8.0 * 2.0_f32.ln()).sqrt();
+            let (sx, sy, sz) = (sigma(6.0), sigma(4.0), sigma(3.0));
+            for (i, j, k) in iproduct!(0..61, 0..61, 0..41) {
+                let (dx, dy, dz) = ((i as f32 - ci) / sx, (j as f32 - cj) / sy, (k as f32 - ck) / sz);
+                image[[i, j, k]] += peak * (-0.5 * (dx*dx + dy*dy + dz*dz)).exp();
+            }
+        };
+        blob(&mut image, [45.0, 30.0, 20.0], 1.0); // on x-axis: radial = x
+        blob(&mut image, [30.0, 10.0, 10.0], 0.8); // on y-axis: radial = y
+        let sources = find_point_sources(&image, 0.2, mm(10.0));
+        let [a, b] = sources.as_slice() else { panic!("Expected 2 sources, found {}", sources.len()) };
+        assert_eq!(a.index, [45, 30, 20]);
+        assert_eq!(b.index, [30, 10, 10]);
+
+        let fwhm = |w: Width| mm_(w.fwhm.unwrap());
+        let r = resolution(&image, *a);
+        assert_float_eq!(fwhm(r.radial    ), 6.0, rmax <= 0.05);
+        assert_float_eq!(fwhm(r.tangential), 4.0, rmax <= 0.05);
+        assert_float_eq!(fwhm(r.axial     ), 3.0, rmax <= 0.05);
+        let r = resolution(&image, *b);
+        assert_float_eq!(fwhm(r.radial    ), 4.0, rmax <= 0.05);
+        assert_float_eq!(fwhm(r.tangential), 6.0, rmax <= 0.05);
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use itertools::iproduct;
+
+use units::{Length, todo::{Intensityf32, Ratiof32}};
+
+use crate::{
+    Point,
+    image::Image,
+    index::Index3_u,
+};
+#[cfg(test)]
+use crate::FOV;
@@ -15,6 +15,11 @@ pub struct FOV {
     pub half_width: Vector,
     pub n: BoxDim_u,
     pub voxel_size: Vector,
+    /// Position of the centre of the FOV
+    pub centre: Point,
+    /// Radius of the cylindrical support around the FOV's central z-axis:
+    /// voxels outside it are not reconstructed
+    pub support: Option<Length>,
 }
 
 impl FOV {
@@ -27,7 +32,30 @@ impl FOV {
         let half_width = Vector::new(dx/2.0, dy/2.0, dz/2.0);
         let n = [nx, ny, nz];
         let voxel_size = Self::voxel_size(n, half_width);
-            Self { half_width, n, voxel_size }
+        Self { half_width, n, voxel_size, centre: Point::zero(), support: None }
+    }
+
+    /// The same voxelization, centred on `centre` rather than the origin
+    pub fn with_centre(self, centre: Point) -> Self { Self { centre, ..self } }
+
+    /// Restrict reconstruction to voxels within `r` of the FOV's central z-axis
+    pub fn with_support(self, r: Length) -> Self { Self { support: Some(r), ..self } }
+
+    /// The corner of the FOV with the lowest coordinates along all axes
+    pub fn lower_corner(&self) -> Point {
+        let Self { centre: c, half_width: h, .. } = self;
+        Point::new(c.x - h.x, c.y - h.y, c.z - h.z)
+    }
+
+    /// Is `p` inside the support of the FOV? Always true if there is no support.
+    pub fn in_support(&self, p: Point) -> bool {
+        match self.support {
+            Some(r) => {
+                let (dx, dy) = (p.x - self.centre.x, p.y - self.centre.y);
+                dx * dx + dy * dy < r * r
+            },
+            None => true,
+        }
     }
 
     fn voxel_size(n: BoxDim_u, half_width: Vector) -> Vector {
@@ -42,9 +70,10 @@ impl FOV {
     pub fn voxel_centre(&self, i: Index3_u) -> Point {
         //i.map(|n| n as f64 + 0.5).component_mul(&self.voxel_size).into()
         let s = self.voxel_size;
-        Point::new((i[0] as Lengthf32 + 0.5) * s.x - self.half_width[0],
-                   (i[1] as Lengthf32 + 0.5) * s.y - self.half_width[1],
-                   (i[2] as Lengthf32 + 0.5) * s.z - self.half_width[2],)
+        let lo = self.lower_corner();
+        Point::new((i[0] as Lengthf32 + 0.5) * s.x + lo.x,
+                   (i[1] as Lengthf32 + 0.5) * s.y + lo.y,
+                   (i[2] as Lengthf32 + 0.5) * s.z + lo.z,)
     }
 
     /// Find centre of voxel with given 1D index
@@ -63,7 +92,8 @@ impl FOV {
         let lor_direction = (p2 - p1).normalize();
         let lor_length    = (p2 - p1).norm();
         let lor: Ray = Ray::new(p1.into(), lor_direction.into());
-        let iso: Isometry = Isometry::identity();
+        let c = self.centre;
+        let iso: Isometry = Isometry::translation(mm_(c.x), mm_(c.y), mm_(c.z));
         Cuboid::new(self.half_width.into())
             .toi_with_ray(&iso, &lor, mm_(lor_length), true)
             .map(|toi| lor.origin + lor.dir * toi)
@@ -113,4 +143,28 @@ mod test_fov {
         let c = [mm_(c.x), mm_(c.y), mm_(c.z)];
         assert_float_eq!(c, expected_position, ulps <= [1, 1, 1]);
     }
+
+    #[test]
+    fn off_centre() {
+        let centre = Point::new(mm(10.0), mm(-20.0), mm(300.0));
+        let fov = FOV::new((mm(4.0), mm(4.0), mm(4.0)), (2,2,2)).with_centre(centre);
+        let c = fov.voxel_centre([1, 0, 1]);
+        assert_float_eq!([mm_(c.x), mm_(c.y), mm_(c.z)], [11.0, -21.0, 301.0], ulps <= [1, 1, 1]);
+        // LOR parallel to x-axis, passing through the shifted FOV
+        let entry = fov.entry(Point::new(mm(-100.0), mm(-20.0), mm(300.0)),
+                              Point::new(mm( 100.0), mm(-20.0), mm(300.0))).unwrap();
+        assert_float_eq!(mm_(entry.x), 8.0, abs <= 1e-4);
+        // ... and one which would hit a FOV centred on the origin
+        assert!(fov.entry(Point::new(mm(-100.0), mm(0.0), mm(0.0)),
+                          Point::new(mm( 100.0), mm(0.0), mm(0.0))).is_none());
+    }
+
+    #[test]
+    fn cylindrical_support() {
+        let centre = Point::new(mm(10.0), mm(0.0), mm(0.0));
+        let fov = FOV::new((mm(40.0), mm(40.0), mm(4.0)), (4,4,1)).with_centre(centre).with_support(mm(15.0));
+        assert!( fov.in_support(fov.voxel_centre([1, 1, 0])));
+        assert!(!fov.in_support(fov.voxel_centre([0, 0, 0])));
+        assert!( FOV::new((mm(40.0), mm(40.0), mm(4.0)), (4,4,1)).in_support(fov.voxel_centre([0, 0, 0])));
+    }
 }
@@ -4,9 +4,13 @@ use units::todo::Intensityf32;
 use crate::{
     FOV, Index1_u, Index3_u,
     index::index3_to_1,
-    io,
+    io::{self, raw::Metadata},
 };
 
+pub mod filter;
+pub mod resample;
+pub mod compare;
+
 pub type ImageData = Vec<Intensityf32>;
 
 
@@ -18,15 +22,46 @@ pub struct Image {
 
 impl Image {
 
+    /// Read a raw image of either version, discarding any metadata
     pub fn from_raw_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok((&crate::io::raw::Image3D::read_from_file(path)?).into())
+        Ok(io::raw::read_image(path)?.0)
     }
 
     pub fn write_to_raw_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        io::raw::Image3D::from(self).write_to_file(path)?;
+        self.write_to_raw_file_with(path, &Metadata::default())
+    }
+
+    pub fn write_to_raw_file_with(&self, path: &Path, metadata: &Metadata) -> Result<(), Box<dyn std::error::Error>> {
+        io::raw::write_image(self, metadata, path)?;
         Ok(())
     }
 
+    /// Read an image in the format indicated by the extension of `path`
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match io::ImageFormat::from_path(path) {
+            io::ImageFormat::Raw       => Self::from_raw_file(path),
+            io::ImageFormat::Nifti     => io::nifti::read(path),
+            io::ImageFormat::Interfile => io::interfile::read(path),
+            io::ImageFormat::MetaImage => io::metaimage::read(path),
+        }
+    }
+
+    /// Write the image in the format indicated by the extension of `path`
+    pub fn write_to_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_to_file_with(path, &Metadata::default())
+    }
+
+    /// Write the image in the format indicated by the extension of `path`,
+    /// recording `metadata` if the format is raw
+    pub fn write_to_file_with(&self, path: &Path, metadata: &Metadata) -> Result<(), Box<dyn std::error::Error>> {
+        match io::ImageFormat::from_path(path) {
+            io::ImageFormat::Raw       => self.write_to_raw_file_with(path, metadata),
+            io::ImageFormat::Nifti     => io::nifti::write(self, path),
+            io::ImageFormat::Interfile => io::interfile::write(self, path),
+            io::ImageFormat::MetaImage => io::metaimage::write(self, path),
+        }
+    }
+
     pub fn ones(fov: FOV) -> Self {
         let [x,y,z] = fov.n;
         let size = x * y * z;
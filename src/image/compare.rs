@@ -0,0 +1,207 @@
+//! Comparison of images against a reference, typically the ground truth
+//! produced by `imageprimaries`
+//!
+//! + RMSE: root mean square difference between the test and reference voxels.
+//!
+//! + NRMSE: `sqrt(sum (test - ref)^2 / sum ref^2)`.
+//!
+//! + SSIM: structural similarity, with local statistics calculated by Gaussian
+//!   smoothing, and averaged over the voxels being compared.
+//!
+//! + Bias: mean difference between test and reference, relative to the mean of
+//!   the reference.
+//!
+//! + Correlation: Pearson correlation coefficient between test and reference.
+//!
+//! All metrics can be restricted to the voxels whose centres lie inside a
+//! `ROI`. SSIM is always calculated over the whole image before being
+//! restricted, so that the local statistics near the edge of the ROI are not
+//! distorted.
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metrics {
+    pub rmse: Intensityf32,
+    pub nrmse: Ratiof32,
+    pub ssim: Ratiof32,
+    pub bias: Ratiof32,
+    pub correlation: Ratiof32,
+}
+
+/// Return `test` on the same voxels as `reference`, resampling it by trilinear
+/// interpolation if the FOVs differ
+pub fn align(test: &Image, reference: &Image) -> Image {
+    if same_fov(&test.fov, &reference.fov) { test.clone() }
+    else { test.resample_trilinear(reference.fov) }
+}
+
+/// Scale `test` so that its total activity matches that of `reference`
+pub fn scale_to_total(test: &Image, reference: &Image) -> Image {
+    let total = |image: &Image| image.data.iter().map(|&v| v as f64).sum::<f64>();
+    let (t, r) = (total(test), total(reference));
+    let factor = if t == 0.0 { 1.0 } else { (r / t) as f32 };
+    Image::new(test.fov, test.data.iter().map(|v| v * factor).collect())
+}
+
+/// Compare `test` with `reference`, in the voxels whose centres lie inside `roi`
+/// (the whole image if `None`). Local statistics for SSIM are gathered with a
+/// Gaussian window of FWHM `ssim_fwhm`.
+pub fn compare(test: &Image, reference: &Image, roi: Option<&ROI>, ssim_fwhm: Length) -> Result<Metrics, String> {
+    if !same_fov(&test.fov, &reference.fov) {
+        return Err("Cannot compare images with different FOVs: align them first".into())
+    }
+    let selected = selected_voxels(&reference.fov, roi);
+    if selected.is_empty() { return Err("No voxels inside ROI".into()) }
+    let pairs = || selected.iter().map(|&i| (test[i] as f64, reference[i] as f64));
+    let n = selected.len() as f64;
+
+    let (sum_x, sum_t) = pairs().fold((0.0, 0.0), |(sx, st), (x, t)| (sx + x, st + t));
+    let (mean_x, mean_t) = (sum_x / n, sum_t / n);
+    let (mut sq_err, mut sq_t, mut cov, mut var_x, mut var_t) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (x, t) in pairs() {
+        sq_err += (x - t).powi(2);
+        sq_t   += t * t;
+        cov    += (x - mean_x) * (t - mean_t);
+        var_x  += (x - mean_x).powi(2);
+        var_t  += (t - mean_t).powi(2);
+    }
+    let ssim_map = ssim_map(test, reference, ssim_fwhm);
+    let ssim = selected.iter().map(|&i| ssim_map[i] as f64).sum::<f64>() / n;
+
+    Ok(Metrics {
+        rmse:        (sq_err / n).sqrt() as f32,
+        nrmse:       (sq_err / sq_t).sqrt() as f32,
+        ssim:        ssim as f32,
+        bias:        ((mean_x - mean_t) / mean_t) as f32,
+        correlation: (cov / (var_x * var_t).sqrt()) as f32,
+    })
+}
+
+/// Voxelwise SSIM of `test` with respect to `reference`, using Gaussian windows
+/// of FWHM `fwhm`. The stabilizing constants are based on the dynamic range of
+/// `reference`.
+pub fn ssim_map(test: &Image, reference: &Image, fwhm: Length) -> ImageData {
+    let (lo, hi) = reference.data.iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let range = hi - lo;
+    let (c1, c2) = ((0.01 * range).powi(2), (0.03 * range).powi(2));
+    let product = |a: &Image, b: &Image| Image::new(a.fov, a.data.iter().zip(&b.data).map(|(a, b)| a * b).collect());
+    let mu_x  = test     .gaussian_filter(fwhm);
+    let mu_t  = reference.gaussian_filter(fwhm);
+    let e_xx = product(test     , test     ).gaussian_filter(fwhm);
+    let e_tt = product(reference, reference).gaussian_filter(fwhm);
+    let e_xt = product(test     , reference).gaussian_filter(fwhm);
+    (0..test.data.len())
+        .map(|i| {
+            let (mx, mt) = (mu_x[i], mu_t[i]);
+            let var_x  = (e_xx[i] - mx * mx).max(0.0);
+            let var_t  = (e_tt[i] - mt * mt).max(0.0);
+            let cov_xt =  e_xt[i] - mx * mt;
+            ((2.0 * mx * mt + c1) * (2.0 * cov_xt + c2)) /
+            ((mx * mx + mt * mt + c1) * (var_x + var_t + c2))
+        })
+        .collect()
+}
+
+/// Voxelwise bias (mean minus reference) and sample variance over several
+/// noise realisations of the same reconstruction
+pub fn bias_and_variance(realisations: &[Image], reference: &Image) -> Result<(Image, Image), String> {
+    if realisations.len() < 2 {
+        return Err(format!("Need at least 2 realisations for variance, got {}", realisations.len()))
+    }
+    if let Some(bad) = realisations.iter().position(|r| !same_fov(&r.fov, &reference.fov)) {
+        return Err(format!("Realisation {bad} has a different FOV from the reference"))
+    }
+    let n = realisations.len() as f64;
+    let (bias, variance) = (0..reference.data.len())
+        .map(|i| {
+            let values = || realisations.iter().map(move |r| r[i] as f64);
+            let mean = values().sum::<f64>() / n;
+            let variance = values().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            ((mean - reference[i] as f64) as f32, variance as f32)
+        })
+        .unzip();
+    Ok((Image::new(reference.fov, bias), Image::new(reference.fov, variance)))
+}
+
+fn selected_voxels(fov: &FOV, roi: Option<&ROI>) -> Vec<Index1_u> {
+    let all = 0..fov.n.iter().product();
+    match roi {
+        None      => all.collect(),
+        Some(roi) => all.filter(|&i| roi.contains(fov.voxel_centre1(i))).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test_compare {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use units::mm;
+
+    fn fov() -> FOV { FOV::new((mm(40.0), mm(40.0), mm(40.0)), (10, 10, 10)) }
+
+    /// Sphere of activity 4 in a background of 1
+    fn phantom() -> Image {
+        let fov = fov();
+        let sphere = ROI::Sphere((mm(0.0), mm(0.0), mm(0.0)), mm(10.0));
+        let data = (0..1000).map(|i| if sphere.contains(fov.voxel_centre1(i)) { 4.0 } else { 1.0 }).collect();
+        Image::new(fov, data)
+    }
+
+    #[test]
+    fn identical_images() {
+        let image = phantom();
+        let m = compare(&image, &image, None, mm(6.0)).unwrap();
+        assert_float_eq!(m.rmse       , 0.0, abs <= 1e-6);
+        assert_float_eq!(m.nrmse      , 0.0, abs <= 1e-6);
+        assert_float_eq!(m.bias       , 0.0, abs <= 1e-6);
+        assert_float_eq!(m.ssim       , 1.0, abs <= 1e-4);
+        assert_float_eq!(m.correlation, 1.0, abs <= 1e-6);
+    }
+
+    #[test]
+    fn scaled_image() {
+        let reference = phantom();
+        let test = Image::new(reference.fov, reference.data.iter().map(|v| v * 2.0).collect());
+        let m = compare(&test, &reference, None, mm(6.0)).unwrap();
+        assert_float_eq!(m.nrmse      , 1.0, abs <= 1e-6);
+        assert_float_eq!(m.bias       , 1.0, abs <= 1e-6);
+        assert_float_eq!(m.correlation, 1.0, abs <= 1e-6);
+        assert!(m.ssim < 0.99);
+
+        let rescaled = scale_to_total(&test, &reference);
+        let m = compare(&rescaled, &reference, None, mm(6.0)).unwrap();
+        assert_float_eq!(m.rmse, 0.0, abs <= 1e-5);
+    }
+
+    #[test]
+    fn restricted_to_roi() {
+        let reference = phantom();
+        let mut test = reference.clone();
+        // Perturb only voxels outside the sphere
+        let sphere = ROI::Sphere((mm(0.0), mm(0.0), mm(0.0)), mm(10.0));
+        for i in 0..test.data.len() {
+            if !sphere.contains(test.fov.voxel_centre1(i)) { test[i] += (i % 3) as f32 }
+        }
+        let inside = compare(&test, &reference, Some(&sphere), mm(6.0)).unwrap();
+        let global = compare(&test, &reference, None        , mm(6.0)).unwrap();
+        assert_float_eq!(inside.rmse, 0.0, abs <= 1e-6);
+        assert!(global.rmse > 0.5);
+    }
+
+    #[test]
+    fn voxelwise_bias_and_variance() {
+        let reference = phantom();
+        let offset = |d: f32| Image::new(reference.fov, reference.data.iter().map(|v| v + d).collect());
+        let realisations = [offset(0.0), offset(1.0), offset(2.0)];
+        let (bias, variance) = bias_and_variance(&realisations, &reference).unwrap();
+        assert!(bias    .data.iter().all(|&b| (b - 1.0).abs() < 1e-6));
+        assert!(variance.data.iter().all(|&v| (v - 1.0).abs() < 1e-6));
+        assert!(bias_and_variance(&realisations[..1], &reference).is_err());
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use units::{Length, todo::{Intensityf32, Ratiof32}};
+
+use crate::{FOV, Index1_u, fom::ROI};
+use super::{Image, ImageData, resample::same_fov};
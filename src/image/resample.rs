@@ -0,0 +1,272 @@
+//! Resampling of images onto a different FOV or voxelization
+//!
+//! + Nearest: each target voxel takes the value of the source voxel containing
+//!   its centre.
+//!
+//! + Trilinear: interpolation between the centres of the 8 source voxels
+//!   surrounding the centre of the target voxel.
+//!
+//! + Overlap: average of the source voxels weighted by the volume of their
+//!   overlap with the target voxel. This conserves the integral of the image,
+//!   so it is the appropriate choice when downsampling.
+//!
+//! In all cases, regions of the target FOV lying outside the source FOV are
+//! treated as zero.
+
+/// Method used to resample an `Image` onto a different `FOV`
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Resampling {
+    /// Value of the source voxel containing the centre of the target voxel
+    Nearest,
+    /// Trilinear interpolation between source voxel centres
+    #[default]
+    Trilinear,
+    /// Average of source voxels, weighted by their overlap with the target voxel
+    Overlap,
+}
+
+impl Image {
+
+    /// Resample this image onto `fov` using `method`. If `fov` is identical to
+    /// this image's FOV, the image is returned unchanged.
+    pub fn resample(&self, fov: FOV, method: Resampling) -> Self {
+        if same_fov(&self.fov, &fov) { return self.clone() }
+        match method {
+            Resampling::Nearest   => self.resample_nearest  (fov),
+            Resampling::Trilinear => self.resample_trilinear(fov),
+            Resampling::Overlap   => self.resample_overlap  (fov),
+        }
+    }
+
+    /// Resample this image onto `fov`, giving each voxel the value of the voxel
+    /// of this image which contains its centre.
+    pub fn resample_nearest(&self, fov: FOV) -> Self {
+        let source = self.fov;
+        let lo = source.lower_corner();
+        let containing = |axis: usize, x: Length| {
+            let u = ratio_((x - lo[axis]) / source.voxel_size[axis]).floor();
+            (u >= 0.0 && u < source.n[axis] as f32).then_some(u as usize)
+        };
+        let data = (0..fov.n.iter().product())
+            .into_par_iter()
+            .map(|index| {
+                let p = fov.voxel_centre1(index);
+                match (containing(0, p.x), containing(1, p.y), containing(2, p.z)) {
+                    (Some(i), Some(j), Some(k)) => self[[i, j, k]],
+                    _ => 0.0,
+                }
+            })
+            .collect();
+        Self::new(fov, data)
+    }
+
+    /// Resample this image onto `fov`, by trilinear interpolation between the
+    /// centres of the voxels of this image. Voxels of `fov` whose centres lie
+    /// outside this image's FOV are set to zero.
+    pub fn resample_trilinear(&self, fov: FOV) -> Self {
+        let source = self.fov;
+        let lo = source.lower_corner();
+        // Continuous index (voxel centres at integers) of position `x` along `axis`
+        let continuous = |axis: usize, x: Length| ratio_((x - lo[axis]) / source.voxel_size[axis]) - 0.5;
+        // Lower neighbour and interpolation weight along one axis, or `None`
+        // if outside the source FOV
+        let neighbours = |axis: usize, x: Length| {
+            let n = source.n[axis];
+            let u = continuous(axis, x);
+            if u < -0.5 || u > n as f32 - 0.5 { return None }
+            let u = u.clamp(0.0, (n - 1) as f32);
+            let i = (u.floor() as usize).min(n.saturating_sub(2));
+            Some((i, (u - i as f32).min(1.0)))
+        };
+        let data = (0..fov.n.iter().product())
+            .into_par_iter()
+            .map(|index| {
+                let p = fov.voxel_centre1(index);
+                let (Some((i, fx)), Some((j, fy)), Some((k, fz))) =
+                    (neighbours(0, p.x), neighbours(1, p.y), neighbours(2, p.z))
+                else { return 0.0 };
+                let at = |di: usize, dj: usize, dk: usize| {
+                    let [nx, ny, nz] = source.n;
+                    self[[(i + di).min(nx - 1), (j + dj).min(ny - 1), (k + dk).min(nz - 1)]]
+                };
+                let lerp = |a: f32, b: f32, f: f32| a + (b - a) * f;
+                let c00 = lerp(at(0,0,0), at(1,0,0), fx);
+                let c10 = lerp(at(0,1,0), at(1,1,0), fx);
+                let c01 = lerp(at(0,0,1), at(1,0,1), fx);
+                let c11 = lerp(at(0,1,1), at(1,1,1), fx);
+                lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+            })
+            .collect();
+        Self::new(fov, data)
+    }
+
+    /// Resample this image onto `fov`, giving each voxel the average of the
+    /// voxels of this image, weighted by the volume of their overlap with it.
+    pub fn resample_overlap(&self, fov: FOV) -> Self {
+        let [wx, wy, wz] = [0, 1, 2].map(|axis| overlap_weights(&self.fov, &fov, axis));
+        let [nx, ny, _] = fov.n;
+        let data = (0..fov.n.iter().product())
+            .into_par_iter()
+            .map(|index| {
+                let (i, j, k) = (index % nx, (index / nx) % ny, index / (nx * ny));
+                let mut sum = 0.0;
+                for &(kk, fz) in &wz[k] {
+                    for &(jj, fy) in &wy[j] {
+                        for &(ii, fx) in &wx[i] {
+                            sum += fx * fy * fz * self[[ii, jj, kk]];
+                        }
+                    }
+                }
+                sum
+            })
+            .collect();
+        Self::new(fov, data)
+    }
+
+}
+
+/// For each target voxel along `axis`, the source voxels which overlap it,
+/// along with the fraction of the target voxel's width which they cover
+fn overlap_weights(source: &FOV, target: &FOV, axis: usize) -> Vec<Vec<(usize, f32)>> {
+    let (s_lo, s_dx, s_n) = (mm_(source.lower_corner()[axis]), mm_(source.voxel_size[axis]), source.n[axis]);
+    let (t_lo, t_dx, t_n) = (mm_(target.lower_corner()[axis]), mm_(target.voxel_size[axis]), target.n[axis]);
+    (0..t_n)
+        .map(|i| {
+            let (a, b) = (t_lo + i as f32 * t_dx, t_lo + (i + 1) as f32 * t_dx);
+            let first = ((a - s_lo) / s_dx).floor().max(0.0) as usize;
+            let last  = (((b - s_lo) / s_dx).ceil().max(0.0) as usize).min(s_n);
+            (first..last)
+                .filter_map(|j| {
+                    let (c, d) = (s_lo + j as f32 * s_dx, s_lo + (j + 1) as f32 * s_dx);
+                    let overlap = b.min(d) - a.max(c);
+                    (overlap > 0.0).then_some((j, overlap / t_dx))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Do `a` and `b` describe the same voxelization of the same region?
+pub fn same_fov(a: &FOV, b: &FOV) -> bool {
+    a.n == b.n && (0..3).all(|i| a.half_width[i] == b.half_width[i] && a.centre[i] == b.centre[i])
+}
+
+/// Does the region of `outer` include the whole of `inner`? Allows for
+/// rounding errors of up to a thousandth of the smallest voxel.
+pub fn covers(outer: &FOV, inner: &FOV) -> bool {
+    (0..3).all(|i| {
+        let tolerance = 1e-3 * outer.voxel_size[i].min(inner.voxel_size[i]);
+        let (o_lo, i_lo) = (outer.lower_corner()[i], inner.lower_corner()[i]);
+        let (o_hi, i_hi) = (o_lo + 2.0 * outer.half_width[i], i_lo + 2.0 * inner.half_width[i]);
+        o_lo - tolerance <= i_lo && o_hi + tolerance >= i_hi
+    })
+}
+
+#[cfg(test)]
+mod test_resample {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use units::mm;
+
+    #[test]
+    fn identity() {
+        let fov = FOV::new((mm(30.0), mm(20.0), mm(10.0)), (6, 4, 2));
+        let image = Image::new(fov, (0..48).map(|i| i as f32).collect());
+        let resampled = image.resample_trilinear(fov);
+        assert_eq!(resampled.data, image.data);
+    }
+
+    #[test]
+    fn linear_gradient_is_preserved() {
+        // Value proportional to x: trilinear interpolation is exact
+        let coarse = FOV::new((mm(100.0), mm(100.0), mm(100.0)), (10, 10, 10));
+        let fine   = FOV::new((mm( 80.0), mm( 80.0), mm( 80.0)), (32, 32, 32));
+        let mut image = Image::empty(coarse);
+        for i in 0..image.data.len() { image[i] = mm_(coarse.voxel_centre1(i).x); }
+        let resampled = image.resample_trilinear(fine);
+        for i in 0..resampled.data.len() {
+            assert_float_eq!(resampled[i], mm_(fine.voxel_centre1(i).x), abs <= 1e-3);
+        }
+    }
+
+    #[test]
+    fn nearest_picks_containing_voxel() {
+        let coarse = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (4, 4, 4));
+        let fine   = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (8, 8, 8));
+        let image = Image::new(coarse, (0..64).map(|i| i as f32).collect());
+        let resampled = image.resample(fine, Resampling::Nearest);
+        assert_eq!(resampled[[0, 0, 0]], image[[0, 0, 0]]);
+        assert_eq!(resampled[[1, 1, 1]], image[[0, 0, 0]]);
+        assert_eq!(resampled[[7, 2, 5]], image[[3, 1, 2]]);
+    }
+
+    #[test]
+    fn overlap_averages_and_conserves_total() {
+        let fine   = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (8, 8, 8));
+        let coarse = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (4, 4, 4));
+        let image = Image::new(fine, (0..512).map(|i| (i % 7) as f32).collect());
+        let resampled = image.resample(coarse, Resampling::Overlap);
+        let expected = iproduct!(0..2, 0..2, 0..2).map(|(i, j, k)| image[[2+i, 2+j, 4+k]]).sum::<f32>() / 8.0;
+        assert_float_eq!(resampled[[1, 1, 2]], expected, ulps <= 4);
+        let total = |image: &Image| image.data.iter().sum::<f32>() * mm_(image.fov.voxel_size.x).powi(3);
+        assert_float_eq!(total(&resampled), total(&image), rmax <= 1e-5);
+    }
+
+    #[test]
+    fn overlap_with_misaligned_voxels() {
+        // Target voxels straddle source voxel boundaries: a uniform image
+        // remains uniform wherever the target lies within the source
+        let source = FOV::new((mm(30.0), mm(30.0), mm(30.0)), (3, 3, 3));
+        let target = FOV::new((mm(21.0), mm(21.0), mm(21.0)), (7, 7, 7));
+        let resampled = Image::ones(source).resample(target, Resampling::Overlap);
+        assert!(resampled.data.iter().all(|&v| (v - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn coverage() {
+        let big   = FOV::new((mm(30.0), mm(30.0), mm(30.0)), (3, 3, 3));
+        let small = FOV::new((mm(21.0), mm(30.0), mm(10.0)), (7, 7, 7));
+        assert!( covers(&big, &small));
+        assert!(!covers(&small, &big));
+        let shifted = small.with_centre(Point::new(mm(10.0), mm(0.0), mm(0.0)));
+        assert!(!covers(&big, &shifted));
+    }
+
+    #[test]
+    fn between_shifted_fovs() {
+        // Shifting both FOVs by the same amount changes nothing
+        let shift = Point::new(mm(15.0), mm(-7.0), mm(3.0));
+        let source = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (8, 8, 8));
+        let target = FOV::new((mm(20.0), mm(20.0), mm(20.0)), (3, 3, 3));
+        let image = Image::new(source, (0..512).map(|i| (i % 11) as f32).collect());
+        let shifted = Image::new(source.with_centre(shift), image.data.clone());
+        for method in [Resampling::Nearest, Resampling::Trilinear, Resampling::Overlap] {
+            let a = image  .resample(target                  , method);
+            let b = shifted.resample(target.with_centre(shift), method);
+            for (a, b) in a.data.iter().zip(&b.data) { assert_float_eq!(a, b, abs <= 1e-4) }
+        }
+    }
+
+    #[test]
+    fn outside_is_zero() {
+        let small = FOV::new((mm(10.0), mm(10.0), mm(10.0)), (2, 2, 2));
+        let large = FOV::new((mm(30.0), mm(30.0), mm(30.0)), (3, 3, 3));
+        let resampled = Image::ones(small).resample_trilinear(large);
+        assert_eq!(resampled[[1, 1, 1]], 1.0);
+        assert_eq!(resampled[[0, 1, 1]], 0.0);
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use units::{Length, mm_, ratio_};
+#[cfg(test)]
+use itertools::iproduct;
+
+use crate::FOV;
+#[cfg(test)]
+use crate::Point;
+use super::Image;
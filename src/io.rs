@@ -1,2 +1,163 @@
+pub mod castor;
+pub mod gate;
 pub mod hdf5;
+pub mod interfile;
+pub mod metaimage;
+pub mod nifti;
 pub mod raw;
+pub mod simset;
+
+/// On-disk image formats, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// petalo's own `Image3D` format (`.raw`, and any unrecognized extension)
+    Raw,
+    /// NIfTI-1: `.nii` or `.nii.gz`
+    Nifti,
+    /// Interfile 3.3 header (`.hv`) with separate data file, as used by STIR and CASToR
+    Interfile,
+    /// MetaImage header (`.mhd`) with separate data file, or with embedded data (`.mha`), as used by ITK
+    MetaImage,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Self {
+        Self::recognize(path).unwrap_or(Self::Raw)
+    }
+
+    /// The format of `path`, if its extension is one of those used for images
+    pub fn recognize(path: &Path) -> Option<Self> {
+        if nifti::is_nifti(path) { return Some(Self::Nifti) }
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "raw"         => Some(Self::Raw),
+            "hv"          => Some(Self::Interfile),
+            "mhd" | "mha" => Some(Self::MetaImage),
+            _             => None,
+        }
+    }
+}
+
+/// Numeric types of voxel values found in images written by other software
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum VoxelType { U8, I8, U16, I16, U32, I32, F32, F64 }
+
+impl VoxelType {
+    pub(crate) fn size(self) -> usize {
+        use VoxelType::*;
+        match self { U8 | I8 => 1, U16 | I16 => 2, U32 | I32 | F32 => 4, F64 => 8 }
+    }
+
+    /// Convert consecutive values of this type in `bytes` to `f32`
+    pub(crate) fn decode(self, bytes: &[u8], little_endian: bool) -> Vec<f32> {
+        macro_rules! decode { ($t:ty) => {
+            bytes.chunks_exact(std::mem::size_of::<$t>())
+                .map(|chunk| {
+                    let b = chunk.try_into().unwrap();
+                    (if little_endian { <$t>::from_le_bytes(b) } else { <$t>::from_be_bytes(b) }) as f32
+                })
+                .collect()
+        }}
+        use VoxelType::*;
+        match self {
+            U8  => decode!(u8 ), I8  => decode!(i8 ),
+            U16 => decode!(u16), I16 => decode!(i16),
+            U32 => decode!(u32), I32 => decode!(i32),
+            F32 => decode!(f32), F64 => decode!(f64),
+        }
+    }
+}
+
+/// Image from `values` stored with x varying fastest, on an axis-aligned grid
+/// of `n` voxels, the first of which is centred at `first`, with `step`s
+/// between voxel centres along each axis (in mm). Axes with negative steps are
+/// flipped, so that indices increase with position.
+pub(crate) fn axis_aligned_image(n: [usize; 3], step: [f32; 3], first: [f32; 3], values: &[f32]) -> Image {
+    let [nx, ny, nz] = n;
+    let flip = step.map(|s| s < 0.0);
+    let data = iproduct!(0..nz, 0..ny, 0..nx)
+        .map(|(k, j, i)| {
+            let [i, j, k] = [(i, nx, flip[0]), (j, ny, flip[1]), (k, nz, flip[2])]
+                .map(|(i, n, flip)| if flip { n - 1 - i } else { i });
+            values[index3_to_1([i, j, k], n)]
+        })
+        .collect();
+    let size   = [0, 1, 2].map(|i| step[i].abs() * n[i] as f32);
+    let centre = [0, 1, 2].map(|i| first[i] + step[i] * (n[i] as f32 - 1.0) / 2.0);
+    let fov = FOV::new((mm(size[0]), mm(size[1]), mm(size[2])), (nx, ny, nz))
+        .with_centre(Point::new(mm(centre[0]), mm(centre[1]), mm(centre[2])));
+    Image::new(fov, data)
+}
+
+/// Centre of the first voxel of `fov`, in mm
+pub(crate) fn first_voxel_centre(fov: &FOV) -> [f32; 3] {
+    let o = fov.voxel_centre([0, 0, 0]);
+    [mm_(o.x), mm_(o.y), mm_(o.z)]
+}
+
+/// `key <separator> value` pairs of text headers, such as those of Interfile
+/// (`:=`) and CASToR (`:`) files. Keys are compared ignoring case, whitespace
+/// and the leading `!` with which Interfile marks required keys. Empty values
+/// count as missing.
+pub(crate) struct Header {
+    /// Name of the format, for error messages
+    format: &'static str,
+    entries: HashMap<String, String>,
+}
+
+impl Header {
+
+    /// Parse the lines of `text` which contain `separator`, ignoring comments
+    /// (lines starting with `comment`)
+    pub(crate) fn from_text(format: &'static str, text: &str, separator: &str, comment: char) -> Self {
+        let entries = Self::lines(text, comment)
+            .filter_map(|line| line.split_once(separator))
+            .map(|(key, value)| (Self::normalize(key), value.trim().to_string()))
+            .collect();
+        Self { format, entries }
+    }
+
+    /// The trimmed lines of `text` which are neither blank nor comments
+    pub(crate) fn lines(text: &str, comment: char) -> impl Iterator<Item = &str> {
+        text.lines().map(str::trim).filter(move |l| !l.is_empty() && !l.starts_with(comment))
+    }
+
+    /// `key` in the form in which keys are compared
+    pub(crate) fn normalize(key: &str) -> String {
+        key.trim_start().trim_start_matches('!').chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(&Self::normalize(key)).map(String::as_str).filter(|v| !v.is_empty())
+    }
+
+    pub(crate) fn required(&self, key: &str) -> Result<&str, String> {
+        self.get(key).ok_or_else(|| format!("{} header lacks `{key}`", self.format))
+    }
+
+    pub(crate) fn parse_opt<T: FromStr>(&self, key: &str) -> Result<Option<T>, String> {
+        self.get(key)
+            .map(|v| v.parse().map_err(|_| format!("Invalid value of `{key}`: {v}")))
+            .transpose()
+    }
+
+    pub(crate) fn parse<T: FromStr>(&self, key: &str) -> Result<T, String> {
+        self.parse_opt(key)?.ok_or_else(|| format!("{} header lacks `{key}`", self.format))
+    }
+
+    pub(crate) fn parse_or<T: FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        Ok(self.parse_opt(key)?.unwrap_or(default))
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use itertools::iproduct;
+
+use units::{mm, mm_};
+
+use crate::{FOV, Point, image::Image, index::index3_to_1};
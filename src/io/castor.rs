@@ -0,0 +1,350 @@
+//! Read / write LORs as CASToR list-mode PET data
+//!
+//! A list-mode dataset consists of a text header (`.Cdh`) of `key: value`
+//! lines, and a binary file of events (`.Cdf`), all of whose fields are
+//! little-endian. Each event is
+//!
+//! + acquisition time in ms (`u32`), which we do not know, and write as 0
+//! + optional correction factors (`f32` each), which are ignored on reading
+//! + optional TOF difference `t1 - t2` in ps (`f32`)
+//! + either the CASToR ids of the two crystals (`u32` each), or, if the header
+//!   sets `LOR coordinates flag`, the coordinates of both ends of the LOR in mm
+//!   (`x1 y1 z1 x2 y2 z2` as `f32`)
+//!
+//! CASToR also needs a description of the scanner, named in the header and
+//! stored in its `config/scanner` directory. `Scanner::geometry` generates
+//! one from a `Discretize`d detector: every element is a crystal in a ring
+//! of `n_azimuthal` sectors. Crystal ids count anticlockwise (from the
+//! x-axis) around each ring, ring after ring, starting with the ring at the
+//! most negative z.
+
+/// A discretized detector, as described to CASToR
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    pub name: String,
+    pub discretize: Discretize,
+    /// Number of rings of crystals
+    pub n_axial: u32,
+}
+
+impl Scanner {
+
+    pub fn new(name: &str, discretize: Discretize, axial_length: Length) -> Self {
+        Self { name: name.into(), discretize, n_axial: discretize.n_axial(axial_length) }
+    }
+
+    pub fn n_crystals(&self) -> u32 { self.discretize.n_azimuthal() * self.n_axial }
+
+    /// CASToR id of the crystal containing `(x, y, z)`, if there is one
+    pub fn crystal_id(&self, (x, y, z): (Length, Length, Length)) -> Option<u32> {
+        let n_half_axial = (self.n_axial / 2) as i32;
+        let id = self.discretize.crystal_id(x, y, z) + n_half_axial * self.discretize.n_azimuthal() as i32;
+        u32::try_from(id).ok().filter(|&id| id < self.n_crystals())
+    }
+
+    /// Centre of the crystal with CASToR id `id`
+    pub fn crystal_centre(&self, id: u32) -> Result<Point, String> {
+        if id >= self.n_crystals() {
+            return Err(format!("Crystal id {id} out of range: scanner `{}` has {} crystals", self.name, self.n_crystals()))
+        }
+        let n_half_axial = self.n_axial / 2;
+        let id = id as i32 - (n_half_axial * self.discretize.n_azimuthal()) as i32;
+        let (x, y, z) = self.discretize.crystal_centre(id);
+        Ok(Point::new(x, y, z))
+    }
+
+    /// Contents of a CASToR `.geom` file describing the scanner
+    pub fn geometry(&self) -> String {
+        let Discretize { r_min, dr, dz, da, .. } = self.discretize;
+        let (n_azimuthal, n_axial) = (self.discretize.n_azimuthal(), self.n_axial);
+        let fov_axial = n_axial as f32 * mm_(dz);
+        format!(
+            "modality : PET\n\
+             scanner name : {name}\n\
+             description : Discretized petalo detector: {n_azimuthal} sectors x {n_axial} rings\n\
+             number of elements : {n_crystals}\n\
+             number of layers : 1\n\
+             number of crystals in layer : {n_crystals}\n\
+             crystals size depth : {dr}\n\
+             crystals size transaxial : {da}\n\
+             crystals size axial : {dz}\n\
+             scanner radius : {r_min}\n\
+             number of rsectors : {n_azimuthal}\n\
+             number of modules transaxial : 1\n\
+             number of modules axial : 1\n\
+             number of submodules transaxial : 1\n\
+             number of submodules axial : 1\n\
+             number of crystals transaxial : 1\n\
+             number of crystals axial : {n_axial}\n\
+             rsectors first angle : 0\n\
+             voxels number transaxial : 256\n\
+             voxels number axial : {n_axial}\n\
+             field of view transaxial : {fov_transaxial}\n\
+             field of view axial : {fov_axial}\n\
+             mean depth of interaction : -1\n",
+            name = self.name,
+            n_crystals = self.n_crystals(),
+            dr = mm_(dr), da = mm_(da), dz = mm_(dz), r_min = mm_(r_min),
+            fov_transaxial = 2.0 * mm_(r_min),
+        )
+    }
+
+    /// Recover the scanner from the contents of a `.geom` file written by
+    /// `geometry`
+    pub fn from_geometry(text: &str) -> Result<Self, String> {
+        let header = read_header(text);
+        let name = header.required("scanner name")?.to_string();
+        let length = |key| header.parse::<f32>(key).map(mm);
+        let discretize = Discretize::new(
+            length("scanner radius")?,
+            length("crystals size depth")?,
+            length("crystals size axial")?,
+            length("crystals size transaxial")?,
+            Adjust::No,
+        );
+        let scanner = Self { name, discretize, n_axial: header.parse("number of crystals axial")? };
+        let n_rsectors: u32 = header.parse("number of rsectors")?;
+        if n_rsectors != discretize.n_azimuthal() || header.parse::<u32>("number of crystals transaxial")? != 1 {
+            return Err(format!("Geometry of `{}` does not describe a discretized ring of scintillator", scanner.name))
+        }
+        Ok(scanner)
+    }
+}
+
+/// How the ends of the LORs are stored in the events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Events {
+    /// CASToR ids of the crystals containing the ends
+    CrystalIds,
+    /// Coordinates of the ends
+    Coordinates,
+}
+
+/// Write `lors` as list-mode events: the header to `path` and the events next
+/// to it, with extension `.Cdf`. LORs with an end outside of the `scanner`'s
+/// crystals are skipped when writing `CrystalIds`. TOF is written only if a
+/// `tof_resolution` (FWHM) is given.
+///
+/// Returns the number of events written.
+pub fn write(
+    lors: impl IntoIterator<Item = Hdf5Lor>,
+    path: &Path,
+    scanner: &Scanner,
+    events: Events,
+    tof_resolution: Option<Time>,
+) -> Result<usize, Box<dyn Error>> {
+    let data_path = path.with_extension("Cdf");
+    let mut data = BufWriter::new(File::create(&data_path)
+        .map_err(|e| format!("Cannot create {}: {e}", data_path.display()))?);
+    let mut n_events = 0;
+    let mut max_tof: f32 = 0.0;
+    for Hdf5Lor { dt, x1, y1, z1, x2, y2, z2, .. } in lors {
+        let mut event = Vec::with_capacity(32);
+        event.extend(0_u32.to_le_bytes());
+        if tof_resolution.is_some() {
+            let tof = -ps_(ns(dt));
+            max_tof = max_tof.max(tof.abs());
+            event.extend(tof.to_le_bytes());
+        }
+        match events {
+            Events::CrystalIds => {
+                let id = |x, y, z| scanner.crystal_id((mm(x), mm(y), mm(z)));
+                let (Some(c1), Some(c2)) = (id(x1, y1, z1), id(x2, y2, z2)) else { continue };
+                event.extend(c1.to_le_bytes());
+                event.extend(c2.to_le_bytes());
+            },
+            Events::Coordinates => {
+                for c in [x1, y1, z1, x2, y2, z2] { event.extend(c.to_le_bytes()) }
+            },
+        }
+        data.write_all(&event)?;
+        n_events += 1;
+    }
+    data.flush()?;
+
+    let data_name = data_path.file_name().ok_or("Invalid data file name")?.to_string_lossy();
+    let mut header = format!(
+        "Scanner name: {}\n\
+         Data filename: {data_name}\n\
+         Number of events: {n_events}\n\
+         Data mode: list-mode\n\
+         Data type: PET\n\
+         Start time (s): 0\n\
+         Duration (s): 1\n\
+         Calibration factor: 1\n\
+         Isotope: unknown\n",
+        scanner.name,
+    );
+    if let Some(resolution) = tof_resolution {
+        header += &format!(
+            "TOF information flag: 1\n\
+             TOF resolution (ps): {}\n\
+             List TOF measurement range (ps): {}\n",
+            ps_(resolution), 2.0 * max_tof.ceil());
+    }
+    if events == Events::Coordinates { header += "LOR coordinates flag: 1\n" }
+    std::fs::write(path, header)?;
+    Ok(n_events)
+}
+
+/// Read the list-mode events described by the header at `path`. The `scanner`
+/// is needed to place the ends of events given by crystal ids.
+///
+/// The ends of the LORs are the centres of the crystals, or the stored
+/// coordinates. CASToR does not record charges or energies: the charges are
+/// set to `NaN` and the energies to 511 keV.
+pub fn read(path: &Path, scanner: Option<&Scanner>) -> Result<Vec<Hdf5Lor>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read CASToR header {}: {e}", path.display()))?;
+    let header = read_header(&text);
+    for (key, expected) in [("data mode", "list-mode"), ("data type", "PET")] {
+        if let Some(value) = header.get(key) {
+            if !value.eq_ignore_ascii_case(expected) { return Err(format!("Unsupported {key}: {value}").into()) }
+        }
+    }
+    let flag = |key| -> Result<bool, String> { Ok(header.parse_or::<u32>(key, 0)? != 0) };
+    for unsupported in ["POI information flag", "Compression flag"] {
+        if flag(unsupported)? { return Err(format!("Events with `{unsupported}` are not supported").into()) }
+    }
+    let n_corrections = ["Attenuation correction flag", "Scatter correction flag",
+                         "Random correction flag", "Normalization correction flag"]
+        .into_iter()
+        .map(flag)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter().filter(|&f| f).count();
+    let tof = flag("TOF information flag")?;
+    let coordinates = flag("LOR coordinates flag")?;
+    if !coordinates && scanner.is_none() {
+        return Err("A scanner geometry is needed to read events with crystal ids".into())
+    }
+
+    let data_path = path.parent().unwrap_or(Path::new("")).join(header.required("data filename")?);
+    let bytes = std::fs::read(&data_path)
+        .map_err(|e| format!("Cannot read CASToR data {}: {e}", data_path.display()))?;
+    let event_size = 4 * (1 + n_corrections + tof as usize + if coordinates { 6 } else { 2 });
+    let n_events: usize = header.parse("number of events")?;
+    if bytes.len() < n_events * event_size {
+        return Err(format!("{} is too short for the {n_events} events described in its header", data_path.display()).into())
+    }
+
+    let word = |event: &[u8], i: usize| -> [u8; 4] { event[4*i..4*i+4].try_into().unwrap() };
+    let first = 1 + n_corrections; // Skip time and corrections
+    bytes.chunks_exact(event_size).take(n_events)
+        .map(|event| -> Result<Hdf5Lor, Box<dyn Error>> {
+            let f = |i| f32::from_le_bytes(word(event, i));
+            let u = |i| u32::from_le_bytes(word(event, i));
+            let dt = if tof { ns_(-ps(f(first))) } else { 0.0 };
+            let ends = first + tof as usize;
+            let [x1, y1, z1, x2, y2, z2] = match scanner.filter(|_| !coordinates) {
+                None => [0, 1, 2, 3, 4, 5].map(|i| f(ends + i)),
+                Some(scanner) => {
+                    let (p1, p2) = (scanner.crystal_centre(u(ends))?, scanner.crystal_centre(u(ends + 1))?);
+                    [p1.x, p1.y, p1.z, p2.x, p2.y, p2.z].map(mm_)
+                },
+            };
+            Ok(Hdf5Lor { dt, x1, y1, z1, x2, y2, z2, q1: f32::NAN, q2: f32::NAN, E1: 511.0, E2: 511.0 })
+        })
+        .collect()
+}
+
+/// The `key: value` pairs of CASToR headers and geometry files
+fn read_header(text: &str) -> Header {
+    Header::from_text("CASToR", text, ":", '#')
+}
+
+#[cfg(test)]
+mod test_castor {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn scanner() -> Scanner {
+        Scanner::new("petalo_test", Discretize::from_f32s_in_mm(350.0, 20.0, 6.0, 6.0, Adjust::No), mm(60.0))
+    }
+
+    fn lors() -> Vec<Hdf5Lor> {
+        let lor = |dt, x1, y1, z1, x2, y2, z2| Hdf5Lor { dt, x1, y1, z1, x2, y2, z2, q1: 1.0, q2: 1.0, E1: 511.0, E2: 511.0 };
+        vec![
+            lor( 0.1, 360.0,   0.0,   0.0, -360.0,    0.0,  12.0),
+            lor(-0.2,   0.0, 360.0, -30.0,    0.0, -360.0,  24.0),
+            // Outside the axial extent of the scanner
+            lor( 0.0, 360.0,   0.0, 100.0, -360.0,    0.0,   0.0),
+        ]
+    }
+
+    #[test]
+    fn roundtrip_crystal_ids() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.Cdh");
+        let scanner = scanner();
+        let tof_resolution = Some(ps(200.0));
+        let n = write(lors(), &path, &scanner, Events::CrystalIds, tof_resolution)?;
+        assert_eq!(n, 2);
+        assert!(dir.path().join("data.Cdf").exists());
+
+        let recovered = Scanner::from_geometry(&scanner.geometry())?;
+        assert_eq!((recovered.n_crystals(), recovered.n_axial), (scanner.n_crystals(), scanner.n_axial));
+        let recovered_lors = read(&path, Some(&recovered))?;
+        assert_eq!(recovered_lors.len(), 2);
+        for (original, read) in lors().iter().zip(&recovered_lors) {
+            // Ends are moved to the centres of their crystals
+            let r = |x: f32, y: f32| x.hypot(y);
+            assert_float_eq!(r(read.x1, read.y1), 360.0, abs <= 1e-2);
+            assert_float_eq!(read.z1, original.z1, abs <= 3.0);
+            assert_float_eq!(read.z2, original.z2, abs <= 3.0);
+            assert_float_eq!(read.dt, original.dt, abs <= 1e-6);
+        }
+        assert!(read(&path, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_coordinates() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.Cdh");
+        let n = write(lors(), &path, &scanner(), Events::Coordinates, None)?;
+        assert_eq!(n, 3);
+        let recovered_lors = read(&path, None)?;
+        assert_eq!(recovered_lors.len(), 3);
+        for (original, read) in lors().iter().zip(&recovered_lors) {
+            assert_eq!([read.x1, read.y1, read.z1, read.x2, read.y2, read.z2],
+                       [original.x1, original.y1, original.z1, original.x2, original.y2, original.z2]);
+            assert_eq!(read.dt, 0.0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn corrections_are_skipped() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let scanner = scanner();
+        let mut event = Vec::new();
+        event.extend(7_u32.to_le_bytes());   // time
+        event.extend(0.5_f32.to_le_bytes()); // normalization
+        event.extend(250_f32.to_le_bytes()); // TOF
+        event.extend(3_u32.to_le_bytes());
+        event.extend(scanner.n_crystals().saturating_sub(1).to_le_bytes());
+        std::fs::write(dir.path().join("foreign.cdf"), &event)?;
+        std::fs::write(dir.path().join("foreign.cdh"),
+                       "Data filename: foreign.cdf\nNumber of events: 1\n\
+                        Normalization correction flag: 1\nTOF information flag: 1\n")?;
+        let [lor] = <[Hdf5Lor; 1]>::try_from(read(&dir.path().join("foreign.cdh"), Some(&scanner))?).unwrap();
+        assert_float_eq!(lor.dt, -0.25, abs <= 1e-6);
+        assert_float_eq!(lor.z1, -30.0, abs <= 1e-3);
+        assert_float_eq!(lor.z2,  30.0, abs <= 1e-3);
+        Ok(())
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use units::{mm, mm_, ns, ns_, ps, ps_, Length, Time};
+
+use crate::{
+    Point,
+    discrete::{Adjust, Discretize},
+    io::{Header, hdf5::Hdf5Lor},
+};
@@ -0,0 +1,281 @@
+//! Read coincidences written by GATE's ASCII (`.dat`) and binary (`.bin`)
+//! outputs
+//!
+//! Each coincidence consists of two singles, each of which holds
+//!
+//! + run, event and source ids
+//! + source position (mm)
+//! + time (s)
+//! + deposited energy (MeV)
+//! + detection position (mm)
+//! + volume ids: one per level of the system, 6 for a `cylindricalPET`
+//! + numbers of Compton and Rayleigh interactions in the phantom and in the detector
+//! + axial position (mm) and angle (deg) of the scanner
+//!
+//! In ASCII files each coincidence is one line of whitespace-separated
+//! columns, so the number of volume ids is deduced from the number of
+//! columns. In binary files the numbers are native (little-endian) `i32`s
+//! (ids and interaction counts) and `f64`s (everything else), with no
+//! separators, so the number of volume ids must be given.
+
+/// One of the two singles in a GATE coincidence
+#[derive(Debug, Clone, PartialEq)]
+pub struct Single {
+    pub run_id: i32,
+    pub event_id: i32,
+    pub source_id: i32,
+    pub source: [f64; 3],
+    /// Seconds
+    pub time: f64,
+    /// MeV
+    pub energy: f64,
+    pub position: [f64; 3],
+    pub volume_ids: Vec<i32>,
+    pub compton_phantom: i32,
+    pub compton_detector: i32,
+    pub rayleigh_phantom: i32,
+    pub rayleigh_detector: i32,
+    pub scanner_axial: f64,
+    pub scanner_angle: f64,
+}
+
+/// Number of values in a single, excluding the volume ids
+const N_FIXED: usize = 17;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coincidence(pub Single, pub Single);
+
+impl Coincidence {
+
+    /// Singles from different events make randoms; otherwise, a Compton or
+    /// Rayleigh interaction of either photon in the phantom makes a scatter.
+    pub fn truth(&self) -> Truth {
+        let Coincidence(a, b) = self;
+        let scattered = |s: &Single| s.compton_phantom > 0 || s.rayleigh_phantom > 0;
+        if a.event_id != b.event_id || a.run_id != b.run_id { Truth::Random }
+        else if scattered(a) || scattered(b)                { Truth::Scatter }
+        else                                                { Truth::True }
+    }
+}
+
+/// Times (ns) are absolute, so in `f32` they lose precision as the
+/// acquisition goes on; `dt` is calculated before converting to `f32`. Charges,
+/// DOI and crystal ids are unknown.
+impl From<&Coincidence> for ExtendedLor {
+    fn from(coincidence: &Coincidence) -> Self {
+        let Coincidence(a, b) = coincidence;
+        let [x1, y1, z1] = a.position.map(|x| x as f32);
+        let [x2, y2, z2] = b.position.map(|x| x as f32);
+        let ns = |t: f64| (t * 1e9) as f32;
+        Self {
+            event_id: a.event_id as u32,
+            t1: ns(a.time),
+            t2: ns(b.time),
+            dt: ns(b.time - a.time),
+            x1, y1, z1, x2, y2, z2,
+            E1: (a.energy * 1000.0) as f32,
+            E2: (b.energy * 1000.0) as f32,
+            truth: coincidence.truth() as u8,
+            ..Self::unknown()
+        }
+    }
+}
+
+/// Iterate over the coincidences in a GATE ASCII coincidence file
+pub fn read_ascii(path: &Path) -> Result<impl Iterator<Item = Result<Coincidence, Box<dyn Error>>>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+    let path = path.to_path_buf();
+    Ok(BufReader::new(file).lines().enumerate()
+       .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+       .map(move |(n, line)| -> Result<Coincidence, Box<dyn Error>> {
+           Ok(parse_line(&line?).map_err(|e| format!("{}, line {}: {e}", path.display(), n + 1))?)
+       }))
+}
+
+/// Iterate over the coincidences in a GATE binary coincidence file, whose
+/// singles have `n_volume_ids` volume ids
+pub fn read_binary(path: &Path, n_volume_ids: usize) -> Result<impl Iterator<Item = Result<Coincidence, Box<dyn Error>>>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let path = path.to_path_buf();
+    let mut record = vec![0; 2 * binary_single_size(n_volume_ids)];
+    let mut n = 0;
+    Ok(std::iter::from_fn(move || -> Option<Result<Coincidence, Box<dyn Error>>> {
+        // Distinguish the clean end of the file from a truncated record
+        match reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {},
+            Err(e) => return Some(Err(e.into())),
+        }
+        n += 1;
+        if let Err(e) = reader.read_exact(&mut record) {
+            return Some(Err(format!("{}, coincidence {n}: {e}", path.display()).into()))
+        }
+        let mut bytes = Bytes(&record);
+        Some(bytes.single(n_volume_ids)
+             .and_then(|a| Ok(Coincidence(a, bytes.single(n_volume_ids)?)))
+             .map_err(Into::into))
+    }))
+}
+
+fn parse_line(line: &str) -> Result<Coincidence, String> {
+    let columns = line.split_whitespace().collect::<Vec<_>>();
+    if columns.len() % 2 != 0 || columns.len() < 2 * N_FIXED {
+        return Err(format!("Expected two singles of at least {N_FIXED} columns each, found {} columns", columns.len()))
+    }
+    let n_volume_ids = columns.len() / 2 - N_FIXED;
+    let mut columns = Columns(columns.into_iter());
+    Ok(Coincidence(columns.single(n_volume_ids)?, columns.single(n_volume_ids)?))
+}
+
+/// Size in bytes of a single in binary files
+fn binary_single_size(n_volume_ids: usize) -> usize { 4 * (7 + n_volume_ids) + 8 * 10 }
+
+/// Source of the values which make up singles
+trait Fields {
+    fn int  (&mut self) -> Result<i32, String>;
+    fn float(&mut self) -> Result<f64, String>;
+
+    fn single(&mut self, n_volume_ids: usize) -> Result<Single, String> {
+        // Fields are evaluated in the order in which they are written
+        Ok(Single {
+            run_id           : self.int()?,
+            event_id         : self.int()?,
+            source_id        : self.int()?,
+            source           : [self.float()?, self.float()?, self.float()?],
+            time             : self.float()?,
+            energy           : self.float()?,
+            position         : [self.float()?, self.float()?, self.float()?],
+            volume_ids       : (0..n_volume_ids).map(|_| self.int()).collect::<Result<_, _>>()?,
+            compton_phantom  : self.int()?,
+            compton_detector : self.int()?,
+            rayleigh_phantom : self.int()?,
+            rayleigh_detector: self.int()?,
+            scanner_axial    : self.float()?,
+            scanner_angle    : self.float()?,
+        })
+    }
+}
+
+/// The columns of a line in an ASCII file
+struct Columns<'a>(std::vec::IntoIter<&'a str>);
+
+impl Columns<'_> {
+    fn next<T: FromStr>(&mut self) -> Result<T, String> {
+        let column = self.0.next().ok_or("Too few columns")?;
+        column.parse().map_err(|_| format!("Invalid value: {column}"))
+    }
+}
+
+impl Fields for Columns<'_> {
+    fn int  (&mut self) -> Result<i32, String> { self.next() }
+    fn float(&mut self) -> Result<f64, String> { self.next() }
+}
+
+/// The bytes of a record in a binary file
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        if self.0.len() < N { return Err("Record too short".into()) }
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        Ok(head.try_into().unwrap())
+    }
+}
+
+impl Fields for Bytes<'_> {
+    fn int  (&mut self) -> Result<i32, String> { Ok(i32::from_le_bytes(self.take()?)) }
+    fn float(&mut self) -> Result<f64, String> { Ok(f64::from_le_bytes(self.take()?)) }
+}
+
+#[cfg(test)]
+mod test_gate {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn single(event_id: i32, time: f64, x: f64, compton_phantom: i32) -> Single {
+        Single {
+            run_id: 0, event_id, source_id: 0,
+            source: [1.0, 2.0, 3.0],
+            time, energy: 0.511,
+            position: [x, 0.0, 10.0],
+            volume_ids: vec![0, 3, 0, 0, 17, 0],
+            compton_phantom, compton_detector: 1, rayleigh_phantom: 0, rayleigh_detector: 0,
+            scanner_axial: 0.0, scanner_angle: 0.0,
+        }
+    }
+
+    fn coincidences() -> Vec<Coincidence> {
+        vec![
+            Coincidence(single(1, 1.0, 300.0, 0), single(1, 1.0 + 0.2e-9, -300.0, 0)),
+            Coincidence(single(2, 2.0, 300.0, 1), single(2, 2.0            , -300.0, 0)),
+            Coincidence(single(3, 3.0, 300.0, 0), single(4, 3.0 - 1.0e-9, -300.0, 0)),
+        ]
+    }
+
+    fn ascii_line(Coincidence(a, b): &Coincidence) -> String {
+        [a, b].iter()
+            .map(|s| {
+                let mut columns = vec![s.run_id.to_string(), s.event_id.to_string(), s.source_id.to_string()];
+                columns.extend(s.source.iter().map(|x| x.to_string()));
+                columns.extend([s.time, s.energy].iter().map(|x| x.to_string()));
+                columns.extend(s.position.iter().map(|x| x.to_string()));
+                columns.extend(s.volume_ids.iter().map(|x| x.to_string()));
+                columns.extend([s.compton_phantom, s.compton_detector, s.rayleigh_phantom, s.rayleigh_detector].iter().map(|x| x.to_string()));
+                columns.extend([s.scanner_axial, s.scanner_angle].iter().map(|x| x.to_string()));
+                columns.join(" ")
+            })
+            .collect::<Vec<_>>()
+            .join("   ")
+    }
+
+    fn binary_record(Coincidence(a, b): &Coincidence) -> Vec<u8> {
+        let mut bytes = vec![];
+        for s in [a, b] {
+            for i in [s.run_id, s.event_id, s.source_id] { bytes.extend(i.to_le_bytes()) }
+            for x in s.source.iter().chain(&[s.time, s.energy]).chain(&s.position) { bytes.extend(x.to_le_bytes()) }
+            for i in s.volume_ids.iter().chain(&[s.compton_phantom, s.compton_detector, s.rayleigh_phantom, s.rayleigh_detector]) {
+                bytes.extend(i.to_le_bytes())
+            }
+            for x in [s.scanner_axial, s.scanner_angle] { bytes.extend(x.to_le_bytes()) }
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_and_binary() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let (dat, bin) = (dir.path().join("coincidences.dat"), dir.path().join("coincidences.bin"));
+        std::fs::write(&dat, coincidences().iter().map(ascii_line).collect::<Vec<_>>().join("\n") + "\n")?;
+        std::fs::write(&bin, coincidences().iter().flat_map(binary_record).collect::<Vec<_>>())?;
+
+        let ascii  = read_ascii (&dat   )?.collect::<Result<Vec<_>, _>>()?;
+        let binary = read_binary(&bin, 6)?.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ascii , coincidences());
+        assert_eq!(binary, coincidences());
+
+        let lors = ascii.iter().map(ExtendedLor::from).collect::<Vec<_>>();
+        assert_eq!(lors.iter().map(ExtendedLor::truth).collect::<Vec<_>>(),
+                   vec![Truth::True, Truth::Scatter, Truth::Random]);
+        assert_float_eq!(lors[0].dt, 0.2, abs <= 1e-6);
+        assert_float_eq!(lors[2].dt, -1.0, abs <= 1e-6);
+        assert_eq!((lors[0].event_id, lors[0].x2, lors[0].E1), (1, -300.0, 511.0));
+
+        // Truncated records are reported
+        let mut truncated = std::fs::read(&bin)?;
+        truncated.pop();
+        std::fs::write(&bin, truncated)?;
+        assert!(read_binary(&bin, 6)?.last().unwrap().is_err());
+        Ok(())
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::io::hdf5::{ExtendedLor, Truth};
@@ -1,17 +1,19 @@
 /// Read LORs from HDF5 tables
 
 use std::error::Error;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use crate::{
     LOR, Point,
     config::mlem::{Bounds, Config},
     lorogram::{Scattergram, Prompt},
-    utils::timing::Progress
+    utils::{FWHM_PER_SIGMA, timing::Progress},
 };
 
 use rayon::prelude::*;
 
-use ndarray::{s, Array1};
+use itertools::Either;
+use ndarray::Array1;
 
 use units::{mm, mm_, ns, ratio, ratio_, Ratio};
 
@@ -19,51 +21,172 @@ use units::{mm, mm_, ns, ratio, ratio_, Ratio};
 pub fn read_dataset<T: hdf5::H5Type>(filename: &dyn AsRef<Path>, dataset: &str, events: Bounds<usize>) -> hdf5::Result<Array1<T>> {
     let file = ::hdf5::File::open(filename)?;
     let dataset = file.dataset(dataset)?;
+    let n = n_rows(&dataset)?;
     let Bounds { min, max } = events;
-    let data = match (min, max) {
-        (None    , None    ) => dataset.read_slice_1d::<T,_>(s![  ..  ])?,
-        (Some(lo), None    ) => dataset.read_slice_1d::<T,_>(s![lo..  ])?,
-        (None    , Some(hi)) => dataset.read_slice_1d::<T,_>(s![  ..hi])?,
-        (Some(lo), Some(hi)) => dataset.read_slice_1d::<T,_>(s![lo..hi])?,
-     };
-    Ok(data)
-}
-
-// Plan
-// 1. DONE Assume (panic) chunked dataset, iterate over all items in all chunks
-// 2. DONE Add bounds
-// 3. TODO Cater for unchunked version
+    let hi = max.map_or(n, |hi| hi.min(n));
+    let lo = min.map_or(0, |lo| lo.min(hi));
+    read_rows(&dataset, lo..hi)
+}
+
+/// Iterate over the elements of `dataset`, reading them from file in blocks.
+///
+/// Chunked datasets are read a whole number of chunks at a time; contiguous
+/// ones in blocks of similar size. Datasets must be 1-dimensional, or have
+/// extent 1 in all but their first dimension.
+///
+/// A failure to read a block is yielded in place of its elements, and ends the
+/// iteration.
 pub fn iter_dataset<T: hdf5::H5Type>(
     filename: &dyn AsRef<Path>,
     dataset: &str,
     Bounds { min, max }: Bounds<usize>,
-) -> hdf5::Result<Box<dyn Iterator<Item = T>>>
+) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<T>>>>
 {
     let file = ::hdf5::File::open(filename)?;
     let dataset = file.dataset(dataset)?;
+    let n = n_rows(&dataset)?;
+    let hi = max.map_or(n, |hi| hi.min(n));
+    let lo = min.map_or(0, |lo| lo.min(hi));
+
+    let chunk = dataset.chunk().map(|c| c[0]).filter(|&c| c > 0);
+    let block = block_size::<T>(chunk);
+    // Start at a chunk boundary, so that every block covers whole chunks
+    let start = chunk.map_or(lo, |c| (lo / c) * c);
+
+    let elements = (start..hi)
+        .step_by(block)
+        .flat_map(move |b| {
+            let (b, e) = (b.max(lo), (b + block).min(hi));
+            match read_rows::<T>(&dataset, b..e) {
+                Ok(rows) => Either::Left(rows.into_iter().map(Ok)),
+                Err(err) => Either::Right(std::iter::once(Err(
+                    hdf5::Error::from(format!("Failed to read elements {b}..{e} of {}: {err}", dataset.name()))
+                ))),
+            }
+        });
+    Ok(Box::new(until_error(elements)))
+}
+
+/// The items of `results`, up to and including the first error
+fn until_error<T, E>(results: impl Iterator<Item = Result<T, E>>) -> impl Iterator<Item = Result<T, E>> {
+    results.scan(false, |failed, result| {
+        if *failed { return None }
+        *failed = result.is_err();
+        Some(result)
+    })
+}
+
+/// The datasets with the same name in several files, read one after another,
+/// as a single dataset
+pub struct MultiDataset {
+    dataset: String,
+    /// Each file, with the number of elements in its dataset
+    files: Vec<(PathBuf, usize)>,
+}
+
+impl MultiDataset {
 
-    let dataset_shape = dataset.shape();
-    assert_eq!(dataset_shape.len(), 1);              // Assuming 1-D dataset
-    let chunk_dimensions = dataset.chunk().unwrap(); // Assuming dataset is chunked, for now
-    assert_eq!(chunk_dimensions.len(), 1);           // Assuming 1-D chunks
-    let chunk_size = chunk_dimensions[0];
-    let dataset_size = dataset_shape[0];
-
-    let start = min.map_or(0,            |lo| (lo / chunk_size    ) * chunk_size);
-    let skip  = min.map_or(0,            |lo| (lo % chunk_size    )             );
-    let stop  = max.map_or(dataset_size, |hi| (hi / chunk_size + 1) * chunk_size);
-    let take  = max.unwrap_or(dataset_size) - min.unwrap_or(0);
-
-    Ok(Box::new(
-        (start..stop)
-            .step_by(chunk_size)
-            .map(move |n| (n, n+chunk_size))
-            .flat_map(move |(b,e)| {
-                dataset.read_slice_1d::<T, _>(s![b..e]).into_iter().flatten()
+    pub fn open(files: &[PathBuf], dataset: &str) -> hdf5::Result<Self> {
+        let files = files.iter()
+            .map(|file| {
+                let n = n_rows(&::hdf5::File::open(file)?.dataset(dataset)?)?;
+                Ok((file.clone(), n))
             })
-            .skip(skip)
-            .take(take)
-    ))
+            .collect::<hdf5::Result<_>>()?;
+        Ok(Self { dataset: dataset.into(), files })
+    }
+
+    /// Total number of elements in all files
+    pub fn len(&self) -> usize { self.files.iter().map(|(_, n)| n).sum() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// The range of (global) indices selected by `bounds`
+    pub fn range(&self, Bounds { min, max }: &Bounds<usize>) -> Range<usize> {
+        let n = self.len();
+        let hi = max.map_or(n, |hi| hi.min(n));
+        let lo = min.map_or(0, |lo| lo.min(hi));
+        lo..hi
+    }
+
+    /// Iterate over the elements with (global) indices in `range`.
+    /// `on_file(file, n)` is called before reading the `n` elements to be read
+    /// from each file. Iteration ends after the first error.
+    pub fn iter<'a, T: hdf5::H5Type>(
+        &'a self,
+        range: Range<usize>,
+        on_file: impl FnMut(&Path, usize) + 'a,
+    ) -> impl Iterator<Item = hdf5::Result<T>> + 'a {
+        self.iter_with(range, on_file, iter_dataset::<T>)
+    }
+
+    /// Like `iter`, for LOR datasets of any version (see `iter_lors`)
+    pub fn iter_lors<'a, L>(
+        &'a self,
+        range: Range<usize>,
+        on_file: impl FnMut(&Path, usize) + 'a,
+    ) -> impl Iterator<Item = hdf5::Result<L>> + 'a
+    where
+        L: From<Hdf5Lor> + From<ExtendedLor> + 'static,
+    {
+        self.iter_with(range, on_file, iter_lors::<L>)
+    }
+
+    fn iter_with<'a, T: 'a>(
+        &'a self,
+        range: Range<usize>,
+        mut on_file: impl FnMut(&Path, usize) + 'a,
+        read: fn(&dyn AsRef<Path>, &str, Bounds<usize>) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<T>>>>,
+    ) -> impl Iterator<Item = hdf5::Result<T>> + 'a {
+        let offsets = self.files.iter().scan(0, |offset, (_, n)| { let o = *offset; *offset += n; Some(o) });
+        let elements = self.files.iter().zip(offsets)
+            .filter_map(move |((file, n), offset)| {
+                let lo = range.start.max(offset)       - offset;
+                let hi = range.end  .min(offset + n).max(offset) - offset;
+                (lo < hi).then_some((file, lo..hi))
+            })
+            .flat_map(move |(file, Range { start, end })| {
+                on_file(file, end - start);
+                match read(file, &self.dataset, Bounds::new(Some(start), Some(end))) {
+                    Ok(elements) => Either::Left(elements),
+                    Err(e) => Either::Right(std::iter::once(Err(
+                        hdf5::Error::from(format!("Failed to read {} from {}: {e}", self.dataset, file.display()))
+                    ))),
+                }
+            });
+        until_error(elements)
+    }
+}
+
+/// Aim to read about this many bytes at a time in `iter_dataset`
+const BLOCK_BYTES: usize = 1 << 24;
+
+/// Number of elements of `T` to read at a time: a whole number of chunks (if
+/// any) amounting to about `BLOCK_BYTES`
+fn block_size<T>(chunk: Option<usize>) -> usize {
+    let target = (BLOCK_BYTES / std::mem::size_of::<T>().max(1)).max(1);
+    match chunk {
+        Some(chunk) => chunk * (target / chunk).max(1),
+        None        => target,
+    }
+}
+
+/// Number of elements in a dataset which is 1-dimensional, or has extent 1 in
+/// all but its first dimension
+fn n_rows(dataset: &hdf5::Dataset) -> hdf5::Result<usize> {
+    let shape = dataset.shape();
+    match shape.split_first() {
+        Some((&n, rest)) if rest.iter().all(|&d| d == 1) => Ok(n),
+        _ => Err(format!("Dataset {} has shape {shape:?}: expected a 1-dimensional dataset", dataset.name()).into()),
+    }
+}
+
+fn read_rows<T: hdf5::H5Type>(dataset: &hdf5::Dataset, rows: std::ops::Range<usize>) -> hdf5::Result<Array1<T>> {
+    use hdf5::{Hyperslab, SliceOrIndex};
+    if rows.is_empty() { return Ok(Array1::from_vec(vec![])) }
+    let mut slab = vec![SliceOrIndex::from(rows)];
+    slab.resize(dataset.ndim(), SliceOrIndex::Index(0));
+    dataset.read_slice_1d::<T, _>(Hyperslab::from(slab))
 }
 
 /// Fill `scattergram`, with spatial distribution of scatters probabilities
@@ -97,34 +220,35 @@ where
 fn read_hdf5_lors(config: &Config) -> Result<Vec<Hdf5Lor>, Box<dyn Error>> {
     let input = &config.input;
     let z_max = config.detector_full_axial_length.map(|l| mm_(l.dz / 2.0));
-    let total = ::hdf5::File::open(&config.input.file).unwrap()
-        .dataset(&config.input.dataset).unwrap()
-        .shape()[0];
-    let Bounds { min, max } = config.input.events;
-    let to_be_read = max.map_or(total, |max| max.min(total)) -
-                     min.map_or(0    , |min| min.max(0    ));
-
-    let smear_energy = make_smear_energy(config.smear_energy.unwrap().fwhm);
-    let pre_smearing_e_cut = 511.0 * (1.0 - 1.6 * ratio_(config.smear_energy.unwrap().fwhm));
-    println!("Applying pre-cut at {pre_smearing_e_cut} keV, final cut at {:?}", config.input.energy);
-
-    let progress = progress::Progress::new(to_be_read);
+    let lors = MultiDataset::open(&input.files()?, &input.dataset)?;
+    let events = lors.range(&input.events);
+
+    // Energies are only smeared (after a pre-cut) if requested, as in `LorStream`
+    let smear_energy = config.smear_energy.map(|smear| {
+        let pre_smearing_e_cut = 511.0 * (1.0 - 1.6 * ratio_(smear.fwhm));
+        println!("Applying pre-cut at {pre_smearing_e_cut} keV, final cut at {:?}", config.input.energy);
+        (pre_smearing_e_cut, make_smear_energy(smear.fwhm))
+    });
+
+    let progress = progress::Progress::new(events.len());
     // Read LOR data from disk
-    let hdf5_lors: Vec<Hdf5Lor> = {
-        iter_dataset::<Hdf5Lor>(&input.file, &input.dataset, input.events.clone())?
+    let hdf5_lors: Vec<Hdf5Lor> = itertools::process_results(
+        lors.iter_lors::<Hdf5Lor>(events, |file, n| progress.file(file, n)),
+        |lors| lors
             .inspect(|_|  { progress.read() })
             .filter(|&Hdf5Lor{z1, z2, ..}| { z_max.map_or(true, |z| z1.abs() < z && z2.abs() < z) })
             .filter(|&Hdf5Lor{q1, q2, ..}| { input.charge.contains(q1) && input.charge.contains(q2) })
-            .filter(|&Hdf5Lor{E1, E2, ..}| {  pre_smearing_e_cut < E1  &&  pre_smearing_e_cut < E2 })
-            .map(|mut l@Hdf5Lor { E1, E2, .. }| {
+            .filter_map(|mut l@Hdf5Lor { E1, E2, .. }| {
+                let Some((pre_smearing_e_cut, smear_energy)) = &smear_energy else { return Some(l) };
+                if E1 <= *pre_smearing_e_cut || E2 <= *pre_smearing_e_cut { return None }
                 l.E1 = smear_energy(E1);
                 l.E2 = smear_energy(E2);
-                l
+                Some(l)
             })
             .filter(|&Hdf5Lor{E1, E2, ..}| { input.energy.contains(E1) && input.energy.contains(E2) })
             .inspect(|_|  { progress.selected() })
             .collect()
-    };
+    )?;
     progress.done();
     Ok(hdf5_lors)
 }
@@ -166,6 +290,10 @@ mod progress {
                 ));
             }
         }
+        pub (super) fn file(&self, path: &std::path::Path, n: usize) {
+            let i = self.0.lock().unwrap();
+            i.bar.println(format!("Reading {} LORs from {}", group_digits(n), path.display()));
+        }
         pub (super) fn selected(&self) {
             let mut i = self.0.lock().unwrap();
             i.selected += 1;
@@ -193,7 +321,7 @@ pub fn read_lors(config: &Config, mut scattergram: Option<Scattergram>, n_thread
     use crate::utils::group_digits as g;
     progress.done_with_message(&format!("loaded {}", g(hdf5_lors.len())));
 
-    smear_positions(&mut hdf5_lors, config, &mut progress);
+    smear_positions(&mut hdf5_lors, config, &mut progress)?;
 
     // Use LORs to gather statistics about spatial distribution of scatter probability
     if let Some(sgram) = scattergram {
@@ -228,28 +356,62 @@ pub fn read_lors(config: &Config, mut scattergram: Option<Scattergram>, n_thread
 }
 
 fn make_smear_energy(fwhm: Ratio) -> impl Fn(f32) -> f32 {
-    move |e| {
-        use rand_distr::{Normal, Distribution};
-        let fwhm = ratio_(fwhm) * e;
-        let sigma = fwhm / 2.35;
-        let gauss = Normal::new(e, sigma).unwrap();
-        gauss.sample(&mut rand::thread_rng())
-    }
-}
-
-fn smear_positions(hdf5_lors: &mut [Hdf5Lor], config: &Config, progress: &mut Progress) {
-    let file = hdf5::File::open(&config.input.file).unwrap();
-    let dataset = file.dataset("reco_info/lors").unwrap();
-    let get = |attr_name| mm(dataset.attr(attr_name).unwrap()
-        .read_1d::<f32>().unwrap()
-        .as_slice().unwrap()
-        [0]);
-    let discretize = crate::discrete::Discretize {
-        r_min: get("r_min"),
-        dr: get("dr"),
-        dz: get("dz"),
-        da: get("da"),
+    move |e| smear_energy_with(e, fwhm, &mut rand::thread_rng())
+}
+
+fn smear_energy_with(e: f32, fwhm: Ratio, rng: &mut impl rand::Rng) -> f32 {
+    use rand_distr::{Normal, Distribution};
+    let fwhm = ratio_(fwhm) * e;
+    let sigma = fwhm / FWHM_PER_SIGMA;
+    let gauss = Normal::new(e, sigma).unwrap();
+    gauss.sample(rng)
+}
+
+/// Names of the attributes of discretized LOR datasets, which hold the
+/// discretization parameters (mm) of the detector
+const DISCRETIZATION_ATTRS: [&str; 4] = ["r_min", "dr", "dz", "da"];
+
+/// Discretization parameters of the detector which produced the LORs in
+/// `config`'s input files, which must all have the same ones. LORs from
+/// continuous detectors carry none.
+fn read_discretization(config: &Config) -> hdf5::Result<Option<crate::discrete::Discretize>> {
+    let dataset = &config.input.dataset;
+    let mut reference: Option<(PathBuf, Option<[f32; 4]>)> = None;
+    for input in config.input.files()? {
+        let ds = ::hdf5::File::open(&input)
+            .and_then(|file| file.dataset(dataset))
+            .map_err(|e| format!("Cannot read {dataset} from {}: {e}", input.display()))?;
+        let attr_names = ds.attr_names()?;
+        let values = if DISCRETIZATION_ATTRS.iter().all(|name| attr_names.iter().any(|n| n == name)) {
+            let mut values = [0.0; 4];
+            for (value, name) in values.iter_mut().zip(DISCRETIZATION_ATTRS) {
+                *value = *ds.attr(name)?.read_raw::<f32>()?.first()
+                    .ok_or_else(|| format!("Empty attribute `{name}` of {dataset} in {}", input.display()))?;
+            }
+            Some(values)
+        } else { None };
+        match &reference {
+            None => reference = Some((input, values)),
+            Some((first, expected)) => if &values != expected {
+                return Err(format!("Discretization ({DISCRETIZATION_ATTRS:?}) of {dataset} differs between {} ({expected:?}) and {} ({values:?})",
+                                   first.display(), input.display()).into())
+            },
+        }
+    }
+    Ok(reference.and_then(|(_, values)| values).map(|[r_min, dr, dz, da]| crate::discrete::Discretize {
+        r_min: mm(r_min),
+        dr: mm(dr),
+        dz: mm(dz),
+        da: mm(da),
         adjust: crate::discrete::Adjust::RandomZPhi,
+    }))
+}
+
+fn smear_positions(hdf5_lors: &mut [Hdf5Lor], config: &Config, progress: &mut Progress) -> hdf5::Result<()> {
+    // LORs from continuous detectors carry no discretization parameters
+    let Some(discretize) = read_discretization(config)? else {
+        progress.done_with_message("   No discretization attributes: positions not smeared");
+        return Ok(());
     };
     let smear_position = discretize.make_adjust_fn();
     progress.start(&format!("   Smearing position: {discretize:.1?}"));
@@ -262,11 +424,15 @@ fn smear_positions(hdf5_lors: &mut [Hdf5Lor], config: &Config, progress: &mut Pr
     });
 
     progress.done();
+    Ok(())
 }
 
 // Include specific table readers and associated types
+pub mod join;
 pub mod mc;
+pub mod results;
 pub mod sensors;
+pub mod stream;
 
 
 
@@ -320,6 +486,136 @@ impl From<&Hdf5Lor> for LOR {
     }
 }
 
+/// Name of the dataset attribute holding the version of the LOR record layout:
+/// 1 (`Hdf5Lor`, also assumed when the attribute is absent) or 2 (`ExtendedLor`)
+pub const LOR_VERSION_ATTR: &str = "lor_version";
+
+/// Event id of LORs whose event is unknown
+pub const NO_EVENT: u32 = u32::MAX;
+
+/// Crystal id of LOR ends not in a discretized detector
+pub const NO_CRYSTAL: i32 = i32::MIN;
+
+/// Version 2 of the LOR record: `Hdf5Lor`'s fields, plus the event it came
+/// from, absolute times, depth of interaction (DOI) estimates, crystal ids and
+/// MC-truth classification. Unknown values are `NaN`, `NO_EVENT`,
+/// `NO_CRYSTAL` and `Truth::Unknown`.
+///
+/// As it contains all of `Hdf5Lor`'s fields, datasets of `ExtendedLor`s can
+/// also be read as `Hdf5Lor`s.
+#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
+#[repr(C)]
+#[allow(nonstandard_style)]
+pub struct ExtendedLor {
+    pub event_id: u32,
+    /// Times of arrival of the photons at either end (ns)
+    pub t1: f32,
+    pub t2: f32,
+    /// `t2 - t1`, or only the measured difference, if `t1` and `t2` are unknown
+    pub dt: f32,
+    pub x1: f32,
+    pub y1: f32,
+    pub z1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub z2: f32,
+    /// Depth of interaction in the scintillator (mm from its inner surface)
+    pub doi1: f32,
+    pub doi2: f32,
+    /// See `Discretize::crystal_id`
+    pub crystal1: i32,
+    pub crystal2: i32,
+    pub q1: f32,
+    pub q2: f32,
+    pub E1: f32,
+    pub E2: f32,
+    /// `Truth` as `u8`
+    pub truth: u8,
+}
+
+/// Monte Carlo truth classification of a LOR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Truth {
+    Unknown = 0,
+    /// Both photons reached the detector without interacting elsewhere
+    True    = 1,
+    /// At least one photon interacted before reaching the detector
+    Scatter = 2,
+    /// The photons come from different decays
+    Random  = 3,
+}
+
+impl From<u8> for Truth {
+    fn from(truth: u8) -> Self {
+        match truth {
+            1 => Self::True,
+            2 => Self::Scatter,
+            3 => Self::Random,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl ExtendedLor {
+    /// A LOR in which everything is unknown
+    pub fn unknown() -> Self {
+        let nan = f32::NAN;
+        Self {
+            event_id: NO_EVENT, t1: nan, t2: nan, dt: nan,
+            x1: nan, y1: nan, z1: nan, x2: nan, y2: nan, z2: nan,
+            doi1: nan, doi2: nan, crystal1: NO_CRYSTAL, crystal2: NO_CRYSTAL,
+            q1: nan, q2: nan, E1: nan, E2: nan, truth: Truth::Unknown as u8,
+        }
+    }
+
+    pub fn truth(&self) -> Truth { self.truth.into() }
+}
+
+impl From<Hdf5Lor> for ExtendedLor {
+    fn from(Hdf5Lor { dt, x1, y1, z1, x2, y2, z2, q1, q2, E1, E2 }: Hdf5Lor) -> Self {
+        Self { dt, x1, y1, z1, x2, y2, z2, q1, q2, E1, E2, ..Self::unknown() }
+    }
+}
+
+impl From<ExtendedLor> for Hdf5Lor {
+    fn from(ExtendedLor { dt, x1, y1, z1, x2, y2, z2, q1, q2, E1, E2, .. }: ExtendedLor) -> Self {
+        Self { dt, x1, y1, z1, x2, y2, z2, q1, q2, E1, E2 }
+    }
+}
+
+/// Version of the LOR record layout in `dataset`
+pub fn lor_version(dataset: &hdf5::Dataset) -> hdf5::Result<u32> {
+    if dataset.attr_names()?.iter().any(|name| name == LOR_VERSION_ATTR) {
+        dataset.attr(LOR_VERSION_ATTR)?.read_scalar::<u32>()
+    } else {
+        Ok(1)
+    }
+}
+
+/// Record the version of the LOR record layout in `dataset`
+pub fn set_lor_version(dataset: &hdf5::Dataset, version: u32) -> hdf5::Result<()> {
+    dataset.new_attr::<u32>().create(LOR_VERSION_ATTR)?.write_scalar(&version)
+}
+
+/// Like `iter_dataset`, but for LOR datasets of any version, whose records are
+/// converted to `L` (`Hdf5Lor` or `ExtendedLor`)
+pub fn iter_lors<L>(
+    filename: &dyn AsRef<Path>,
+    dataset: &str,
+    events: Bounds<usize>,
+) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<L>>>>
+where
+    L: From<Hdf5Lor> + From<ExtendedLor> + 'static,
+{
+    let version = lor_version(&::hdf5::File::open(filename)?.dataset(dataset)?)?;
+    Ok(match version {
+        1 => Box::new(iter_dataset::<Hdf5Lor    >(filename, dataset, events)?.map(|lor| lor.map(L::from))),
+        2 => Box::new(iter_dataset::<ExtendedLor>(filename, dataset, events)?.map(|lor| lor.map(L::from))),
+        v => return Err(format!("Unknown LOR record version {v} in {dataset}").into()),
+    })
+}
+
 // ----- TESTS ------------------------------------------------------------------------------------------
 #[cfg(test)]
 mod test_chunked {
@@ -360,7 +656,8 @@ mod test_chunked {
         write_chunked(d, c, &file_path).unwrap();
         let read = iter_dataset::<f32>(&file_path, dataset, Bounds { min, max })
             .unwrap()
-            .collect_vec();
+            .collect::<hdf5::Result<Vec<_>>>()
+            .unwrap();
         let expected = expected.map(|n| n as f32).collect_vec();
         println!("{read:?}");
         println!("{expected:?}");
@@ -390,6 +687,135 @@ mod test_chunked {
 
 }
 
+#[cfg(test)]
+mod test_layouts {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::tempdir;
+    use itertools::Itertools;
+    use ndarray::Array2;
+
+    #[rstest(
+        case::contiguous_all  (None   , None    , 0..50),
+        case::contiguous_front(Some(7), None    , 7..50),
+        case::contiguous_both (Some(7), Some(31), 7..31),
+        case::contiguous_over (Some(7), Some(99), 7..50),
+    )]
+    fn contiguous(
+        #[case] min: Option<usize>,
+        #[case] max: Option<usize>,
+        #[case] expected: std::ops::Range<usize>,
+    ) -> hdf5::Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("contiguous.h5");
+        let data = (0..50).map(|i| i as f32).collect_vec();
+        hdf5::File::create(&file_path)?
+            .new_dataset_builder()
+            .with_data(&data)
+            .create("stuff")?;
+        let expected = expected.map(|n| n as f32).collect_vec();
+        let bounds = Bounds { min, max };
+        assert_eq!(iter_dataset::<f32>(&file_path, "stuff", bounds.clone())?.collect::<hdf5::Result<Vec<_>>>()?, expected);
+        assert_eq!(read_dataset::<f32>(&file_path, "stuff", bounds)?.to_vec(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn column() -> hdf5::Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("column.h5");
+        let file = hdf5::File::create(&file_path)?;
+        let column = Array2::from_shape_fn((20, 1), |(i, _)| i as u32);
+        file.new_dataset_builder().with_data(&column).create("column")?;
+        let table = Array2::from_shape_fn((20, 2), |(i, j)| (i + j) as u32);
+        file.new_dataset_builder().with_data(&table).create("table")?;
+
+        let read = iter_dataset::<u32>(&file_path, "column", Bounds::new(Some(3), Some(6)))?.collect::<hdf5::Result<Vec<_>>>()?;
+        assert_eq!(read, vec![3, 4, 5]);
+        assert!(iter_dataset::<u32>(&file_path, "table", Bounds::none()).is_err());
+        assert!(read_dataset::<u32>(&file_path, "table", Bounds::none()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn blocks_are_whole_chunks() {
+        assert_eq!(block_size::<u8>(None), BLOCK_BYTES);
+        assert_eq!(block_size::<u8>(Some(1000)) % 1000, 0);
+        assert_eq!(block_size::<u8>(Some(BLOCK_BYTES * 3)), BLOCK_BYTES * 3);
+    }
+
+    #[test]
+    fn lor_versions() -> hdf5::Result<()> {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("lors.h5");
+        let file = hdf5::File::create(&file_path)?;
+        let v1 = Hdf5Lor { dt: 0.5, x1: 1.0, y1: 2.0, z1: 3.0, x2: 4.0, y2: 5.0, z2: 6.0,
+                           q1: 7.0, q2: 8.0, E1: 511.0, E2: 510.0 };
+        let v2 = ExtendedLor { event_id: 42, t1: 1.0, t2: 1.5, doi1: 3.0, doi2: 4.0, crystal1: 9, crystal2: 10,
+                               truth: Truth::Scatter as u8, ..ExtendedLor::from(v1.clone()) };
+        file.new_dataset_builder().with_data(&[v1.clone()]).create("v1")?;
+        let ds = file.new_dataset_builder().with_data(&[v2.clone()]).create("v2")?;
+        set_lor_version(&ds, 2)?;
+
+        // Version 1 records are extended with unknowns
+        let old = iter_lors::<ExtendedLor>(&file_path, "v1", Bounds::none())?.next().unwrap()?;
+        assert_eq!((old.event_id, old.truth(), old.E1), (NO_EVENT, Truth::Unknown, 511.0));
+        assert!(old.t1.is_nan());
+        assert_eq!(iter_lors::<ExtendedLor>(&file_path, "v2", Bounds::none())?.collect::<hdf5::Result<Vec<_>>>()?, vec![v2]);
+        assert_eq!(iter_lors::<Hdf5Lor    >(&file_path, "v2", Bounds::none())?.collect::<hdf5::Result<Vec<_>>>()?, vec![v1]);
+        Ok(())
+    }
+
+    #[test]
+    fn discretization_must_match_in_all_files() -> hdf5::Result<()> {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, dr: f32| -> hdf5::Result<()> {
+            let ds = hdf5::File::create(dir.path().join(name))?
+                .create_group("lors")?
+                .new_dataset_builder()
+                .with_data(&[0.0_f32])
+                .create("discrete")?;
+            for (attr, value) in DISCRETIZATION_ATTRS.iter().zip([350.0, dr, 3.0, 3.0]) {
+                ds.new_attr::<f32>().shape(1).create(*attr)?.write_raw(&[value])?;
+            }
+            Ok(())
+        };
+        write("a.h5", 20.0)?;
+        write("b.h5", 20.0)?;
+        write("c.h5", 10.0)?;
+        let config = |files: &[&str], dataset: &str| Config {
+            input: crate::config::mlem::Input {
+                files: files.iter().map(|f| dir.path().join(f).display().to_string()).collect(),
+                dataset: dataset.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let discretize = read_discretization(&config(&["a.h5", "b.h5"], "lors/discrete"))?.unwrap();
+        assert_eq!(mm_(discretize.dr), 20.0);
+        assert!(read_discretization(&config(&["a.h5", "c.h5"], "lors/discrete")).is_err());
+        assert!(read_discretization(&config(&["a.h5"], "reco_info/lors")).is_err());
+        assert!(read_discretization(&config(&["missing.h5"], "lors/discrete")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn read_errors_end_iteration() -> hdf5::Result<()> {
+        let dir = tempdir().unwrap();
+        let files = ["a.h5", "b.h5", "c.h5"].map(|name| dir.path().join(name));
+        for file in &files {
+            hdf5::File::create(file)?.new_dataset_builder().with_data(&[1.0_f32, 2.0]).create("stuff")?;
+        }
+        let stuff = MultiDataset::open(&files, "stuff")?;
+        std::fs::remove_file(&files[1]).unwrap();
+        let read = stuff.iter::<f32>(stuff.range(&Bounds::none()), |_, _| {}).collect_vec();
+        assert_eq!(read.len(), 3);
+        assert!(read[..2].iter().all(Result::is_ok));
+        assert!(read[2].is_err());
+        Ok(())
+    }
+}
+
 // Proof of concept: nested compound hdf5 types
 #[allow(nonstandard_style)]
 #[cfg(test)]
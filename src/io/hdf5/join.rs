@@ -0,0 +1,290 @@
+//! Concatenate a dataset stored in several HDF5 files into a single file
+//!
+//! The data are streamed from the inputs into a chunked output dataset, so
+//! they never need to fit in memory. The dataset's attributes (such as the
+//! discretization parameters of LOR datasets) must be the same in all inputs,
+//! and are copied to the output. LOR datasets of either record version can be
+//! mixed: they are converted to the version being written. `common_lor_version`
+//! finds the version to write when the inputs should be kept as they are.
+
+use std::path::Path;
+
+use hdf5::{Attribute, Dataset, types::{FloatSize, IntSize, TypeDescriptor, VarLenAscii, VarLenUnicode}};
+
+use crate::{
+    config::mlem::Bounds,
+    io::hdf5::{ExtendedLor, Hdf5Lor, LOR_VERSION_ATTR, NO_EVENT, iter_dataset, iter_lors, lor_version, set_lor_version, mc::{Primary, Vertex}, sensors::{Qtot, SensorHit}},
+};
+
+/// Types of rows which can be joined. Those belonging to events can have their
+/// event ids offset, to keep them unique across files.
+pub trait Joinable: hdf5::H5Type + Clone + 'static {
+    fn event_id(&mut self) -> Option<&mut u32> { None }
+
+    /// Version of the LOR record layout, for LOR types
+    fn lor_version() -> Option<u32> { None }
+
+    fn rows(input: &Path, dataset: &str) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<Self>>>> {
+        iter_dataset::<Self>(&input, dataset, Bounds::none())
+    }
+}
+
+impl Joinable for Hdf5Lor {
+    fn lor_version() -> Option<u32> { Some(1) }
+    fn rows(input: &Path, dataset: &str) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<Self>>>> {
+        iter_lors::<Self>(&input, dataset, Bounds::none())
+    }
+}
+
+impl Joinable for ExtendedLor {
+    // Unknown events are not offset
+    fn event_id(&mut self) -> Option<&mut u32> {
+        if self.event_id == NO_EVENT { None } else { Some(&mut self.event_id) }
+    }
+    fn lor_version() -> Option<u32> { Some(2) }
+    fn rows(input: &Path, dataset: &str) -> hdf5::Result<Box<dyn Iterator<Item = hdf5::Result<Self>>>> {
+        iter_lors::<Self>(&input, dataset, Bounds::none())
+    }
+}
+
+impl Joinable for Vertex    { fn event_id(&mut self) -> Option<&mut u32> { Some(&mut self.event_id) } }
+impl Joinable for Primary   { fn event_id(&mut self) -> Option<&mut u32> { Some(&mut self.event_id) } }
+impl Joinable for SensorHit { fn event_id(&mut self) -> Option<&mut u32> { Some(&mut self.event_id) } }
+impl Joinable for Qtot      { fn event_id(&mut self) -> Option<&mut u32> { Some(&mut self.event_id) } }
+
+/// Write the concatenation of `dataset` (a path such as `reco_info/lors`) in
+/// all `inputs` to `dataset` in `output`, in chunks of `chunk_size` rows.
+///
+/// If `offset_event_ids` is set, the event ids in each file are increased by
+/// one more than the largest (already offset) event id in the preceding files.
+/// Fails if an offset event id does not fit in a `u32`. Returns the number of
+/// rows written.
+pub fn join<T: Joinable>(
+    inputs: &[impl AsRef<Path>],
+    dataset: &str,
+    output: impl AsRef<Path>,
+    chunk_size: usize,
+    offset_event_ids: bool,
+) -> hdf5::Result<usize> {
+    let attributes = consistent_attributes(inputs, dataset)?;
+
+    let file = hdf5::File::create(output)?;
+    let (group, name) = dataset.rsplit_once('/').unwrap_or(("", dataset));
+    let group = if group.is_empty() { file.group("/")? } else { file.create_group(group)? };
+    let out = group.new_dataset::<T>().chunk(chunk_size.max(1)).shape(0..).create(name)?;
+    for (name, value) in &attributes { value.write(&out, name)?; }
+    if let Some(version) = T::lor_version() { set_lor_version(&out, version)?; }
+
+    let mut written = 0;
+    let mut max_event_id: Option<u32> = None;
+    for input in inputs {
+        let input = input.as_ref();
+        println!("Reading data from {}", input.display());
+        let overflow = || format!("Event ids in {} overflow u32 when offset to follow those in preceding files", input.display());
+        let offset = match max_event_id {
+            Some(max) if offset_event_ids => max.checked_add(1).ok_or_else(overflow)?,
+            _ => 0,
+        };
+        let mut rows = T::rows(input, dataset)?;
+        loop {
+            let mut chunk = rows.by_ref().take(chunk_size.max(1)).collect::<hdf5::Result<Vec<_>>>()?;
+            if chunk.is_empty() { break }
+            for row in &mut chunk {
+                if let Some(id) = row.event_id() {
+                    *id = id.checked_add(offset).ok_or_else(overflow)?;
+                    max_event_id = max_event_id.max(Some(*id));
+                }
+            }
+            out.resize(written + chunk.len())?;
+            out.write_slice(&chunk, written..written + chunk.len())?;
+            written += chunk.len();
+        }
+    }
+    Ok(written)
+}
+
+/// The LOR record version of `dataset` (see `lor_version`), which must be the
+/// same in all `inputs`
+pub fn common_lor_version(inputs: &[impl AsRef<Path>], dataset: &str) -> hdf5::Result<u32> {
+    let mut reference: Option<(&Path, u32)> = None;
+    for input in inputs {
+        let input = input.as_ref();
+        let version = lor_version(&hdf5::File::open(input)?.dataset(dataset)?)?;
+        match reference {
+            None => reference = Some((input, version)),
+            Some((first, expected)) => if version != expected {
+                return Err(format!("LOR record version of {dataset} differs between {} (v{expected}) and {} (v{version}): choose the version to write with `--type`",
+                                   first.display(), input.display()).into())
+            },
+        }
+    }
+    reference.map(|(_, version)| version).ok_or_else(|| "No input files".into())
+}
+
+/// The attributes of `dataset`, which must be identical in all `inputs`. The
+/// LOR record version is excluded, as it is set by the type being written.
+fn consistent_attributes(inputs: &[impl AsRef<Path>], dataset: &str) -> hdf5::Result<Vec<(String, Value)>> {
+    let mut reference: Option<(&Path, Vec<(String, Value)>)> = None;
+    for input in inputs {
+        let input = input.as_ref();
+        let ds = hdf5::File::open(input)?.dataset(dataset)?;
+        let mut attributes = ds.attr_names()?.into_iter()
+            .filter(|name| name != LOR_VERSION_ATTR)
+            .map(|name| {
+                let value = Value::read(&ds.attr(&name)?, &name)?;
+                Ok((name, value))
+            })
+            .collect::<hdf5::Result<Vec<_>>>()?;
+        attributes.sort_by(|a, b| a.0.cmp(&b.0));
+        match &reference {
+            None => reference = Some((input, attributes)),
+            Some((first, expected)) => if &attributes != expected {
+                return Err(format!("Attributes of {dataset} differ between {} and {}:\n{expected:?}\n{attributes:?}",
+                                   first.display(), input.display()).into())
+            },
+        }
+    }
+    Ok(reference.map(|(_, attributes)| attributes).unwrap_or_default())
+}
+
+/// The value of an attribute, of any of the types that we store in attributes
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    F32(Vec<f32>, Vec<usize>),
+    F64(Vec<f64>, Vec<usize>),
+    I8 (Vec<i8 >, Vec<usize>),
+    I16(Vec<i16>, Vec<usize>),
+    I32(Vec<i32>, Vec<usize>),
+    I64(Vec<i64>, Vec<usize>),
+    U8 (Vec<u8 >, Vec<usize>),
+    U16(Vec<u16>, Vec<usize>),
+    U32(Vec<u32>, Vec<usize>),
+    U64(Vec<u64>, Vec<usize>),
+    Unicode(String),
+    Ascii(String),
+}
+
+impl Value {
+
+    fn read(attr: &Attribute, name: &str) -> hdf5::Result<Self> {
+        use TypeDescriptor as D;
+        let shape = attr.shape();
+        Ok(match attr.dtype()?.to_descriptor()? {
+            D::Float   (FloatSize::U4) => Self::F32(attr.read_raw()?, shape),
+            D::Float   (FloatSize::U8) => Self::F64(attr.read_raw()?, shape),
+            D::Integer (IntSize  ::U1) => Self::I8 (attr.read_raw()?, shape),
+            D::Integer (IntSize  ::U2) => Self::I16(attr.read_raw()?, shape),
+            D::Integer (IntSize  ::U4) => Self::I32(attr.read_raw()?, shape),
+            D::Integer (IntSize  ::U8) => Self::I64(attr.read_raw()?, shape),
+            D::Unsigned(IntSize  ::U1) => Self::U8 (attr.read_raw()?, shape),
+            D::Unsigned(IntSize  ::U2) => Self::U16(attr.read_raw()?, shape),
+            D::Unsigned(IntSize  ::U4) => Self::U32(attr.read_raw()?, shape),
+            D::Unsigned(IntSize  ::U8) => Self::U64(attr.read_raw()?, shape),
+            D::VarLenUnicode => Self::Unicode(attr.read_scalar::<VarLenUnicode>()?.as_str().into()),
+            D::VarLenAscii   => Self::Ascii  (attr.read_scalar::<VarLenAscii  >()?.as_str().into()),
+            other => return Err(format!("Cannot copy attribute `{name}` of type {other:?}").into()),
+        })
+    }
+
+    fn write(&self, dataset: &Dataset, name: &str) -> hdf5::Result<()> {
+        fn numeric<T: hdf5::H5Type>(dataset: &Dataset, name: &str, data: &[T], shape: &[usize]) -> hdf5::Result<()> {
+            let builder = dataset.new_attr::<T>();
+            // An empty shape means a scalar attribute
+            let builder = if shape.is_empty() { builder } else { builder.shape(shape) };
+            builder.create(name)?.write_raw(data)
+        }
+        match self {
+            Self::F32(data, shape) => numeric(dataset, name, data, shape),
+            Self::F64(data, shape) => numeric(dataset, name, data, shape),
+            Self::I8 (data, shape) => numeric(dataset, name, data, shape),
+            Self::I16(data, shape) => numeric(dataset, name, data, shape),
+            Self::I32(data, shape) => numeric(dataset, name, data, shape),
+            Self::I64(data, shape) => numeric(dataset, name, data, shape),
+            Self::U8 (data, shape) => numeric(dataset, name, data, shape),
+            Self::U16(data, shape) => numeric(dataset, name, data, shape),
+            Self::U32(data, shape) => numeric(dataset, name, data, shape),
+            Self::U64(data, shape) => numeric(dataset, name, data, shape),
+            Self::Unicode(text) => {
+                let text: VarLenUnicode = text.parse().map_err(|e| format!("Attribute `{name}`: {e}"))?;
+                dataset.new_attr::<VarLenUnicode>().create(name)?.write_scalar(&text)
+            },
+            Self::Ascii(text) => {
+                let text: VarLenAscii = text.parse().map_err(|e| format!("Attribute `{name}`: {e}"))?;
+                dataset.new_attr::<VarLenAscii>().create(name)?.write_scalar(&text)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::hdf5::read_dataset;
+
+    fn write_hits(path: &Path, event_ids: &[u32], dr: f32) -> hdf5::Result<()> {
+        let hits = event_ids.iter()
+            .map(|&event_id| SensorHit { event_id, sensor_id: 7, time: 1.5 })
+            .collect::<Vec<_>>();
+        let ds = hdf5::File::create(path)?
+            .create_group("MC")?
+            .new_dataset_builder()
+            .with_data(&hits)
+            .create("waveform")?;
+        ds.new_attr::<f32>().shape(1).create("dr")?.write_raw(&[dr])?;
+        ds.new_attr::<u8 >().create("flag" )?.write_scalar(&1_u8)?;
+        ds.new_attr::<i16>().create("shift")?.write_scalar(&-2_i16)?;
+        Ok(())
+    }
+
+    #[test]
+    fn join_offset_and_copy_attributes() -> hdf5::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (dir.path().join("a.h5"), dir.path().join("b.h5"), dir.path().join("c.h5"));
+        write_hits(&a, &[0, 0, 1, 3], 10.0)?;
+        write_hits(&b, &[0, 2, 2], 10.0)?;
+        write_hits(&c, &[5], 20.0)?;
+
+        let out = dir.path().join("joined.h5");
+        let n = join::<SensorHit>(&[&a, &b], "MC/waveform", &out, 2, true)?;
+        assert_eq!(n, 7);
+        let joined = read_dataset::<SensorHit>(&out, "MC/waveform", Bounds::none())?;
+        let ids = joined.iter().map(|h| h.event_id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 0, 1, 3, 4, 6, 6]);
+
+        let file = hdf5::File::open(&out)?;
+        let ds = file.dataset("MC/waveform")?;
+        assert_eq!(ds.chunk(), Some(vec![2]));
+        assert_eq!(ds.attr("dr")?.read_raw::<f32>()?, vec![10.0]);
+        // Integer attributes keep their size and signedness
+        assert_eq!(ds.attr("flag" )?.dtype()?.to_descriptor()?, TypeDescriptor::Unsigned(IntSize::U1));
+        assert_eq!(ds.attr("shift")?.dtype()?.to_descriptor()?, TypeDescriptor::Integer (IntSize::U2));
+        assert_eq!(ds.attr("shift")?.read_scalar::<i16>()?, -2);
+
+        // Inconsistent attributes are rejected
+        assert!(join::<SensorHit>(&[&a, &c], "MC/waveform", dir.path().join("bad.h5"), 2, false).is_err());
+
+        // Offsets which overflow the event ids are rejected
+        let d = dir.path().join("d.h5");
+        write_hits(&d, &[u32::MAX - 1], 10.0)?;
+        assert!(join::<SensorHit>(&[&a, &d], "MC/waveform", dir.path().join("overflow.h5"), 2, true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lor_versions_must_match_unless_chosen() -> hdf5::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let (v1, v2) = (dir.path().join("v1.h5"), dir.path().join("v2.h5"));
+        let lor = Hdf5Lor { dt: 0.0, x1: 1.0, y1: 2.0, z1: 3.0, x2: 4.0, y2: 5.0, z2: 6.0,
+                            q1: 7.0, q2: 8.0, E1: 511.0, E2: 510.0 };
+        let group = hdf5::File::create(&v1)?.create_group("reco_info")?;
+        group.new_dataset_builder().with_data(&[lor.clone()]).create("lors")?;
+        let group = hdf5::File::create(&v2)?.create_group("reco_info")?;
+        let ds = group.new_dataset_builder().with_data(&[ExtendedLor::from(lor)]).create("lors")?;
+        set_lor_version(&ds, 2)?;
+
+        assert_eq!(common_lor_version(&[&v2, &v2], "reco_info/lors")?, 2);
+        assert_eq!(common_lor_version(&[&v1], "reco_info/lors")?, 1);
+        assert!(common_lor_version(&[&v1, &v2], "reco_info/lors").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! A single HDF5 file holding the images saved during a reconstruction
+//!
+//! + `images`: chunked, extensible dataset (iteration x z x y x x), with the
+//!   FOV (`n`, `half_width`, `voxel_size` and `centre` in mm) as attributes
+//! + `iteration`, `subset`: which OSEM (sub-)iteration produced each image
+//! + `duration`: seconds spent producing each image
+//! + `elapsed`: seconds since the start of the reconstruction
+//! + `log_likelihood`: Poisson log-likelihood of the data given each image
+//! + `sensitivity`: the sensitivity image (if any) used in the reconstruction
+//! + `config` (attribute of the file): the reconstruction's configuration
+
+use std::path::Path;
+
+use hdf5::{Dataset, File, types::VarLenUnicode};
+use ndarray::{s, ArrayView3};
+use units::mm_;
+
+use crate::{FOV, image::Image, mlem::Osem};
+
+pub struct Results {
+    images        : Dataset,
+    iteration     : Dataset,
+    subset        : Dataset,
+    duration      : Dataset,
+    elapsed       : Dataset,
+    log_likelihood: Dataset,
+    n_written     : usize,
+}
+
+/// Measurements accompanying each image written to `Results`
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub osem: Osem,
+    pub duration: f64,
+    pub elapsed: f64,
+    pub log_likelihood: f64,
+}
+
+impl Results {
+
+    pub fn create(path: impl AsRef<Path>, fov: FOV, config: &str, sensitivity: Option<&Image>) -> hdf5::Result<Self> {
+        let file = File::create(path)?;
+        let [nx, ny, nz] = fov.n;
+
+        let config: VarLenUnicode = config.parse().map_err(|e| format!("Config cannot be stored in HDF5: {e}"))?;
+        file.new_attr::<VarLenUnicode>().create("config")?.write_scalar(&config)?;
+
+        let images = file
+            .new_dataset::<f32>()
+            .chunk((1, nz, ny, nx))
+            .shape((0.., nz, ny, nx))
+            .create("images")?;
+        let (hw, vs, c) = (fov.half_width, fov.voxel_size, fov.centre);
+        for (name, value) in [
+            ("half_width", [mm_(hw.x), mm_(hw.y), mm_(hw.z)]),
+            ("voxel_size", [mm_(vs.x), mm_(vs.y), mm_(vs.z)]),
+            ("centre"    , [mm_( c.x), mm_( c.y), mm_( c.z)]),
+        ] {
+            images.new_attr_builder().with_data(&value).create(name)?;
+        }
+        images.new_attr_builder().with_data(&[nx as u64, ny as u64, nz as u64]).create("n")?;
+
+        if let Some(sensitivity) = sensitivity {
+            file.new_dataset_builder()
+                .with_data(&view(sensitivity))
+                .create("sensitivity")?;
+        }
+
+        let extensible = |name| file.new_dataset::<f64>().chunk(64).shape(0..).create(name);
+        let counter    = |name| file.new_dataset::<u32>().chunk(64).shape(0..).create(name);
+        Ok(Self {
+            images,
+            iteration     : counter("iteration")?,
+            subset        : counter("subset")?,
+            duration      : extensible("duration")?,
+            elapsed       : extensible("elapsed")?,
+            log_likelihood: extensible("log_likelihood")?,
+            n_written     : 0,
+        })
+    }
+
+    /// Append `image` and its accompanying measurements to the file
+    pub fn write(&mut self, image: &Image, record: Record) -> hdf5::Result<()> {
+        let Record { osem: Osem { iteration, subset, .. }, duration, elapsed, log_likelihood } = record;
+        let n = self.n_written;
+        let [nx, ny, nz] = image.fov.n;
+        self.images.resize((n + 1, nz, ny, nx))?;
+        self.images.write_slice(&view(image), s![n, .., .., ..])?;
+        append(&self.iteration     , iteration as u32, n)?;
+        append(&self.subset        , subset    as u32, n)?;
+        append(&self.duration      , duration        , n)?;
+        append(&self.elapsed       , elapsed         , n)?;
+        append(&self.log_likelihood, log_likelihood  , n)?;
+        self.images.file()?.flush()?;
+        self.n_written += 1;
+        Ok(())
+    }
+}
+
+/// The image's data (x varies fastest) as a z x y x x array
+fn view(image: &Image) -> ArrayView3<'_, f32> {
+    let [nx, ny, nz] = image.fov.n;
+    ArrayView3::from_shape((nz, ny, nx), &image.data).unwrap()
+}
+
+fn append<T: hdf5::H5Type>(dataset: &Dataset, value: T, n: usize) -> hdf5::Result<()> {
+    dataset.resize(n + 1)?;
+    dataset.write_slice(&[value], n..n + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use units::mm;
+
+    #[test]
+    fn roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let fov = FOV::new((mm(30.0), mm(20.0), mm(10.0)), (3, 2, 1));
+        let image = |k: f32| Image::new(fov, (0..6).map(|i| i as f32 * k).collect());
+        let osem = |iteration, subset| Osem { n_subsets: 2, iteration, subset };
+        let record = |osem, t| Record { osem, duration: t, elapsed: 2.0 * t, log_likelihood: -t };
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("mlem.h5");
+        {
+            let mut results = Results::create(&path, fov, "[iterations]\nnumber = 1", Some(&image(3.0)))?;
+            results.write(&image(1.0), record(osem(1, 1), 1.5))?;
+            results.write(&image(2.0), record(osem(1, 2), 2.5))?;
+        }
+
+        let file = File::open(&path)?;
+        let images = file.dataset("images")?;
+        assert_eq!(images.shape(), vec![2, 1, 2, 3]);
+        let images = images.read_dyn::<f32>()?;
+        assert_eq!(images.iter().copied().collect::<Vec<_>>(),
+                   image(1.0).data.into_iter().chain(image(2.0).data).collect::<Vec<_>>());
+        assert_eq!(file.dataset("images")?.attr("n")?.read_raw::<u64>()?, vec![3, 2, 1]);
+        assert_eq!(file.dataset("images")?.attr("half_width")?.read_raw::<f32>()?, vec![15.0, 10.0, 5.0]);
+        assert_eq!(file.dataset("iteration")?.read_raw::<u32>()?, vec![1, 1]);
+        assert_eq!(file.dataset("subset"   )?.read_raw::<u32>()?, vec![1, 2]);
+        assert_eq!(file.dataset("elapsed"  )?.read_raw::<f64>()?, vec![3.0, 5.0]);
+        assert_eq!(file.dataset("sensitivity")?.read_raw::<f32>()?, image(3.0).data);
+        let config = file.attr("config")?.read_scalar::<VarLenUnicode>()?;
+        assert_eq!(config.as_str(), "[iterations]\nnumber = 1");
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Read LORs from HDF5 in bounded memory, one chunk of events at a time
+//!
+//! Unlike `read_lors`, which selects and smears the LORs once and keeps them
+//! all in memory, `LorStream` re-reads them every time they are needed. In
+//! order for every reading to produce the same LORs, the random numbers used in
+//! smearing are seeded by the event's position in the input dataset.
+
+use std::path::Path;
+
+use rand::{SeedableRng, rngs::StdRng};
+use rayon::prelude::*;
+
+use units::{mm, mm_, Ratio};
+
+use crate::{
+    LOR,
+    config::mlem::{Bounds, Config},
+    discrete::Discretize,
+    lorogram::Scattergram,
+};
+
+use super::{Hdf5Lor, MultiDataset, fill_scattergram, read_discretization, smear_energy_with};
+
+pub struct LorStream {
+    lors: MultiDataset,
+    /// Called with each file, and the number of events to be read from it,
+    /// whenever reading from it starts
+    on_file: Box<dyn Fn(&Path, usize) + Send + Sync>,
+    /// Range of events (indices in the concatenation of the input files) to be read
+    events: std::ops::Range<usize>,
+    chunk_size: usize,
+    selection: Selection,
+    scattergram: Option<Scattergram>,
+}
+
+impl LorStream {
+
+    pub fn new(config: &Config, chunk_size: usize, seed: u64) -> hdf5::Result<Self> {
+        let lors = MultiDataset::open(&config.input.files()?, &config.input.dataset)?;
+        let events = lors.range(&config.input.events);
+        Ok(Self {
+            lors,
+            on_file: Box::new(|_, _| {}),
+            events,
+            chunk_size: chunk_size.max(1),
+            selection: Selection::new(config, seed)?,
+            scattergram: None,
+        })
+    }
+
+    /// Report, with `on_file(file, n)`, the `n` events to be read from each
+    /// file, every time the stream starts reading it
+    pub fn report_files(&mut self, on_file: impl Fn(&Path, usize) + Send + Sync + 'static) {
+        self.on_file = Box::new(on_file);
+    }
+
+    /// The energy cut (keV) applied before smearing energies, if they are smeared
+    pub fn pre_smearing_energy_cut(&self) -> Option<f32> {
+        self.selection.smear_energy.map(|(cut, _)| cut)
+    }
+
+    /// Fill `scattergram` in one pass over all events, and use it to set the
+    /// additive correction of the LORs subsequently produced by the stream
+    pub fn fill_scattergram(&mut self, scattergram: Scattergram, n_threads: usize) -> hdf5::Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n_threads).build()
+            .map_err(|e| e.to_string())?;
+        let mut filled = scattergram.clone();
+        for lors in self.hdf5_chunks(self.events.clone()) {
+            let lors = lors?;
+            let job_size = (lors.len() / n_threads).max(1);
+            filled += &pool.install(|| fill_scattergram(scattergram.clone(), &lors, job_size));
+        }
+        self.scattergram = Some(filled);
+        Ok(())
+    }
+
+    /// All LORs in the stream, in chunks. Iteration ends after the first error.
+    pub fn chunks(&self) -> impl Iterator<Item = hdf5::Result<Vec<LOR>>> + '_ {
+        self.subset(1, 1)
+    }
+
+    /// LORs in the `subset`th (counting from 1) of `n_subsets` equal ranges of
+    /// events, in chunks. As in `mlem`, any remainder events are ignored.
+    /// Iteration ends after the first error.
+    pub fn subset(&self, subset: usize, n_subsets: usize) -> impl Iterator<Item = hdf5::Result<Vec<LOR>>> + '_ {
+        let size = self.events.len() / n_subsets;
+        let lo = self.events.start + (subset - 1) * size;
+        self.hdf5_chunks(lo..lo + size).map(|lors| lors.map(|lors| self.to_lors(lors)))
+    }
+
+    /// The selected and smeared `Hdf5Lor`s in `events`, in chunks
+    fn hdf5_chunks(&self, events: std::ops::Range<usize>) -> impl Iterator<Item = hdf5::Result<Vec<Hdf5Lor>>> + '_ {
+        let first = events.start;
+        let mut raw = self.lors.iter_lors::<Hdf5Lor>(events, |file, n| (self.on_file)(file, n)).zip(first..);
+        let chunk_size = self.chunk_size;
+        std::iter::from_fn(move || {
+            let chunk = raw.by_ref().take(chunk_size)
+                .map(|(lor, event)| lor.map(|lor| (lor, event)))
+                .collect::<hdf5::Result<Vec<_>>>();
+            match chunk {
+                Ok(chunk) if chunk.is_empty() => None,
+                Ok(chunk) => Some(Ok(chunk.into_par_iter()
+                                     .filter_map(|(lor, event)| self.selection.apply(lor, event))
+                                     .collect())),
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
+
+    fn to_lors(&self, lors: Vec<Hdf5Lor>) -> Vec<LOR> {
+        lors.into_par_iter()
+            .map(|hdf5_lor| {
+                let mut lor = LOR::from(hdf5_lor);
+                if let Some(scattergram) = &self.scattergram {
+                    lor.additive_correction = scattergram.value(&lor);
+                }
+                lor
+            })
+            .collect()
+    }
+}
+
+/// The cuts and smearing applied by `read_lors`, but with per-event random
+/// numbers
+struct Selection {
+    z_max: Option<f32>,
+    charge: Bounds<f32>,
+    energy: Bounds<f32>,
+    /// Pre-smearing energy cut and FWHM of energy smearing
+    smear_energy: Option<(f32, Ratio)>,
+    discretize: Option<Discretize>,
+    seed: u64,
+}
+
+impl Selection {
+
+    fn new(config: &Config, seed: u64) -> hdf5::Result<Self> {
+        let smear_energy = config.smear_energy.map(|smear| {
+            let pre_smearing_e_cut = 511.0 * (1.0 - 1.6 * units::ratio_(smear.fwhm));
+            (pre_smearing_e_cut, smear.fwhm)
+        });
+        Ok(Self {
+            z_max: config.detector_full_axial_length.map(|l| mm_(l.dz / 2.0)),
+            charge: config.input.charge.clone(),
+            energy: config.input.energy.clone(),
+            smear_energy,
+            discretize: read_discretization(config)?,
+            seed,
+        })
+    }
+
+    /// The smeared `lor`, if it passes all the cuts
+    fn apply(&self, mut lor: Hdf5Lor, event: usize) -> Option<Hdf5Lor> {
+        let Hdf5Lor { z1, z2, q1, q2, .. } = lor;
+        if let Some(z) = self.z_max { if z1.abs() >= z || z2.abs() >= z { return None } }
+        if !(self.charge.contains(q1) && self.charge.contains(q2)) { return None }
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (event as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        if let Some((pre_cut, fwhm)) = self.smear_energy {
+            if lor.E1 <= pre_cut || lor.E2 <= pre_cut { return None }
+            lor.E1 = smear_energy_with(lor.E1, fwhm, &mut rng);
+            lor.E2 = smear_energy_with(lor.E2, fwhm, &mut rng);
+        }
+        if !(self.energy.contains(lor.E1) && self.energy.contains(lor.E2)) { return None }
+        if let Some(discretize) = self.discretize {
+            let Hdf5Lor { x1, y1, z1, x2, y2, z2, .. } = &mut lor;
+            let (x,y,z) = discretize.adjust_with_rng((mm(*x1), mm(*y1), mm(*z1)), &mut rng);
+            (*x1, *y1, *z1) = (mm_(x), mm_(y), mm_(z));
+            let (x,y,z) = discretize.adjust_with_rng((mm(*x2), mm(*y2), mm(*z2)), &mut rng);
+            (*x2, *y2, *z2) = (mm_(x), mm_(y), mm_(z));
+        }
+        Some(lor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use units::ratio;
+
+    #[test]
+    fn same_lors_every_time() -> Result<(), Box<dyn std::error::Error>> {
+        let lors = (0..100)
+            .map(|i| {
+                let x = i as f32;
+                Hdf5Lor { dt: 0.0, x1: x, y1: 300.0, z1: 10.0, x2: -x, y2: -300.0, z2: -10.0,
+                          q1: 1000.0, q2: 1000.0, E1: 511.0, E2: 511.0 - x / 10.0 }
+            })
+            .collect::<Vec<_>>();
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("lors.h5");
+        hdf5::File::create(&file)?
+            .create_group("reco_info")?
+            .new_dataset_builder()
+            .chunk(16)
+            .with_data(&lors)
+            .create("lors")?;
+
+        let mut config: Config = toml::from_str(&format!(r#"
+            [input]
+            file = "{}"
+            dataset = "reco_info/lors"
+            energy = {{ min = 500.0 }}
+            events = {{ min = 10, max = 90 }}
+        "#, file.display()))?;
+        config.smear_energy = Some(crate::config::mlem::SmearEnergy { fwhm: ratio(0.05) });
+
+        let mut stream = LorStream::new(&config, 7, 1)?;
+        let reported = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let report = reported.clone();
+        stream.report_files(move |_, n| report.lock().unwrap().push(n));
+        let all = |stream: &LorStream| stream.chunks().map(Result::unwrap).flatten().map(|l| l.p1.x).collect::<Vec<_>>();
+        // Smearing is random, but repeatable
+        let first = all(&stream);
+        assert_eq!(first, all(&stream));
+        assert_eq!(*reported.lock().unwrap(), vec![80, 80]);
+        assert!(0 < first.len() && first.len() < 80);
+        // The subsets partition the events
+        let subsets = (1..=4)
+            .flat_map(|s| stream.subset(s, 4).map(Result::unwrap).flatten().map(|l| l.p1.x).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(first, subsets);
+        // A different seed smears differently
+        assert_ne!(first, all(&LorStream::new(&config, 7, 2)?));
+        Ok(())
+    }
+}
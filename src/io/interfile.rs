@@ -0,0 +1,182 @@
+//! Read / write images as Interfile 3.3, as used by STIR and CASToR
+//!
+//! An image consists of a text header (`.hv`) and a separate file of voxel
+//! values (written by `io::raw::write` as little-endian `f32`, with extension
+//! `.v`). Matrix axes 1, 2 and 3 are taken to be our x, y and z.
+//!
+//! The position of the image is given by `first pixel offset (mm)`: the centre
+//! of the first voxel. Images without it are centred on the origin.
+
+/// Write the header to `path` and voxel values next to it, with extension `.v`
+pub fn write(image: &Image, path: &Path) -> Result<(), Box<dyn Error>> {
+    let data_path = path.with_extension("v");
+    raw::write(image.data.iter().copied(), &data_path)?;
+    let data_name = data_path.file_name().ok_or("Invalid data file name")?.to_string_lossy();
+    std::fs::write(path, header(&image.fov, &data_name))?;
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Image, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read Interfile header {}: {e}", path.display()))?;
+    let header = read_header(&text)?;
+    let data_path = path.parent().unwrap_or(Path::new("")).join(header.required("name of data file")?);
+
+    let n_dims: usize = header.parse_or("number of dimensions", 3)?;
+    if !(1..=4).contains(&n_dims) { return Err(format!("Unsupported number of dimensions: {n_dims}").into()) }
+    let sizes = (1..=n_dims)
+        .map(|i| header.parse::<usize>(&format!("matrix size [{i}]")))
+        .collect::<Result<Vec<_>, _>>()?;
+    if sizes.iter().skip(3).any(|&s| s > 1) { return Err(format!("Only 3D images are supported, got matrix size {sizes:?}").into()) }
+    if header.parse_or("number of time frames", 1)? > 1_usize { return Err("Images with several time frames are not supported".into()) }
+    let n = [0, 1, 2].map(|i| sizes.get(i).copied().unwrap_or(1));
+
+    // Voxel size and centre of the first voxel along each axis
+    let axes = (0..3)
+        .map(|i| -> Result<(f32, f32), String> {
+            let step: f32 = header.parse_or(&format!("scaling factor (mm/pixel) [{}]", i + 1), 1.0)?;
+            let first = header.parse_opt(&format!("first pixel offset (mm) [{}]", i + 1))?
+                .unwrap_or(-step * (n[i] as f32 - 1.0) / 2.0);
+            Ok((step, first))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let step  = [0, 1, 2].map(|i| axes[i].0);
+    let first = [0, 1, 2].map(|i| axes[i].1);
+
+    let bytes_per_pixel: usize = header.parse_or("number of bytes per pixel", 4)?;
+    let format = header.get("number format").unwrap_or("float").to_lowercase();
+    let voxel_type = match (format.as_str(), bytes_per_pixel) {
+        ("float" | "short float", 4) => VoxelType::F32,
+        ("float" | "long float" , 8) => VoxelType::F64,
+        ("signed integer"  , 1) => VoxelType::I8,
+        ("signed integer"  , 2) => VoxelType::I16,
+        ("signed integer"  , 4) => VoxelType::I32,
+        ("unsigned integer", 1) => VoxelType::U8,
+        ("unsigned integer", 2) => VoxelType::U16,
+        ("unsigned integer", 4) => VoxelType::U32,
+        _ => return Err(format!("Unsupported number format: {format}, {bytes_per_pixel} bytes per pixel").into()),
+    };
+    let little_endian = match header.get("imagedata byte order").map(str::to_lowercase).as_deref() {
+        Some("littleendian")         => true,
+        Some("bigendian") | None     => false, // Interfile's default
+        Some(order) => return Err(format!("Unknown byte order: {order}").into()),
+    };
+    let offset: usize = header.parse_or("data offset in bytes [1]", 0)?;
+    let scale: f32 = header.parse_or("image scaling factor [1]", 1.0)?;
+
+    let bytes = std::fs::read(&data_path)
+        .map_err(|e| format!("Cannot read Interfile data {}: {e}", data_path.display()))?;
+    let n_bytes = n.iter().product::<usize>() * voxel_type.size();
+    let data = bytes.get(offset..offset + n_bytes)
+        .ok_or_else(|| format!("{} is too short for the image described in its header", data_path.display()))?;
+    let values = voxel_type.decode(data, little_endian).into_iter().map(|v| v * scale).collect::<Vec<_>>();
+    Ok(axis_aligned_image(n, step, first, &values))
+}
+
+fn header(fov: &FOV, data_file: &str) -> String {
+    let [nx, ny, nz] = fov.n;
+    let d = [0, 1, 2].map(|i| mm_(fov.voxel_size[i]));
+    let o = first_voxel_centre(fov);
+    let axis = |i: usize, label: &str, n: usize| format!(
+        "matrix axis label [{i}] := {label}\n\
+         !matrix size [{i}] := {n}\n\
+         scaling factor (mm/pixel) [{i}] := {}\n", d[i-1]);
+    format!(
+        "!INTERFILE :=\n\
+         !imaging modality := PT\n\
+         !version of keys := 3.3\n\
+         name of data file := {data_file}\n\
+         !GENERAL DATA :=\n\
+         !GENERAL IMAGE DATA :=\n\
+         !type of data := PET\n\
+         imagedata byte order := LITTLEENDIAN\n\
+         !PET STUDY (General) :=\n\
+         !PET data type := Image\n\
+         process status := Reconstructed\n\
+         !number format := float\n\
+         !number of bytes per pixel := 4\n\
+         number of dimensions := 3\n\
+         {}{}{}\
+         first pixel offset (mm) [1] := {}\n\
+         first pixel offset (mm) [2] := {}\n\
+         first pixel offset (mm) [3] := {}\n\
+         number of time frames := 1\n\
+         image scaling factor [1] := 1\n\
+         data offset in bytes [1] := 0\n\
+         quantification units := 1\n\
+         !END OF INTERFILE :=\n",
+        axis(1, "x", nx), axis(2, "y", ny), axis(3, "z", nz),
+        o[0], o[1], o[2],
+    )
+}
+
+/// The `key := value` pairs of an Interfile header, which must start with
+/// `!INTERFILE :=`
+fn read_header(text: &str) -> Result<Header, String> {
+    if !Header::lines(text, ';').next().is_some_and(|l| Header::normalize(l).starts_with("interfile:=")) {
+        return Err("Not an Interfile header: missing `!INTERFILE :=`".into())
+    }
+    Ok(Header::from_text("Interfile", text, ":=", ';'))
+}
+
+#[cfg(test)]
+mod test_interfile {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use units::mm;
+    use crate::Point;
+
+    #[test]
+    fn roundtrip() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("image.hv");
+        let fov = FOV::new((mm(30.0), mm(40.0), mm(15.0)), (3, 4, 5))
+            .with_centre(Point::new(mm(1.0), mm(-2.0), mm(100.0)));
+        let original = Image::new(fov, (0..60).map(|i| i as f32 * 0.5).collect());
+        write(&original, &path)?;
+        assert!(dir.path().join("image.v").exists());
+        let recovered = read(&path)?;
+        assert_eq!(recovered.fov.n, original.fov.n);
+        for i in 0..3 {
+            assert_float_eq!(mm_(recovered.fov.voxel_size[i]), mm_(original.fov.voxel_size[i]), abs <= 1e-4);
+            assert_float_eq!(mm_(recovered.fov.centre[i])    , mm_(original.fov.centre[i])    , abs <= 1e-4);
+        }
+        assert_eq!(recovered.data, original.data);
+        Ok(())
+    }
+
+    #[test]
+    fn foreign_header() -> Result<(), Box<dyn Error>> {
+        // Big-endian 16-bit integers, no position, irregular key spelling
+        let dir = tempfile::tempdir()?;
+        let header = "!INTERFILE  :=\n\
+                      ; comment\n\
+                      name of data file := mu.img\n\
+                      !number format := unsigned integer\n\
+                      !number of bytes per pixel := 2\n\
+                      number of dimensions := 3\n\
+                      !matrix size [1] := 2\n\
+                      !matrix size[2] := 1\n\
+                      !Matrix Size [3] := 2\n\
+                      scaling factor (mm/pixel) [1] := 4\n\
+                      scaling factor (mm/pixel) [2] := 4\n\
+                      scaling factor (mm/pixel) [3] := 2\n\
+                      !END OF INTERFILE :=\n";
+        std::fs::write(dir.path().join("mu.hv"), header)?;
+        std::fs::write(dir.path().join("mu.img"), [0, 1, 0, 2, 1, 0, 2, 0])?;
+        let image = read(&dir.path().join("mu.hv"))?;
+        assert_eq!(image.data, vec![1.0, 2.0, 256.0, 512.0]);
+        assert_eq!(image.fov.n, [2, 1, 2]);
+        assert_float_eq!(mm_(image.fov.centre.z), 0.0, abs <= 1e-6);
+        assert_float_eq!(mm_(image.fov.half_width.x), 4.0, abs <= 1e-6);
+        Ok(())
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{error::Error, path::Path};
+
+use units::mm_;
+
+use crate::{FOV, image::Image};
+use super::{Header, VoxelType, axis_aligned_image, first_voxel_centre, raw};
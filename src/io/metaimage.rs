@@ -0,0 +1,201 @@
+//! Read / write images in MetaImage format, as used by ITK
+//!
+//! `.mhd` headers are written next to a file of voxel values (written by
+//! `io::raw::write` as little-endian `f32`, with extension `.img`, so as not to
+//! be mistaken for, or overwrite, petalo's own `.raw` images); `.mha` files
+//! contain the header followed by the voxel values.
+//!
+//! `Offset` is the centre of the first voxel. The grid must be aligned with
+//! the coordinate axes: `TransformMatrix` may only reverse axes. Compressed
+//! (zlib) data are accepted when reading.
+
+/// The file in which the voxel values described by the `.mhd` header at `path` are written
+pub fn data_file(path: &Path) -> PathBuf { path.with_extension("img") }
+
+/// Write a `.mha` file with embedded data, or a `.mhd` header and separate data file
+pub fn write(image: &Image, path: &Path) -> Result<(), Box<dyn Error>> {
+    let embedded = path.extension().is_some_and(|x| x.eq_ignore_ascii_case("mha"));
+    if embedded {
+        let mut bytes = header(&image.fov, "LOCAL").into_bytes();
+        for v in &image.data { bytes.extend(v.to_le_bytes()) }
+        std::fs::write(path, bytes)?;
+    } else {
+        let data_path = data_file(path);
+        raw::write(image.data.iter().copied(), &data_path)?;
+        let data_name = data_path.file_name().ok_or("Invalid data file name")?.to_string_lossy();
+        std::fs::write(path, header(&image.fov, &data_name))?;
+    }
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Image, Box<dyn Error>> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Cannot read MetaImage {}: {e}", path.display()))?;
+    // The header ends with the line specifying the data file
+    let mut end = 0;
+    let mut header = HashMap::new();
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        end += line.len();
+        let line = std::str::from_utf8(line)?.trim();
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim().to_lowercase();
+        let last = key == "elementdatafile";
+        header.insert(key, value.trim().to_string());
+        if last { break }
+    }
+    let get = |key: &str| header.get(&key.to_lowercase()).map(String::as_str);
+    let numbers = |key: &str| -> Result<Option<Vec<f32>>, String> {
+        get(key).map(|v| v.split_whitespace()
+                     .map(|x| x.parse::<f32>().map_err(|_| format!("Invalid value of `{key}`: {v}")))
+                     .collect())
+                .transpose()
+    };
+    let flag = |key: &str| get(key).is_some_and(|v| v.eq_ignore_ascii_case("true"));
+
+    let n_dims = get("NDims").ok_or("MetaImage header lacks `NDims`")?;
+    if n_dims != "3" { return Err(format!("Only 3D images are supported, got NDims = {n_dims}").into()) }
+    if get("ElementNumberOfChannels").is_some_and(|c| c != "1") { return Err("Only single-channel images are supported".into()) }
+    let n = numbers("DimSize")?.ok_or("MetaImage header lacks `DimSize`")?;
+    let spacing = numbers("ElementSpacing")?.or(numbers("ElementSize")?).unwrap_or(vec![1.0; 3]);
+    let offset = numbers("Offset")?.or(numbers("Origin")?).or(numbers("Position")?);
+    let transform = numbers("TransformMatrix")?.or(numbers("Rotation")?).or(numbers("Orientation")?)
+        .unwrap_or(vec![1.0, 0.0, 0.0,  0.0, 1.0, 0.0,  0.0, 0.0, 1.0]);
+    if n.len() != 3 || spacing.len() != 3 || transform.len() != 9 || offset.as_ref().is_some_and(|o| o.len() != 3) {
+        return Err("Inconsistent dimensions in MetaImage header".into())
+    }
+    let direction = [0, 4, 8].map(|i| transform[i]);
+    let axis_aligned = (0..9).all(|i| i % 4 == 0 || transform[i].abs() < 1e-4)
+        && direction.iter().all(|d| (d.abs() - 1.0).abs() < 1e-4);
+    if !axis_aligned { return Err("Oblique or permuted MetaImage orientations are not supported".into()) }
+    let n = [0, 1, 2].map(|i| n[i] as usize);
+    let step = [0, 1, 2].map(|i| spacing[i] * direction[i].signum());
+    let first = match offset {
+        Some(o) => [o[0], o[1], o[2]],
+        None    => [0, 1, 2].map(|i| -step[i] * (n[i] as f32 - 1.0) / 2.0),
+    };
+
+    let voxel_type = match get("ElementType").ok_or("MetaImage header lacks `ElementType`")? {
+        "MET_UCHAR"  => VoxelType::U8 , "MET_CHAR"  => VoxelType::I8 ,
+        "MET_USHORT" => VoxelType::U16, "MET_SHORT" => VoxelType::I16,
+        "MET_UINT"   => VoxelType::U32, "MET_INT"   => VoxelType::I32,
+        "MET_FLOAT"  => VoxelType::F32, "MET_DOUBLE"=> VoxelType::F64,
+        t => return Err(format!("Unsupported MetaImage element type {t}").into()),
+    };
+    let little_endian = !(flag("BinaryDataByteOrderMSB") || flag("ElementByteOrderMSB"));
+
+    // Voxel values, either after the header or in a separate file
+    let data_file = get("ElementDataFile").ok_or("MetaImage header lacks `ElementDataFile`")?;
+    let external;
+    let data = if data_file == "LOCAL" { &bytes[end..] } else {
+        let data_path = path.parent().unwrap_or(Path::new("")).join(data_file);
+        external = std::fs::read(&data_path)
+            .map_err(|e| format!("Cannot read MetaImage data {}: {e}", data_path.display()))?;
+        &external[..]
+    };
+    let decompressed;
+    let data = if flag("CompressedData") {
+        let mut buffer = vec![];
+        ZlibDecoder::new(data).read_to_end(&mut buffer)?;
+        decompressed = buffer;
+        &decompressed[..]
+    } else { data };
+    let n_bytes = n.iter().product::<usize>() * voxel_type.size();
+    let skip = match get("HeaderSize").map(str::parse::<i64>).transpose()? {
+        None | Some(0) => 0,
+        Some(-1)       => data.len().saturating_sub(n_bytes), // Data at the end of the file
+        Some(s)        => s as usize,
+    };
+    let data = data.get(skip..skip + n_bytes).ok_or("MetaImage data too short for the image described in its header")?;
+    Ok(axis_aligned_image(n, step, first, &voxel_type.decode(data, little_endian)))
+}
+
+fn header(fov: &FOV, data_file: &str) -> String {
+    let [nx, ny, nz] = fov.n;
+    let d = [0, 1, 2].map(|i| mm_(fov.voxel_size[i]));
+    let o = first_voxel_centre(fov);
+    format!(
+        "ObjectType = Image\n\
+         NDims = 3\n\
+         BinaryData = True\n\
+         BinaryDataByteOrderMSB = False\n\
+         CompressedData = False\n\
+         TransformMatrix = 1 0 0 0 1 0 0 0 1\n\
+         Offset = {} {} {}\n\
+         CenterOfRotation = 0 0 0\n\
+         AnatomicalOrientation = RAI\n\
+         ElementSpacing = {} {} {}\n\
+         DimSize = {nx} {ny} {nz}\n\
+         ElementType = MET_FLOAT\n\
+         ElementDataFile = {data_file}\n",
+        o[0], o[1], o[2], d[0], d[1], d[2],
+    )
+}
+
+#[cfg(test)]
+mod test_metaimage {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use rstest::rstest;
+    use units::mm;
+    use crate::Point;
+
+    #[rstest(name, case("image.mhd"), case("image.mha"))]
+    fn roundtrip(name: &str) -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(name);
+        let fov = FOV::new((mm(30.0), mm(40.0), mm(15.0)), (3, 4, 5))
+            .with_centre(Point::new(mm(1.0), mm(-2.0), mm(100.0)));
+        let original = Image::new(fov, (0..60).map(|i| i as f32 * 0.5).collect());
+        write(&original, &path)?;
+        let recovered = read(&path)?;
+        assert_eq!(recovered.fov.n, original.fov.n);
+        for i in 0..3 {
+            assert_float_eq!(mm_(recovered.fov.voxel_size[i]), mm_(original.fov.voxel_size[i]), abs <= 1e-4);
+            assert_float_eq!(mm_(recovered.fov.centre[i])    , mm_(original.fov.centre[i])    , abs <= 1e-4);
+        }
+        assert_eq!(recovered.data, original.data);
+        Ok(())
+    }
+
+    #[test]
+    fn data_file_does_not_clobber_raw_image() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let fov = FOV::new((mm(30.0), mm(40.0), mm(15.0)), (3, 4, 5));
+        let raw_image = Image::new(fov, vec![1.0; 60]);
+        raw_image.write_to_file(&dir.path().join("image.raw"))?;
+        write(&Image::new(fov, vec![2.0; 60]), &dir.path().join("image.mhd"))?;
+        assert_eq!(Image::from_file(&dir.path().join("image.raw"))?.data, raw_image.data);
+        Ok(())
+    }
+
+    #[test]
+    fn reversed_big_endian_shorts() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let header = "ObjectType = Image\n\
+                      NDims = 3\n\
+                      BinaryDataByteOrderMSB = True\n\
+                      TransformMatrix = -1 0 0 0 1 0 0 0 1\n\
+                      Offset = 10 0 0\n\
+                      ElementSpacing = 2 2 2\n\
+                      DimSize = 3 1 1\n\
+                      ElementType = MET_SHORT\n\
+                      ElementDataFile = mu.dat\n";
+        std::fs::write(dir.path().join("mu.mhd"), header)?;
+        std::fs::write(dir.path().join("mu.dat"), [0, 1, 0, 2, 255, 255])?;
+        let image = read(&dir.path().join("mu.mhd"))?;
+        // x reversed: first voxel (at x = 10) becomes the last
+        assert_eq!(image.data, vec![-1.0, 2.0, 1.0]);
+        assert_float_eq!(mm_(image.fov.centre.x), 8.0, abs <= 1e-6);
+        Ok(())
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{collections::HashMap, error::Error, io::Read, path::{Path, PathBuf}};
+
+use flate2::read::ZlibDecoder;
+
+use units::mm_;
+
+use crate::{FOV, image::Image};
+use super::{VoxelType, axis_aligned_image, first_voxel_centre, raw};
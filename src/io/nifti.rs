@@ -0,0 +1,270 @@
+//! Read / write images in NIfTI-1 format (`.nii`, or gzipped `.nii.gz`)
+//!
+//! Images are written as single-file NIfTI-1 with 32-bit float voxels, in
+//! millimetres. Both the qform and sform map voxel indices onto the FOV's
+//! coordinate system, with the x, y and z axes of the FOV taken as the NIfTI
+//! (RAS+) axes.
+//!
+//! When reading, any endianness and the common integer and floating-point
+//! voxel types are accepted, and `scl_slope` / `scl_inter` are applied. The
+//! voxel grid must be aligned with the coordinate axes (reversed axes are
+//! flipped into our increasing-coordinate order) but oblique or permuted
+//! orientations are rejected.
+
+const HEADER_SIZE: usize = 348;
+/// Header plus the 4-byte extension flag
+const VOX_OFFSET: usize = 352;
+
+const DT_FLOAT32: i16 = 16;
+const UNITS_MM: u8 = 2;
+
+/// Is this a path that should be written / read as NIfTI?
+pub fn is_nifti(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    name.ends_with(".nii") || name.ends_with(".nii.gz")
+}
+
+fn is_gzipped(path: &Path) -> bool {
+    path.extension().is_some_and(|x| x.eq_ignore_ascii_case("gz"))
+}
+
+pub fn write(image: &Image, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut bytes = header(&image.fov)?;
+    bytes.extend([0; VOX_OFFSET - HEADER_SIZE]); // No extensions
+    bytes.reserve(image.data.len() * 4);
+    for v in &image.data { bytes.extend(v.to_le_bytes()) }
+    let file = BufWriter::new(File::create(path)?);
+    if is_gzipped(path) {
+        let mut gz = GzEncoder::new(file, Compression::default());
+        gz.write_all(&bytes)?;
+        gz.finish()?.flush()?;
+    } else {
+        let mut file = file;
+        file.write_all(&bytes)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<Image, Box<dyn Error>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut bytes = vec![];
+    if is_gzipped(path) { GzDecoder::new(file).read_to_end(&mut bytes)?; }
+    else                { let mut file = file; file.read_to_end(&mut bytes)?; }
+    from_bytes(&bytes)
+}
+
+fn header(fov: &FOV) -> Result<Vec<u8>, String> {
+    let mut h = Header { bytes: vec![0; HEADER_SIZE], little_endian: true };
+    h.put_i32(0, HEADER_SIZE as i32);
+    h.bytes[38] = b'r';
+    // Dimensions
+    h.put_i16(40, 3);
+    for (i, &n) in fov.n.iter().enumerate() {
+        let n = i16::try_from(n).map_err(|_| format!("NIfTI-1 cannot store {n} voxels along an axis"))?;
+        h.put_i16(42 + 2 * i, n);
+    }
+    for i in 3..7 { h.put_i16(42 + 2 * i, 1) }
+    h.put_i16(70, DT_FLOAT32);
+    h.put_i16(72, 32);
+    // Voxel sizes, with qfac = 1
+    let d = [0, 1, 2].map(|i| mm_(fov.voxel_size[i]));
+    h.put_f32(76, 1.0);
+    for (i, &d) in d.iter().enumerate() { h.put_f32(80 + 4 * i, d) }
+    for i in 4..8 { h.put_f32(76 + 4 * i, 1.0) }
+    h.put_f32(108, VOX_OFFSET as f32);
+    h.put_f32(112, 1.0); // scl_slope
+    h.bytes[123] = UNITS_MM;
+    h.bytes[148..154].copy_from_slice(b"petalo");
+    // Position of the centre of the first voxel. Identity rotation: quaternion b = c = d = 0
+    let o = first_voxel_centre(fov);
+    h.put_i16(252, 1); // qform_code: scanner-based coordinates
+    h.put_i16(254, 1); // sform_code
+    for (i, &o) in o.iter().enumerate() { h.put_f32(268 + 4 * i, o) }
+    for row in 0..3 {
+        h.put_f32(280 + 16 * row + 4 * row, d[row]);
+        h.put_f32(280 + 16 * row + 12   , o[row]);
+    }
+    h.bytes[344..348].copy_from_slice(b"n+1\0");
+    Ok(h.bytes)
+}
+
+fn from_bytes(bytes: &[u8]) -> Result<Image, Box<dyn Error>> {
+    if bytes.len() < HEADER_SIZE { return Err("File too short for a NIfTI-1 header".into()) }
+    let little_endian = match (i32::from_le_bytes(bytes[0..4].try_into()?), i32::from_be_bytes(bytes[0..4].try_into()?)) {
+        (348, _) => true,
+        (_, 348) => false,
+        _ => return Err("Not a NIfTI-1 file: sizeof_hdr is not 348".into()),
+    };
+    let h = Header { bytes: bytes[..HEADER_SIZE].to_vec(), little_endian };
+    match &h.bytes[344..348] {
+        b"n+1\0" => (),
+        b"ni1\0" => return Err("Two-file NIfTI-1 (.hdr + .img) is not supported".into()),
+        _        => return Err("Not a NIfTI-1 file: bad magic".into()),
+    }
+
+    // Dimensions: any beyond the third must be trivial
+    let ndim = h.i16(40);
+    if !(1..=7).contains(&ndim) { return Err(format!("Invalid number of dimensions: {ndim}").into()) }
+    let dims = (1..=ndim as usize).map(|i| h.i16(40 + 2 * i)).collect::<Vec<_>>();
+    if dims.iter().any(|&d| d < 1) { return Err(format!("Invalid dimensions: {dims:?}").into()) }
+    if dims.iter().skip(3).any(|&d| d > 1) { return Err(format!("Only 3D images are supported, got dimensions {dims:?}").into()) }
+    let n = [0, 1, 2].map(|i| dims.get(i).copied().unwrap_or(1) as usize);
+
+    // Units of length
+    let unit = match h.bytes[123] & 0x07 {
+        0 | 2 => 1.0,    // unknown: assume mm
+        1     => 1000.0, // m
+        3     => 0.001,  // μm
+        u     => return Err(format!("Unknown spatial units code {u}").into()),
+    };
+
+    // Affine transformation from voxel indices to positions
+    let pixdim = [1, 2, 3].map(|i| h.f32(76 + 4 * i));
+    let (matrix, offset) = if h.i16(254) > 0 {
+        let row = |r: usize| [0, 1, 2, 3].map(|c| h.f32(280 + 16 * r + 4 * c));
+        let rows = [row(0), row(1), row(2)];
+        (rows.map(|r| [r[0], r[1], r[2]]), rows.map(|r| r[3]))
+    } else if h.i16(252) > 0 {
+        let qfac = if h.f32(76) < 0.0 { -1.0 } else { 1.0 };
+        let [b, c, d] = [256, 260, 264].map(|at| h.f32(at));
+        let a = (1.0 - (b*b + c*c + d*d)).max(0.0).sqrt();
+        let r = [[a*a + b*b - c*c - d*d, 2.0 * (b*c - a*d)    , 2.0 * (b*d + a*c)    ],
+                 [2.0 * (b*c + a*d)    , a*a + c*c - b*b - d*d, 2.0 * (c*d - a*b)    ],
+                 [2.0 * (b*d - a*c)    , 2.0 * (c*d + a*b)    , a*a + d*d - c*c - b*b]];
+        let scale = [pixdim[0], pixdim[1], pixdim[2] * qfac];
+        (r.map(|row| [0, 1, 2].map(|c| row[c] * scale[c])), [268, 272, 276].map(|at| h.f32(at)))
+    } else {
+        // No orientation information: centre the image on the origin
+        let offset = [0, 1, 2].map(|i| -pixdim[i] * (n[i] as f32 - 1.0) / 2.0);
+        ([[pixdim[0], 0.0, 0.0], [0.0, pixdim[1], 0.0], [0.0, 0.0, pixdim[2]]], offset)
+    };
+    let step = [0, 1, 2].map(|i| matrix[i][i]);
+    for (r, row) in matrix.iter().enumerate() {
+        for (c, &m) in row.iter().enumerate() {
+            if r != c && m.abs() > 1e-4 * step[c].abs().max(step[r].abs()) {
+                return Err("Oblique or permuted NIfTI orientations are not supported".into())
+            }
+        }
+    }
+    if step.contains(&0.0) { return Err(format!("Zero voxel size: {step:?}").into()) }
+
+    // Voxel values
+    let n_voxels: usize = n.iter().product();
+    let vox_offset = h.f32(108).max(HEADER_SIZE as f32) as usize;
+    let (slope, inter) = match h.f32(112) { 0.0 => (1.0, 0.0), s => (s, h.f32(116)) };
+    let voxel_type = match h.i16(70) {
+        2 => VoxelType::U8 , 256 => VoxelType::I8 ,
+        4 => VoxelType::I16, 512 => VoxelType::U16,
+        8 => VoxelType::I32, 768 => VoxelType::U32,
+       16 => VoxelType::F32,  64 => VoxelType::F64,
+        t => return Err(format!("Unsupported NIfTI datatype {t}").into()),
+    };
+    let data = bytes.get(vox_offset..vox_offset + n_voxels * voxel_type.size())
+        .ok_or("File too short for the image dimensions in its header")?;
+    let values = voxel_type.decode(data, little_endian).into_iter()
+        .map(|v| v * slope + inter)
+        .collect::<Vec<_>>();
+
+    Ok(axis_aligned_image(n, step.map(|s| s * unit), offset.map(|o| o * unit), &values))
+}
+
+/// Raw NIfTI-1 header bytes, with accessors respecting its endianness
+struct Header { bytes: Vec<u8>, little_endian: bool }
+
+impl Header {
+    fn i16(&self, at: usize) -> i16 {
+        let b = self.bytes[at..at+2].try_into().unwrap();
+        if self.little_endian { i16::from_le_bytes(b) } else { i16::from_be_bytes(b) }
+    }
+    fn f32(&self, at: usize) -> f32 {
+        let b = self.bytes[at..at+4].try_into().unwrap();
+        if self.little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+    }
+    fn put_i16(&mut self, at: usize, v: i16) { self.bytes[at..at+2].copy_from_slice(&v.to_le_bytes()) }
+    fn put_i32(&mut self, at: usize, v: i32) { self.bytes[at..at+4].copy_from_slice(&v.to_le_bytes()) }
+    fn put_f32(&mut self, at: usize, v: f32) { self.bytes[at..at+4].copy_from_slice(&v.to_le_bytes()) }
+}
+
+#[cfg(test)]
+mod test_nifti {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use itertools::iproduct;
+    use rstest::rstest;
+    use units::mm;
+    use crate::Point;
+
+    fn guinea_pig() -> Image {
+        let fov = FOV::new((mm(30.0), mm(40.0), mm(15.0)), (3, 4, 5))
+            .with_centre(Point::new(mm(1.0), mm(-2.0), mm(100.0)));
+        Image::new(fov, (0..60).map(|i| i as f32 * 0.5).collect())
+    }
+
+    fn assert_same(a: &Image, b: &Image) {
+        assert_eq!(a.fov.n, b.fov.n);
+        for i in 0..3 {
+            assert_float_eq!(mm_(a.fov.voxel_size[i]), mm_(b.fov.voxel_size[i]), abs <= 1e-4);
+            assert_float_eq!(mm_(a.fov.centre[i])    , mm_(b.fov.centre[i])    , abs <= 1e-4);
+        }
+        assert_eq!(a.data, b.data);
+    }
+
+    #[rstest(name, case("image.nii"), case("image.nii.gz"))]
+    fn roundtrip(name: &str) -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(name);
+        let original = guinea_pig();
+        write(&original, &path)?;
+        assert_same(&read(&path)?, &original);
+        Ok(())
+    }
+
+    #[test]
+    fn reversed_axis_is_flipped() -> Result<(), Box<dyn Error>> {
+        let original = guinea_pig();
+        let mut bytes = header(&original.fov)?;
+        bytes.extend([0; 4]);
+        // Store x in decreasing order: first voxel at the highest x
+        let [nx, ny, nz] = original.fov.n;
+        for (k, j, i) in iproduct!(0..nz, 0..ny, 0..nx) {
+            bytes.extend(original[[nx - 1 - i, j, k]].to_le_bytes());
+        }
+        let mut h = Header { bytes: bytes[..HEADER_SIZE].to_vec(), little_endian: true };
+        let x_max = mm_(original.fov.voxel_centre([nx - 1, 0, 0]).x);
+        h.put_f32(280, -10.0);
+        h.put_f32(292, x_max);
+        bytes[..HEADER_SIZE].copy_from_slice(&h.bytes);
+        assert_same(&from_bytes(&bytes)?, &original);
+        Ok(())
+    }
+
+    #[test]
+    fn oblique_is_rejected() -> Result<(), Box<dyn Error>> {
+        let image = guinea_pig();
+        let mut bytes = header(&image.fov)?;
+        bytes.extend([0; 4]);
+        for v in &image.data { bytes.extend(v.to_le_bytes()) }
+        let mut h = Header { bytes: bytes[..HEADER_SIZE].to_vec(), little_endian: true };
+        h.put_f32(284, 3.0);
+        bytes[..HEADER_SIZE].copy_from_slice(&h.bytes);
+        assert!(from_bytes(&bytes).is_err());
+        Ok(())
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use units::mm_;
+
+use crate::{FOV, image::Image};
+use super::{VoxelType, axis_aligned_image, first_voxel_centre};
+
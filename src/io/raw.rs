@@ -73,7 +73,8 @@ mod test {
 
 // ----- Raw 3d image with matrix/physical size metadata --------------------------------
 
-use binrw::{binrw, BinWrite, BinReaderExt};
+use binrw::{binrw, BinRead, BinWrite, BinReaderExt, Endian};
+use std::io::Seek;
 
 #[derive(PartialEq, Debug)]
 // #[br(magic = b"IMG3D")] // TODO: magic was not supported by older writers
@@ -192,6 +193,229 @@ mod test_with_metadata {
 
 }
 
+// ----- Versioned raw 3d image with provenance metadata ---------------------------------
+//
+// v1 files (`Image3D`) consist of the matrix and physical sizes of the image,
+// followed by the voxel values, all big-endian. v2 files start with
+//
+//   MAGIC, endianness (b'L' or b'B'), version (2)
+//
+// followed by `Image3DV2`, in the stated endianness. v1 files are recognized
+// by the absence of MAGIC.
+
+pub const MAGIC: &[u8; 8] = b"PETALOIM";
+pub const VERSION: u8 = 2;
+
+/// What the voxel values of an image represent
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageKind {
+    #[default]
+    Unknown     = 0,
+    Activity    = 1,
+    /// Sensitivity (correction) image, as used by `mlem`
+    Sensitivity = 2,
+    /// Linear attenuation coefficients (μ)
+    Attenuation = 3,
+    /// Mass density, from which μ can be derived
+    Density     = 4,
+}
+
+/// Provenance and meaning of an image, recorded in v2 raw files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub kind: ImageKind,
+    /// Units of the voxel values, e.g. `"mm^-1"` for μ maps; empty if unknown
+    pub units: String,
+    /// OSEM iteration and subset which produced the image
+    pub iteration: Option<(usize, usize)>,
+    /// The TOML configuration which produced the image
+    pub config: Option<String>,
+}
+
+impl Metadata {
+    pub fn new(kind: ImageKind) -> Self { Self { kind, ..Self::default() } }
+}
+
+#[binrw]
+#[derive(PartialEq, Debug)]
+struct Image3DV2 {
+    pixels: [u32; 3],
+    /// Voxel size in mm
+    voxel_size: [f32; 3],
+    /// Centre of the FOV in mm
+    origin: [f32; 3],
+    kind: ImageKind,
+    /// 0 if the image was not produced by an iterative reconstruction
+    iteration: u32,
+    subset: u32,
+    #[bw(calc = units.len() as u16)]
+    units_len: u16,
+    #[br(count = units_len as usize, try_map = String::from_utf8)]
+    #[bw(map = |s: &String| s.as_bytes().to_vec())]
+    units: String,
+    #[bw(calc = config.len() as u32)]
+    config_len: u32,
+    #[br(count = config_len as usize, try_map = String::from_utf8)]
+    #[bw(map = |s: &String| s.as_bytes().to_vec())]
+    config: String,
+    #[br(count = pixels.iter().map(|&n| n as usize).product::<usize>())]
+    data: Vec<f32>,
+}
+
+fn invalid_data(message: String) -> binrw::Error {
+    binrw::Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+/// Write `image` with its `metadata` in the v2 raw format. Fails if the units
+/// or config are too long for their length fields.
+pub fn write_image(image: &MLEMImage, metadata: &Metadata, path: impl AsRef<std::path::Path>) -> Result<(), binrw::Error> {
+    if metadata.units.len() > u16::MAX as usize {
+        return Err(invalid_data(format!("Image units are {} bytes long: at most {} are allowed", metadata.units.len(), u16::MAX)))
+    }
+    if metadata.config.as_ref().map_or(0, String::len) > u32::MAX as usize {
+        return Err(invalid_data(format!("Image config is too long: at most {} bytes are allowed", u32::MAX)))
+    }
+    let fov = &image.fov;
+    let (iteration, subset) = metadata.iteration.unwrap_or((0, 0));
+    let body = Image3DV2 {
+        pixels: fov.n.map(|n| n as u32),
+        voxel_size: [0, 1, 2].map(|i| mm_(fov.voxel_size[i])),
+        origin: [0, 1, 2].map(|i| mm_(fov.centre[i])),
+        kind: metadata.kind,
+        iteration: iteration as u32,
+        subset: subset as u32,
+        units: metadata.units.clone(),
+        config: metadata.config.clone().unwrap_or_default(),
+        data: image.data.clone(),
+    };
+    let mut buf = BufWriter::new(File::create(path)?);
+    buf.write_all(MAGIC)?;
+    buf.write_all(&[b'L', VERSION])?;
+    body.write_le(&mut buf)?;
+    buf.flush()?;
+    Ok(())
+}
+
+/// Read an image in either raw format, with its metadata (default for v1 files)
+pub fn read_image(path: impl AsRef<std::path::Path>) -> Result<(MLEMImage, Metadata), binrw::Error> {
+    let mut buf = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    let is_v2 = match buf.read_exact(&mut magic) {
+        Ok(()) => &magic == MAGIC,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e.into()),
+    };
+    if !is_v2 {
+        buf.rewind()?;
+        let v1: Image3D = buf.read_be()?;
+        return Ok(((&v1).into(), Metadata::default()))
+    }
+    let mut marks = [0; 2];
+    buf.read_exact(&mut marks)?;
+    let endian = match marks[0] {
+        b'L' => Endian::Little,
+        b'B' => Endian::Big,
+        e    => return Err(invalid_data(format!("Invalid endianness marker {e:#x} in raw image"))),
+    };
+    if marks[1] != VERSION {
+        // v1 files have no header, so any other version in a header is unsupported
+        return Err(invalid_data(format!("Unsupported raw image version {} in header: expected {VERSION}", marks[1])))
+    }
+    let body = Image3DV2::read_options(&mut buf, endian, ())?;
+    let [nx, ny, nz] = body.pixels.map(|n| n as usize);
+    let [dx, dy, dz] = body.voxel_size;
+    let [cx, cy, cz] = body.origin;
+    let fov = crate::FOV::new((mm(dx * nx as f32), mm(dy * ny as f32), mm(dz * nz as f32)), (nx, ny, nz))
+        .with_centre(crate::Point::new(mm(cx), mm(cy), mm(cz)));
+    let metadata = Metadata {
+        kind: body.kind,
+        units: body.units,
+        iteration: (body.iteration > 0).then_some((body.iteration as usize, body.subset as usize)),
+        config: Some(body.config).filter(|c| !c.is_empty()),
+    };
+    Ok((MLEMImage::new(fov, body.data), metadata))
+}
+
+#[cfg(test)]
+mod test_v2 {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn guinea_pig() -> MLEMImage {
+        let fov = crate::FOV::new((mm(10.0), mm(20.0), mm(30.0)), (1, 2, 3))
+            .with_centre(crate::Point::new(mm(1.0), mm(2.0), mm(-300.0)));
+        MLEMImage::new(fov, (0..6).map(|n| n as f32).collect())
+    }
+
+    #[test]
+    fn roundtrip_with_metadata() -> Result<(), binrw::Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("image.raw");
+        let original = guinea_pig();
+        let metadata = Metadata {
+            kind: ImageKind::Activity,
+            units: "counts".into(),
+            iteration: Some((3, 2)),
+            config: Some("iterations = { number = 4, subsets = 2 }\n".into()),
+        };
+        write_image(&original, &metadata, &path)?;
+        let (image, recovered) = read_image(&path)?;
+        assert_eq!(recovered, metadata);
+        assert_eq!(image.fov.n, original.fov.n);
+        assert_eq!(image.data, original.data);
+        for i in 0..3 {
+            assert_float_eq!(mm_(image.fov.centre[i])    , mm_(original.fov.centre[i])    , ulps <= 4);
+            assert_float_eq!(mm_(image.fov.voxel_size[i]), mm_(original.fov.voxel_size[i]), ulps <= 4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn v1_files_still_readable() -> Result<(), binrw::Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("image.raw");
+        let v1 = Image3D { pixels: [1, 2, 3], mm: [2.0, 4.0, 9.0], data: (0..6).map(|n| n as f32).collect() };
+        v1.write_to_file(&path)?;
+        let (image, metadata) = read_image(&path)?;
+        assert_eq!(metadata, Metadata::default());
+        assert_eq!(Image3D::from(&image), v1);
+        Ok(())
+    }
+
+    #[test]
+    fn big_endian_v2() -> Result<(), binrw::Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("image.raw");
+        let original = guinea_pig();
+        let body = Image3DV2 {
+            pixels: [1, 2, 3], voxel_size: [10.0; 3], origin: [1.0, 2.0, -300.0],
+            kind: ImageKind::Attenuation, iteration: 0, subset: 0,
+            units: "mm^-1".into(), config: String::new(), data: original.data.clone(),
+        };
+        let mut bytes = Cursor::new(vec![]);
+        bytes.write_all(MAGIC)?;
+        bytes.write_all(&[b'B', VERSION])?;
+        body.write_be(&mut bytes)?;
+        std::fs::write(&path, bytes.into_inner())?;
+        let (image, metadata) = read_image(&path)?;
+        assert_eq!(image.data, original.data);
+        assert_eq!(metadata, Metadata { kind: ImageKind::Attenuation, units: "mm^-1".into(), iteration: None, config: None });
+        Ok(())
+    }
+
+    #[test]
+    fn overlong_units_rejected() -> Result<(), binrw::Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("image.raw");
+        let metadata = Metadata { units: "m".repeat(u16::MAX as usize + 1), ..Metadata::new(ImageKind::Activity) };
+        assert!(write_image(&guinea_pig(), &metadata, &path).is_err());
+        assert!(!path.exists());
+        Ok(())
+    }
+}
+
 // ----- Proofs of concept ---------------------------------------------------------------
 #[cfg(test)]
 mod test_br_enum {
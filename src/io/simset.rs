@@ -0,0 +1,37 @@
+//! Convert events in SimSET standard history files into LORs
+//!
+//! SimSET lengths are in cm, times in s and energies in keV: the LORs have
+//! lengths in mm and times in ns, as usual. SimSET records the time of each
+//! detected photon since its decay: the times of arrival are those times
+//! added to the time of the decay.
+
+/// The LOR between the first blue and the first pink photon of `event`, if
+/// both were detected. SimSET's artificial randoms are classified as randoms;
+/// otherwise either photon having scattered makes it a scatter.
+pub fn lor(event: &Event, event_id: u32) -> Option<ExtendedLor> {
+    let (blue, pink) = event.coincidence()?;
+    let mm = |cm: f32| cm * 10.0;
+    let ns = |s: f64| (s * 1e9) as f32;
+    let decay_time = event.decay.time;
+    let truth =
+        if      event.is_random()                       { Truth::Random  }
+        else if blue.scattered() || pink.scattered()    { Truth::Scatter }
+        else                                            { Truth::True    };
+    Some(ExtendedLor {
+        event_id,
+        t1: ns(decay_time + blue.time),
+        t2: ns(decay_time + pink.time),
+        dt: ns(pink.time - blue.time),
+        x1: mm(blue.location.x), y1: mm(blue.location.y), z1: mm(blue.location.z),
+        x2: mm(pink.location.x), y2: mm(pink.location.y), z2: mm(pink.location.z),
+        E1: blue.energy,
+        E2: pink.energy,
+        truth: truth as u8,
+        ..ExtendedLor::unknown()
+    })
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use ::simset::Event;
+
+use crate::io::hdf5::{ExtendedLor, Truth};
@@ -17,3 +17,7 @@ pub mod config;
 pub mod sensors;
 pub mod projector;
 pub mod discrete;
+pub mod simulate;
+pub mod phantom;
+pub mod countrates;
+pub mod multibed;
@@ -11,25 +11,75 @@ pub fn mlem<'a, S: Projector + 'a>(
     measured_lors: &'a [LOR],
     sensitivity  : Option<Image>,
     n_subsets    : usize,
+    filter       : Option<Filter>,
 ) -> impl Iterator<Item = (Image, Osem)> + '_ {
 
-    // Start off with a uniform image
-    let mut image = Image::ones(fov);
-
-    let sensitivity = sensitivity.or_else(|| Some(Image::ones(fov))).unwrap();
-
+    let (mut image, sensitivity) = initial_image(fov, sensitivity);
     let mut osem = Osem::new(n_subsets);
 
     // Return an iterator which generates an infinite sequence of images,
     // each one made by performing one MLEM iteration on the previous one
     std::iter::from_fn(move || {
         one_iteration::<S>(parameters, &mut image, osem.subset(measured_lors), &sensitivity.data);
+        // Sieve: feed the filtered image into the next iteration
+        if let Some(filter) = filter { image = filter.apply(&image); }
         let image_id = osem;
         osem.advance();
         Some((image.clone(), image_id)) // TODO see if we can sensibly avoid cloning
     })
 }
 
+/// Like `mlem`, but the LORs are not held in memory: `subset_lors(subset,
+/// n_subsets)` provides the LORs of the given subset afresh in every iteration,
+/// as a sequence of chunks which are processed one at a time.
+pub fn mlem_streaming<'a, S, F, C>(
+    parameters : S::Data,
+    fov        : FOV,
+    subset_lors: F,
+    sensitivity: Option<Image>,
+    n_subsets  : usize,
+    filter     : Option<Filter>,
+) -> impl Iterator<Item = (Image, Osem)> + 'a
+where
+    S: Projector + 'a,
+    F: Fn(usize, usize) -> C + 'a,
+    C: Iterator<Item = Vec<LOR>>,
+{
+    let (mut image, sensitivity) = initial_image(fov, sensitivity);
+    let mut osem = Osem::new(n_subsets);
+
+    std::iter::from_fn(move || {
+        let mut backprojection = Image::zeros_buffer(fov);
+        for lors in subset_lors(osem.subset, osem.n_subsets) {
+            let parallel_lors = parallelize_lors(&lors, 10000);
+            let chunk = project_lors::<S,_,_>(parallel_lors, parameters, &image, None, project_one_lor_mlem::<S>);
+            backprojection.iter_mut().zip(chunk).for_each(|(b, c)| *b += c);
+        }
+        apply_sensitivity_image(&mut image.data, &backprojection, &sensitivity.data);
+        if let Some(filter) = filter { image = filter.apply(&image); }
+        let image_id = osem;
+        osem.advance();
+        Some((image.clone(), image_id))
+    })
+}
+
+/// The sensitivity correction (zero outside the FOV's support) and an image
+/// which is uniform wherever it is non-zero, with which to start MLEM
+fn initial_image(fov: FOV, sensitivity: Option<Image>) -> (Image, Image) {
+    let mut sensitivity = sensitivity.or_else(|| Some(Image::ones(fov))).unwrap();
+
+    // Voxels outside the FOV's support are never reconstructed
+    if fov.support.is_some() {
+        for (i, s) in sensitivity.data.iter_mut().enumerate() {
+            if !fov.in_support(fov.voxel_centre1(i)) { *s = 0.0 }
+        }
+    }
+
+    // Start off with an image which is uniform wherever the sensitivity is non-zero
+    let image = Image::new(fov, sensitivity.data.iter().map(|&s| if s > 0.0 { 1.0 } else { 0.0 }).collect());
+    (image, sensitivity)
+}
+
 fn one_iteration<S: Projector>(
     projector    : S::Data,
     image        : &mut Image,
@@ -51,6 +101,51 @@ fn apply_sensitivity_image(image: &mut ImageData, backprojection: &[Lengthf32],
     })
 }
 
+/// Poisson log-likelihood of `measured_lors` given `image`, up to a constant:
+/// the sum over LORs of the log of their expected counts (forward projections),
+/// minus the total expected counts (the image weighted by the sensitivity, the
+/// reciprocal of the `sensitivity` correction used by `mlem`).
+pub fn log_likelihood<S: Projector>(
+    parameters   : S::Data,
+    image        : &Image,
+    measured_lors: &[LOR],
+    sensitivity  : Option<&Image>,
+) -> f64 {
+    log_likelihood_chunks::<S, _>(parameters, image, std::iter::once(measured_lors), sensitivity)
+}
+
+/// `log_likelihood` of LORs provided in chunks, as in `mlem_streaming`
+pub fn log_likelihood_chunks<S: Projector, L: AsRef<[LOR]>>(
+    parameters   : S::Data,
+    image        : &Image,
+    measured_lors: impl IntoIterator<Item = L>,
+    sensitivity  : Option<&Image>,
+) -> f64 {
+    let fov = image.fov;
+    let n_voxels = image.data.len();
+    let log_expected: f64 = measured_lors.into_iter()
+        .map(|lors| parallelize_lors(lors.as_ref(), 10000)
+            .map_init(|| S::buffers(fov), |row, lor| {
+                row.clear();
+                S::update_system_matrix_row(row, lor, fov, &parameters);
+                // Skip problematic LORs, as in `project_lors`
+                if row.iter().any(|&(j, _)| j >= n_voxels) { return 0.0 }
+                let projection = row.iter().map(|&(j, w)| w * image[j]).sum::<f32>();
+                let expected = projection * ratio_(lor.additive_correction);
+                if expected > 0.0 { (expected as f64).ln() } else { 0.0 }
+            })
+            .sum::<f64>())
+        .sum();
+    let total_expected: f64 = match sensitivity {
+        None => image.data.iter().map(|&x| x as f64).sum(),
+        Some(s) => image.data.iter().zip(&s.data)
+            .filter(|(_, &s)| s > 0.0)
+            .map(|(&x, &s)| (x / s) as f64)
+            .sum(),
+    };
+    log_expected - total_expected
+}
+
 // TODO filter measured LORs that don't pass through FOV!
 
 use rayon::prelude::{ParallelIterator, ParallelBridge};
@@ -98,15 +193,85 @@ impl Osem {
 /// Number of threads to be use to parallelize MLEM/OSEM image reconstruction
 pub static mut N_MLEM_THREADS: usize = 1;
 
+#[cfg(test)]
+mod test_log_likelihood {
+    use super::*;
+    use crate::{Point, projectors::Siddon};
+    use units::{mm, ns, ratio};
+
+    #[test]
+    fn increases_with_mlem_iterations() {
+        let fov = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (8, 8, 8));
+        // LORs crossing near a point off the centre of the FOV
+        let lors = (0..200)
+            .map(|i| {
+                let phi = i as f32 * 0.1;
+                let (s, c) = phi.sin_cos();
+                let z = (i % 7) as f32 - 3.0;
+                LOR::new(ns(0.0), ns(0.0),
+                         Point::new(mm(5.0 + 100.0 * c), mm(3.0 + 100.0 * s), mm(z)),
+                         Point::new(mm(5.0 - 100.0 * c), mm(3.0 - 100.0 * s), mm(-z)),
+                         ratio(1.0))
+            })
+            .collect::<Vec<_>>();
+        let parameters = Siddon::new(None).data();
+        let likelihoods = mlem::<Siddon>(parameters, fov, &lors, None, 1, None)
+            .take(5)
+            .map(|(image, _)| log_likelihood::<Siddon>(parameters, &image, &lors, None))
+            .collect::<Vec<_>>();
+        assert!(likelihoods.windows(2).all(|w| w[1] > w[0]), "{likelihoods:?}");
+    }
+}
+
+#[cfg(test)]
+mod test_streaming {
+    use super::*;
+    use crate::{Point, projectors::Siddon};
+    use units::{mm, ns, ratio};
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn same_images_as_in_memory() {
+        let fov = FOV::new((mm(40.0), mm(40.0), mm(40.0)), (8, 8, 8));
+        let lors = (0..240)
+            .map(|i| {
+                let (s, c) = (i as f32 * 0.37).sin_cos();
+                let z = (i % 5) as f32 - 2.0;
+                LOR::new(ns(0.0), ns(0.0),
+                         Point::new(mm( 100.0 * c), mm( 100.0 * s), mm(z)),
+                         Point::new(mm(-100.0 * c), mm(-100.0 * s), mm(-z)),
+                         ratio(1.0))
+            })
+            .collect::<Vec<_>>();
+        let parameters = Siddon::new(None).data();
+        let n_subsets = 3;
+        // Each subset delivered in chunks of 25 LORs
+        let subset_lors = |subset: usize, n_subsets: usize| {
+            let size = lors.len() / n_subsets;
+            lors[(subset - 1) * size..subset * size].chunks(25).map(<[LOR]>::to_vec).collect::<Vec<_>>().into_iter()
+        };
+        let in_memory = mlem::<Siddon>(parameters, fov, &lors, None, n_subsets, None);
+        let streamed = mlem_streaming::<Siddon, _, _>(parameters, fov, subset_lors, None, n_subsets, None);
+        for ((a, osem_a), (b, osem_b)) in in_memory.zip(streamed).take(6) {
+            assert_eq!((osem_a.iteration, osem_a.subset), (osem_b.iteration, osem_b.subset));
+            assert_float_eq!(a.data, b.data, rmax_all <= 1e-4);
+        }
+        let image = Image::ones(fov);
+        assert_float_eq!(log_likelihood::<Siddon>(parameters, &image, &lors, None),
+                         log_likelihood_chunks::<Siddon, _>(parameters, &image, lors.chunks(25), None),
+                         rmax <= 1e-9);
+    }
+}
+
 
 // ----- Imports ------------------------------------------------------------------------------------------
 use ndarray::azip;
 
-use units::todo::{Lengthf32, Intensityf32};
+use units::{ratio_, todo::{Lengthf32, Intensityf32}};
 
 use crate::{
     FOV, LOR,
-    image::{Image, ImageData},
+    image::{Image, ImageData, filter::Filter},
     projector::{project_lors, project_one_lor_mlem},
     projectors::Projector
 };
@@ -0,0 +1,235 @@
+//! Reconstruction of acquisitions split into several overlapping bed positions
+//!
+//! Each bed's LORs are recorded in scanner coordinates. Moving the bed by
+//! `offset` along z is equivalent to moving the scanner by `offset` relative to
+//! the patient, so each bed's LORs and FOV are shifted by `offset` into patient
+//! coordinates.
+//!
+//! Beds can be reconstructed
+//!
+//! + separately, each in its own FOV, after which the bed images are stitched
+//!   together, each voxel being the average of the overlapping beds, weighted
+//!   by their sensitivities;
+//!
+//! + jointly, in a single FOV covering all beds, using the LORs of all beds and
+//!   the sum of their sensitivities. The LORs of the beds are interleaved, so
+//!   that every OSEM subset contains LORs from all beds.
+//!
+//! Sensitivity images used by `mlem` are multiplicative corrections, so the
+//! sensitivity of a bed is their reciprocal. Beds without a sensitivity image
+//! are given a triangular axial sensitivity profile, peaking at the centre of
+//! the bed, as is typical of 3D PET scanners.
+
+/// How the beds of a multi-bed acquisition are combined
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Reconstruct each bed in its own FOV, and stitch the results together
+    #[default]
+    Separate,
+    /// Reconstruct all beds together in a single FOV
+    Joint,
+}
+
+/// The FOV of a bed at axial `offset`, given the FOV in scanner coordinates
+pub fn bed_fov(fov: FOV, offset: Length) -> FOV {
+    let c = fov.centre;
+    fov.with_centre(Point::new(c.x, c.y, c.z + offset))
+}
+
+/// Move LORs recorded in scanner coordinates into patient coordinates, for a
+/// bed at axial `offset`
+pub fn shift_lors(lors: &mut [LOR], offset: Length) {
+    let shift = Vector::new(Length::ZERO, Length::ZERO, offset);
+    for lor in lors {
+        lor.p1 += shift;
+        lor.p2 += shift;
+    }
+}
+
+/// Merge the LORs of all beds, spreading each bed's LORs evenly through the
+/// result. OSEM subsets are contiguous slices of the LORs: if the beds were
+/// concatenated, each subset would see only some beds, and the voxels outside
+/// them would be zeroed for good.
+pub fn interleave(beds: Vec<Vec<LOR>>) -> Vec<LOR> {
+    let mut lors = beds.into_iter()
+        .flat_map(|bed| {
+            let n = bed.len() as f64;
+            bed.into_iter().enumerate().map(move |(i, lor)| ((i as f64 + 0.5) / n, lor))
+        })
+        .collect::<Vec<_>>();
+    lors.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    lors.into_iter().map(|(_, lor)| lor).collect()
+}
+
+/// The smallest FOV, with the voxelization of the beds, which covers all of
+/// them. The beds must differ only in their axial positions.
+pub fn whole_body_fov(beds: &[FOV]) -> Result<FOV, String> {
+    let first = beds.first().ok_or("No beds")?;
+    let dz = first.voxel_size.z;
+    if let Some(bed) = beds.iter().find(|b| b.n[..2] != first.n[..2] || b.voxel_size.z != dz) {
+        return Err(format!("All beds must have the same voxelization: {bed:?} differs from {first:?}"))
+    }
+    let (lo, hi) = beds.iter()
+        .map(|fov| { let lo = fov.lower_corner().z; (lo, lo + 2.0 * fov.half_width.z) })
+        .fold((first.lower_corner().z, first.lower_corner().z), |(lo, hi), (l, h)| (lo.min(l), hi.max(h)));
+    let nz = ratio_((hi - lo) / dz).round() as usize;
+    let [nx, ny, _] = first.n;
+    let size = (first.half_width.x * 2.0, first.half_width.y * 2.0, nz as f32 * dz);
+    let c = first.centre;
+    Ok(FOV { support: first.support, ..FOV::new(size, (nx, ny, nz)) }
+       .with_centre(Point::new(c.x, c.y, lo + size.2 / 2.0)))
+}
+
+/// Sensitivity of a bed with the given `correction` image (as used by `mlem`),
+/// or with a triangular axial profile if there is none
+pub fn sensitivity(fov: FOV, correction: Option<&Image>) -> Image {
+    match correction {
+        Some(correction) => Image::new(fov, correction.data.iter()
+                                       .map(|&c| if c > 0.0 { 1.0 / c } else { 0.0 })
+                                       .collect()),
+        None => {
+            let data = (0..fov.n.iter().product())
+                .map(|i| {
+                    let dz = (fov.voxel_centre1(i).z - fov.centre.z).abs();
+                    (1.0 - ratio_(dz / fov.half_width.z)).max(0.0)
+                })
+                .collect();
+            Image::new(fov, data)
+        }
+    }
+}
+
+/// Correction image (as used by `mlem`) for the joint reconstruction of beds
+/// with the given `sensitivities`, in `fov`
+pub fn joint_correction(sensitivities: &[Image], fov: FOV) -> Image {
+    let total = total_sensitivity(sensitivities, fov);
+    Image::new(fov, total.iter().map(|&s| if s > 0.0 { 1.0 / s } else { 0.0 }).collect())
+}
+
+/// Combine images of separately reconstructed beds into `fov`, weighting each
+/// bed's image by its sensitivity
+pub fn stitch(images: &[Image], sensitivities: &[Image], fov: FOV) -> Image {
+    let mut sum = Image::zeros_buffer(fov);
+    for (image, sensitivity) in images.iter().zip(sensitivities) {
+        let image       = image      .resample(fov, Resampling::Trilinear);
+        let sensitivity = sensitivity.resample(fov, Resampling::Trilinear);
+        for ((sum, v), w) in sum.iter_mut().zip(&image.data).zip(&sensitivity.data) {
+            *sum += v * w;
+        }
+    }
+    let total = total_sensitivity(sensitivities, fov);
+    Image::new(fov, sum.iter().zip(&total).map(|(&s, &w)| if w > 0.0 { s / w } else { 0.0 }).collect())
+}
+
+fn total_sensitivity(sensitivities: &[Image], fov: FOV) -> ImageData {
+    let mut total = Image::zeros_buffer(fov);
+    for sensitivity in sensitivities {
+        for (t, s) in total.iter_mut().zip(sensitivity.resample(fov, Resampling::Trilinear).data) {
+            *t += s;
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod test_multibed {
+    use super::*;
+    use float_eq::assert_float_eq;
+    use units::{mm, mm_};
+
+    fn scanner_fov() -> FOV { FOV::new((mm(100.0), mm(100.0), mm(100.0)), (10, 10, 10)) }
+
+    #[test]
+    fn whole_body_covers_all_beds() {
+        let beds = [-70.0, 0.0, 70.0].map(|z| bed_fov(scanner_fov(), mm(z)));
+        let fov = whole_body_fov(&beds).unwrap();
+        assert_eq!(fov.n, [10, 10, 24]);
+        assert_float_eq!(mm_(fov.centre.z), 0.0, abs <= 1e-4);
+        assert_float_eq!(mm_(fov.lower_corner().z), -120.0, abs <= 1e-4);
+        assert!(beds.iter().all(|bed| covers(&fov, bed)));
+    }
+
+    #[test]
+    fn stitching_uniform_beds_is_uniform() {
+        // Beds overlapping by 30 mm, each containing a uniform image
+        let beds = [-35.0, 35.0].map(|z| bed_fov(scanner_fov(), mm(z)));
+        let fov = whole_body_fov(&beds).unwrap();
+        let images = beds.map(Image::ones);
+        let sensitivities = beds.map(|bed| sensitivity(bed, None));
+        let stitched = stitch(&images, &sensitivities, fov);
+        assert!(stitched.data.iter().all(|&v| (v - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn stitching_favours_more_sensitive_bed() {
+        let beds = [-35.0, 35.0].map(|z| bed_fov(scanner_fov(), mm(z)));
+        let fov = whole_body_fov(&beds).unwrap();
+        let images = [Image::ones(beds[0]), Image::new(beds[1], vec![3.0; 1000])];
+        let sensitivities = beds.map(|bed| sensitivity(bed, None));
+        let stitched = stitch(&images, &sensitivities, fov);
+        // Voxels covered by only one bed take its value
+        assert_float_eq!(stitched[[5, 5,  0]], 1.0, abs <= 1e-5);
+        assert_float_eq!(stitched[[5, 5, 16]], 3.0, abs <= 1e-5);
+        // In the overlap, the value moves from one to the other
+        let overlap = (7..10).map(|k| stitched[[5, 5, k]]).collect::<Vec<_>>();
+        assert!(overlap.windows(2).all(|w| w[0] < w[1]));
+        assert!(overlap.iter().all(|&v| v > 1.0 && v < 3.0));
+    }
+
+    #[test]
+    fn sensitivity_from_correction() {
+        let fov = scanner_fov();
+        let correction = Image::new(fov, (0..1000).map(|i| (i % 4) as f32).collect());
+        let s = sensitivity(fov, Some(&correction));
+        assert_eq!(&s.data[..4], &[0.0, 1.0, 0.5, 1.0 / 3.0]);
+        let joint = joint_correction(&[s.clone(), s], fov);
+        assert_eq!(&joint.data[..4], &[0.0, 0.5, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn lors_shifted_axially() {
+        let p = |z| Point::new(mm(1.0), mm(2.0), mm(z));
+        let mut lors = [LOR::new(units::ns(0.0), units::ns(0.0), p(-10.0), p(20.0), units::ratio(1.0))];
+        shift_lors(&mut lors, mm(100.0));
+        assert_eq!((mm_(lors[0].p1.z), mm_(lors[0].p2.z)), (90.0, 120.0));
+    }
+
+    #[test]
+    fn joint_osem_keeps_all_beds() {
+        use crate::{mlem::mlem, projectors::{Projector, Siddon}};
+        let beds = [-35.0, 35.0].map(|z| bed_fov(scanner_fov(), mm(z)));
+        let fov = whole_body_fov(&beds).unwrap();
+        // Transaxial LORs through a point in the part of each bed which is not
+        // covered by the other
+        let bed_lors = |z: f32| (0..50)
+            .map(|i| {
+                let (s, c) = (i as f32 * 0.13).sin_cos();
+                LOR::new(units::ns(0.0), units::ns(0.0),
+                         Point::new(mm(3.0 + 100.0 * c), mm(5.0 + 100.0 * s), mm(z)),
+                         Point::new(mm(3.0 - 100.0 * c), mm(5.0 - 100.0 * s), mm(z)),
+                         units::ratio(1.0))
+            })
+            .collect::<Vec<_>>();
+        let lors = interleave(vec![bed_lors(-52.0), bed_lors(52.0)]);
+        let sensitivities = beds.map(|bed| sensitivity(bed, None));
+        let correction = joint_correction(&sensitivities, fov);
+        let parameters = Siddon::new(None).data();
+        let (image, _) = mlem::<Siddon>(parameters, fov, &lors, Some(correction), 2, None)
+            .nth(3).unwrap();
+        assert!(image[[5, 5,  3]] > 0.0);
+        assert!(image[[5, 5, 13]] > 0.0);
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use serde::Deserialize;
+
+use units::{Length, ratio_, uom::ConstZero};
+
+use crate::{
+    FOV, LOR, Point, Vector,
+    image::{Image, ImageData, resample::Resampling},
+};
+#[cfg(test)]
+use crate::image::resample::covers;
@@ -0,0 +1,327 @@
+//! Analytic phantoms, and their rasterization into activity and density images
+//!
+//! A `Phantom` is an ordered collection of `Component`s: shapes filled with
+//! uniform activity and density. Later components overwrite earlier ones
+//! wherever they overlap, so inserts are described after the body which
+//! contains them.
+//!
+//! Rasterization samples each voxel on a regular sub-voxel grid, so that
+//! voxels straddling the boundary of a shape receive the appropriate fraction
+//! of its value.
+//!
+//! Activities are relative: backgrounds have activity 1. Densities are in
+//! g/cm³, as expected by `make_sensitivity_image`.
+
+#[derive(Clone, Debug)]
+pub enum Shape {
+
+    /// Any of the ROIs used in FOM calculations
+    Roi(ROI),
+
+    /// Cross-section of the body of the NEMA NU 2 image quality phantom:
+    /// semicircle of radius 147 mm above the x-axis, and a 140 mm wide
+    /// rectangle with 77 mm rounded corners below it. Infinite in z.
+    NemaBody,
+}
+
+impl Shape {
+    pub fn contains(&self, p: Point) -> bool {
+        match self {
+            Shape::Roi(roi) => roi.contains(p),
+            Shape::NemaBody => {
+                let (x, y) = (mm_(p.x).abs(), mm_(p.y));
+                if y >= 0.0 {
+                    x.hypot(y) < 147.0
+                } else if x <= 70.0 {
+                    y > -77.0
+                } else {
+                    (x - 70.0).hypot(y) < 77.0
+                }
+            },
+        }
+    }
+}
+
+/// A shape filled with uniform activity and density
+#[derive(Clone, Debug)]
+pub struct Component {
+    pub shape: Shape,
+    /// Axial extent `[z_min, z_max)`. Unlimited if `None`
+    pub z: Option<(Length, Length)>,
+    pub activity: Intensityf32,
+    /// Density in g/cm³
+    pub density: f32,
+}
+
+impl Component {
+    pub fn contains(&self, p: Point) -> bool {
+        let in_z = match self.z {
+            Some((lo, hi)) => lo <= p.z && p.z < hi,
+            None => true,
+        };
+        in_z && self.shape.contains(p)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Phantom {
+    pub components: Vec<Component>,
+}
+
+impl Phantom {
+
+    /// Image of the activity distribution, sampling each voxel on a grid of
+    /// `supersample`³ points
+    pub fn activity_image(&self, fov: FOV, supersample: usize) -> Image {
+        self.rasterize(fov, supersample, |c| c.activity)
+    }
+
+    /// Image of the density distribution, in g/cm³, sampling each voxel on a
+    /// grid of `supersample`³ points
+    pub fn density_image(&self, fov: FOV, supersample: usize) -> Image {
+        self.rasterize(fov, supersample, |c| c.density)
+    }
+
+    fn rasterize(&self, fov: FOV, supersample: usize, value: impl Fn(&Component) -> f32 + Sync) -> Image {
+        assert!(supersample > 0, "Supersampling factor must be at least 1");
+        // Search components in reverse order, so that the first one found to
+        // contain a point is the one which should be painted on top
+        let value_at = |p: Point| self.components.iter().rev()
+            .find(|c| c.contains(p))
+            .map_or(0.0, &value);
+
+        // Offsets of the sub-voxel sampling points from the voxel centre
+        let s = supersample;
+        let offsets = itertools::iproduct!(0..s, 0..s, 0..s)
+            .map(|(i, j, k)| {
+                let offset = |n: usize, size: Length| size * ((n as f32 + 0.5) / s as f32 - 0.5);
+                let size = fov.voxel_size;
+                Vector::new(offset(i, size.x), offset(j, size.y), offset(k, size.z))
+            })
+            .collect::<Vec<_>>();
+        let n_samples = offsets.len() as f32;
+
+        let [nx, ny, nz] = fov.n;
+        let data = (0..nx*ny*nz)
+            .into_par_iter()
+            .map(|i| {
+                let centre = fov.voxel_centre1(i);
+                offsets.iter()
+                    .map(|&offset| value_at(centre + offset))
+                    .sum::<f32>() / n_samples
+            })
+            .collect();
+        Image::new(fov, data)
+    }
+
+    /// The NEMA NU 2 image quality phantom: D-shaped body 180 mm long, with
+    /// six hot spheres (4:1) in a ring of radius 57.2 mm at z = 0 and a central
+    /// lung insert. Matches the geometry analysed by `foms nema7`.
+    pub fn nema_iq() -> Self {
+        let z = Some((mm(70.0 - 180.0), mm(70.0)));
+        let body = Component { shape: Shape::NemaBody, z, activity: 1.0, density: WATER };
+        let lung = Component {
+            shape: Shape::Roi(ROI::CylinderZ((mm(0.0), mm(0.0)), mm(51.0 / 2.0))),
+            z, activity: 0.0, density: LUNG,
+        };
+        let spheres = [(1, 10.0), (2, 13.0), (3, 17.0), (4, 22.0), (5, 28.0), (0, 37.0)]
+            .into_iter()
+            .map(|(position, diameter)| hot_sphere(mm(114.4 / 2.0), position, mm(diameter), mm(0.0)));
+        Self { components: [body, lung].into_iter().chain(spheres).collect() }
+    }
+
+    /// Deluxe Jaszczak phantom: cylinder of radius 108 mm and length 186 mm,
+    /// with six hot spheres (4:1) in a ring of radius 54 mm at z = 34 mm, and
+    /// six sectors of cold rods in the lower half. Matches the geometry
+    /// analysed by `foms jaszczak`.
+    pub fn jaszczak() -> Self {
+        let body = Component {
+            shape: Shape::Roi(ROI::CylinderZ((mm(0.0), mm(0.0)), mm(108.0))),
+            z: Some((mm(-93.0), mm(93.0))),
+            activity: 1.0, density: WATER,
+        };
+        let spheres = [9.5, 12.7, 15.9, 19.1, 25.4, 31.8]
+            .into_iter()
+            .enumerate()
+            .map(|(position, diameter)| hot_sphere(mm(54.0), position as u16, mm(diameter), mm(34.0)));
+        let rods = rod_sectors(&[4.8, 6.4, 7.9, 9.5, 11.1, 12.7], mm(108.0), (mm(-93.0), mm(-5.0)), 0.0, ACRYLIC);
+        Self { components: std::iter::once(body).chain(spheres).chain(rods).collect() }
+    }
+
+    /// Derenzo hot-rod phantom: cylinder of radius 108 mm and length 186 mm,
+    /// filled with inactive water, containing six sectors of hot rods of
+    /// diameters between 4.8 and 12.7 mm, 88 mm long, centred on z = 0.
+    /// Within each sector, rods are separated by twice their diameter.
+    pub fn derenzo() -> Self {
+        let body = Component {
+            shape: Shape::Roi(ROI::CylinderZ((mm(0.0), mm(0.0)), mm(108.0))),
+            z: Some((mm(-93.0), mm(93.0))),
+            activity: 0.0, density: WATER,
+        };
+        let rods = rod_sectors(&[4.8, 6.4, 7.9, 9.5, 11.1, 12.7], mm(108.0), (mm(-44.0), mm(44.0)), 1.0, WATER);
+        Self { components: std::iter::once(body).chain(rods).collect() }
+    }
+
+}
+
+impl TryFrom<&config::Config> for Phantom {
+    type Error = Box<dyn std::error::Error>;
+    fn try_from(config: &config::Config) -> Result<Self, Self::Error> {
+        config.validate()?;
+        let components = config.component.iter().enumerate()
+            .map(|(n, c)| Ok(Component {
+                shape: Shape::Roi(c.roi().map_err(|e| format!("Phantom component {n}: {e}"))?),
+                z: c.z,
+                activity: c.activity,
+                density: c.density,
+            }))
+            .collect::<Result<_, Self::Error>>()?;
+        Ok(Self { components })
+    }
+}
+
+/// Hot sphere in the `position`th of 6 angular positions on a ring of radius
+/// `ring_r` in the plane `z`
+fn hot_sphere(ring_r: Length, position: u16, diameter: Length, z: Length) -> Component {
+    let angle = std::f32::consts::TAU * position as f32 / 6.0;
+    let centre = (ring_r * angle.cos(), ring_r * angle.sin(), z);
+    Component {
+        shape: Shape::Roi(ROI::Sphere(centre, diameter / 2.0)),
+        z: None,
+        activity: 4.0,
+        density: WATER,
+    }
+}
+
+/// Rods parallel to z, in six triangular sectors around the axis, one sector
+/// per diameter (in mm). Within each sector the rods lie on a triangular
+/// lattice with spacing of twice the rod diameter, and the outermost rods stay
+/// clear of `body_r`.
+fn rod_sectors(
+    diameters: &[f32],
+    body_r   : Length,
+    z        : (Length, Length),
+    activity : Intensityf32,
+    density  : f32,
+) -> Vec<Component> {
+    let body_r = mm_(body_r);
+    let mut rods = vec![];
+    for (sector, &d) in diameters.iter().enumerate() {
+        let angle = std::f32::consts::TAU * sector as f32 / diameters.len() as f32;
+        let (radial, lateral) = ((angle.cos(), angle.sin()), (-angle.sin(), angle.cos()));
+        let spacing = 2.0 * d;
+        let row_spacing = spacing * 3.0_f32.sqrt() / 2.0;
+        // Row `i` has `i + 1` rods, at distance `spacing + i * row_spacing` from the axis
+        for row in 0.. {
+            let distance = spacing + row as f32 * row_spacing;
+            let half_span = row as f32 * spacing / 2.0;
+            if distance.hypot(half_span) + d / 2.0 > 0.9 * body_r { break }
+            for n in 0..=row {
+                let offset = n as f32 * spacing - half_span;
+                let x = distance * radial.0 + offset * lateral.0;
+                let y = distance * radial.1 + offset * lateral.1;
+                rods.push(Component {
+                    shape: Shape::Roi(ROI::CylinderZ((mm(x), mm(y)), mm(d / 2.0))),
+                    z: Some(z),
+                    activity,
+                    density,
+                });
+            }
+        }
+    }
+    rods
+}
+
+// Densities in g/cm³
+const WATER  : f32 = 1.0;
+const LUNG   : f32 = 0.3;
+const ACRYLIC: f32 = 1.18;
+
+#[cfg(test)]
+mod test_phantom {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    fn sphere(r: f32, activity: Intensityf32) -> Component {
+        Component {
+            shape: Shape::Roi(ROI::Sphere((mm(0.0), mm(0.0), mm(0.0)), mm(r))),
+            z: None, activity, density: 1.0,
+        }
+    }
+
+    #[test]
+    fn later_components_overwrite_earlier_ones() {
+        let phantom = Phantom { components: vec![sphere(8.0, 1.0), sphere(3.0, 5.0)] };
+        let fov = FOV::new((mm(20.0), mm(20.0), mm(20.0)), (5, 5, 5));
+        let image = phantom.activity_image(fov, 1);
+        assert_eq!(image[[2, 2, 2]], 5.0);
+        assert_eq!(image[[1, 2, 2]], 1.0);
+        assert_eq!(image[[0, 0, 0]], 0.0);
+    }
+
+    #[test]
+    fn supersampling_gives_partial_volume() {
+        // Sphere of volume 4/3 π r³ in a FOV of coarse voxels: the total
+        // activity approaches the volume as the supersampling increases
+        let r = 7.0;
+        let phantom = Phantom { components: vec![sphere(r, 1.0)] };
+        let fov = FOV::new((mm(20.0), mm(20.0), mm(20.0)), (5, 5, 5));
+        let voxel_volume = 4.0 * 4.0 * 4.0;
+        let volume = 4.0 / 3.0 * std::f32::consts::PI * r * r * r;
+        let total = |s| phantom.activity_image(fov, s).data.iter().sum::<f32>() * voxel_volume;
+        assert_float_eq!(total(20), volume, rmax <= 0.01);
+        // Partial voxels take fractional values
+        let image = phantom.activity_image(fov, 10);
+        assert!(image.data.iter().any(|&v| 0.0 < v && v < 1.0));
+    }
+
+    #[test]
+    fn nema_body_outline() {
+        let inside = |p| Shape::NemaBody.contains(p);
+        let p = |x, y| Point::new(mm(x), mm(y), mm(0.0));
+        assert!( inside(p(   0.0,  146.0)));
+        assert!(!inside(p(   0.0,  148.0)));
+        assert!( inside(p( 146.0,    0.0)));
+        assert!( inside(p(   0.0,  -76.0)));
+        assert!(!inside(p(   0.0,  -78.0)));
+        assert!( inside(p(-140.0,  -10.0)));
+        assert!(!inside(p(-140.0,  -70.0)));
+    }
+
+    #[test]
+    fn nema_iq_densities() {
+        let fov = FOV::new((mm(300.0), mm(300.0), mm(180.0)), (60, 60, 36));
+        let phantom = Phantom::nema_iq();
+        let density  = phantom.density_image (fov, 1);
+        let activity = phantom.activity_image(fov, 1);
+        // Lung insert at centre
+        let centre = [30, 30, 18];
+        assert_eq!(density [centre], LUNG);
+        assert_eq!(activity[centre], 0.0);
+        // Largest sphere, at position 0 on the ring, 57.2 mm along x
+        let sphere = [30 + 11, 30, 18];
+        assert_eq!(activity[sphere], 4.0);
+        // Outside the body
+        assert_eq!(density[[0, 0, 18]], 0.0);
+    }
+
+    #[test]
+    fn derenzo_rods_stay_in_their_sectors() {
+        for rod in Phantom::derenzo().components.iter().skip(1) {
+            let Shape::Roi(ROI::CylinderZ((x, y), r)) = rod.shape else { panic!("Expected rod") };
+            assert!(mm_(x).hypot(mm_(y)) + mm_(r) < 108.0);
+        }
+    }
+}
+
+// ----- Imports ------------------------------------------------------------------------------------------
+use rayon::prelude::*;
+
+use units::{Length, mm, mm_, todo::Intensityf32};
+
+use crate::{
+    FOV, Point, Vector,
+    config::phantom as config,
+    fom::ROI,
+    image::Image,
+};
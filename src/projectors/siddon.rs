@@ -88,6 +88,7 @@ impl Siddon {
             delta_index,
             mut remaining,
             tof_peak,
+            support: (support_start, support_end),
         }: FovHit
     ) {
         // How far we have moved since entering the FOV
@@ -97,8 +98,9 @@ impl Siddon {
             // Which voxel boundary will be hit next, and its position
             let (dimension, boundary_position) = next_boundary.argmin();
 
-            // The weight is the length of LOR in this voxel
-            let mut weight = boundary_position - here;
+            // The weight is the length of LOR in this voxel, ignoring any part
+            // lying outside the support
+            let mut weight = boundary_position.min(support_end) - here.max(support_start);
 
             // If TOF enabled, adjust weight
             if let Some(gauss) = &self.tof {
@@ -124,8 +126,8 @@ impl Siddon {
             index += delta_index[dimension];
             remaining[dimension] -= 1;
 
-            // If we have traversed the whole FOV, we're finished
-            if remaining[dimension] == 0 { break; }
+            // If we have traversed the whole FOV or left the support, we're finished
+            if remaining[dimension] == 0 || here >= support_end { break; }
         }
     }
 
@@ -192,7 +194,11 @@ pub fn lor_fov_hit(lor: &LOR, fov: FOV) -> Option<FovHit> {
     // Simplify expression of the algorithm by flipping axes so that the
     // direction from p1 to p2 is non-negative along all axes. Remember
     // which directions have been flipped, to recover correct voxel indices.
-    let (p1, p2, flipped) = flip_axes(lor.p1, lor.p2);
+    // Flipping is done in a coordinate system centred on the FOV, so that the
+    // FOV is unaffected by it.
+    let to_fov_centre = Point::zero() - fov.centre;
+    let (p1, p2, flipped) = flip_axes(lor.p1 + to_fov_centre, lor.p2 + to_fov_centre);
+    let fov = fov.with_centre(Point::zero());
 
     // If and where LOR enters FOV.
     let entry_point: Point = match fov.entry(p1, p2) {
@@ -202,6 +208,9 @@ pub fn lor_fov_hit(lor: &LOR, fov: FOV) -> Option<FovHit> {
         Some(point) => point,
     };
 
+    // Which part of the LOR lies within the support. If none, there is nothing to be done
+    let support = support_interval(entry_point, p2, fov.support)?;
+
     // How far the entry point is from the TOF peak
     let tof_peak = find_tof_peak(entry_point, p1, p2, lor.dt);
 
@@ -225,7 +234,31 @@ pub fn lor_fov_hit(lor: &LOR, fov: FOV) -> Option<FovHit> {
 
     // Return the values needed by `system_matrix_elements`
     let tof_peak = tof_peak;
-    Some(FovHit { next_boundary, voxel_size, index, delta_index, remaining, tof_peak } )
+    Some(FovHit { next_boundary, voxel_size, index, delta_index, remaining, tof_peak, support } )
+}
+
+/// Distances from `entry_point`, along the LOR towards `p2`, at which it enters
+/// and leaves the cylinder of radius `support` around the z-axis. `None` if the
+/// LOR misses the cylinder, unbounded if there is no support.
+#[inline]
+fn support_interval(entry_point: Point, p2: Point, support: Option<Length>) -> Option<(Length, Length)> {
+    let Some(r) = support else { return Some((Length::ZERO, mm(f32::INFINITY))) };
+    let d = (p2 - entry_point).normalize();
+    let [ex, ey, r] = [entry_point.x, entry_point.y, r].map(mm_);
+    let [dx, dy] = [ratio_(d.x), ratio_(d.y)];
+    // Solve |(ex, ey) + t (dx, dy)|^2 = r^2 for t
+    let a = dx * dx + dy * dy;
+    let b = 2.0 * (ex * dx + ey * dy);
+    let c = ex * ex + ey * ey - r * r;
+    if a < 1e-12 {
+        // LOR parallel to the axis: entirely inside or entirely outside
+        return (c < 0.0).then_some((Length::ZERO, mm(f32::INFINITY)))
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant <= 0.0 { return None }
+    let sqrt = discriminant.sqrt();
+    let (t_in, t_out) = ((-b - sqrt) / (2.0 * a), (-b + sqrt) / (2.0 * a));
+    (t_out > 0.0).then_some((mm(t_in.max(0.0)), mm(t_out)))
 }
 
 /// Calculate information needed to keep track of progress across FOV:
@@ -307,6 +340,9 @@ pub struct FovHit {
 
     /// Distance to the peak of the TOF gaussian.
     pub tof_peak     : Length,
+
+    /// Distances along the LOR between which it lies inside the FOV's support.
+    pub support      : (Length, Length),
 }
 
 #[inline(always)]
@@ -488,6 +524,48 @@ mod test {
 
         }
     }
+
+    // --------------------------------------------------------------------------------
+    // Shifting both the FOV and the LOR by the same amount changes nothing
+    #[rstest(/**/ dx,     dy,    dz,
+             case(  0.0,   0.0,  0.0),
+             case( 37.0,   0.0,  0.0),
+             case(-12.5,  80.0, 33.3),
+    )]
+    fn off_centre_fov(dx: Lengthf32, dy: Lengthf32, dz: Lengthf32) {
+        let p1 = Point::new(mm(-300.0), mm(-40.0), mm( 20.0));
+        let p2 = Point::new(mm( 300.0), mm( 70.0), mm(-10.0));
+        let fov = FOV::new((mm(200.0), mm(200.0), mm(100.0)), (20, 20, 10));
+        let shift = Vector::new(mm(dx), mm(dy), mm(dz));
+        let shifted_fov = fov.with_centre(Point::zero() + shift);
+        let lor         = LOR::new(Time::ZERO, Time::ZERO, p1        , p2        , ratio(1.0));
+        let shifted_lor = LOR::new(Time::ZERO, Time::ZERO, p1 + shift, p2 + shift, ratio(1.0));
+        let row         = Siddon::notof().new_system_matrix_row(&        lor, &        fov).0;
+        let shifted_row = Siddon::notof().new_system_matrix_row(&shifted_lor, &shifted_fov).0;
+        assert_eq!(row.len(), shifted_row.len());
+        for ((i, w), (j, v)) in row.into_iter().zip(shifted_row) {
+            assert_eq!(i, j);
+            assert_float_eq!(w, v, abs <= 1e-3);
+        }
+    }
+
+    // Weights are restricted to the part of the LOR inside the cylindrical support
+    #[test]
+    fn cylindrical_support() {
+        let fov = FOV::new((mm(200.0), mm(200.0), mm(10.0)), (20, 20, 1));
+        let lor = |y| LOR::new(Time::ZERO, Time::ZERO,
+                               Point::new(mm(-300.0), mm(y), mm(0.0)),
+                               Point::new(mm( 300.0), mm(y), mm(0.0)), ratio(1.0));
+        let total = |fov, y| Siddon::notof().new_system_matrix_row(&lor(y), &fov).into_iter().map(|(_, w)| w).sum::<Lengthf32>();
+        assert_float_eq!(total(fov, 0.0), 200.0, rel <= 1e-5);
+        let supported = fov.with_support(mm(50.0));
+        assert_float_eq!(total(supported,  0.0), 100.0, rel <= 1e-5);
+        assert_float_eq!(total(supported, 30.0),  80.0, rel <= 1e-4);
+        assert!(Siddon::notof().new_system_matrix_row(&lor(60.0), &supported).0.is_empty());
+        let voxels = Siddon::notof().new_system_matrix_row(&lor(0.0), &supported).0;
+        assert_eq!(voxels.len(), 10);
+        assert!(voxels.iter().all(|&(i, _)| (5..15).contains(&index1_to_3(i, fov.n)[0])));
+    }
 }
 
 #[cfg(test)]
@@ -891,7 +969,7 @@ mod sensitivity_image {
         let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
         let parameters = Siddon::notof().data();
         pool.install(|| {
-            mlem::<Siddon>(parameters, fov, &lors, None, 1)
+            mlem::<Siddon>(parameters, fov, &lors, None, 1, None)
                 .take(10)
                 .for_each(save_each_image_in(format!("test-mlem-images/{name}/")));
         });
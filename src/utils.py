@@ -34,16 +34,54 @@ def show_image(image, *, axis, slice_, extent, shape, ax=plt):
     if axis == 'z': ax.imshow(image[:, :, s].T , extent = [-xe,xe, -ye,ye], origin = 'lower')
 
 
+MAGIC = b'PETALOIM'
+
 def read_raw(filename, header_only=False, end='>'):
-    metadata_length = 18
-    buffer = open(filename, 'rb').read(metadata_length if header_only else -1)
-    metadata = buffer[: metadata_length  ]
-    data     = buffer[  metadata_length :]
-    pixels = struct.unpack_from('>HHH', metadata[:6])
-    mm     = struct.unpack_from('>fff', metadata[6:])
+    """Read petalo raw image (v1 or v2), returning ((pixels, mm), data).
+
+    `mm` is the full physical size of the FOV. Endianness of v2 files is taken
+    from their header, so `end` only applies to v1 files. With `header_only`,
+    only `(pixels, mm)` is returned, and the voxel values are not read.
+    """
+    with open(filename, 'rb') as file:
+        if file.read(8) == MAGIC:
+            return read_raw_v2(file, header_only)
+        file.seek(0)
+        metadata_length = 18
+        metadata = file.read(metadata_length)
+        pixels = struct.unpack_from('>HHH', metadata[:6])
+        mm     = struct.unpack_from('>fff', metadata[6:])
+        if header_only:
+            return pixels, mm
+        data = file.read()
+    data   = struct.unpack_from(end + 'f' * (len(data) // 4), data)
+    return (pixels, mm), data
+
+
+def read_raw_v2(file, header_only):
+    """Read the rest of a v2 raw image from `file`, positioned just after MAGIC.
+
+    Raises `ValueError` if the file is not a valid v2 raw image.
+    """
+    marks = file.read(2)
+    if len(marks) < 2 or marks[:1] not in (b'L', b'B'):
+        raise ValueError(f'Invalid raw image header in {file.name}')
+    end = {b'L': '<', b'B': '>'}[marks[:1]]
+    version = marks[1]
+    if version != 2:
+        raise ValueError(f'Unsupported raw image version {version} in {file.name}: expected 2')
+    def read(fmt):
+        fmt = end + fmt
+        return struct.unpack(fmt, file.read(struct.calcsize(fmt)))
+    pixels     = read('III')
+    voxel_size = read('fff')
+    file.seek(12 + 1 + 8, 1) # origin, kind, iteration, subset
+    (units_len ,) = read('H'); file.seek(units_len , 1)
+    (config_len,) = read('I'); file.seek(config_len, 1)
+    mm = tuple(n * d for n, d in zip(pixels, voxel_size))
     if header_only:
         return pixels, mm
-    data   = struct.unpack_from(end + 'f' * (len(data) // 4), data)
+    data = read('f' * reduce(mul, pixels))
     return (pixels, mm), data
 
 
@@ -65,6 +103,17 @@ def wrap_1d_into_3d(data, shape, row_major=False):
 
 
 def read_raw_without_header(filename, end='>'):
-    data = open(filename, 'rb').read()
+    """Read the voxel values of a headerless raw image.
+
+    Files which do carry a v2 header (as written by default by the Rust
+    version of MLEM) are also accepted: their header is skipped, and their
+    endianness is taken from it, rather than from `end`.
+    """
+    with open(filename, 'rb') as file:
+        if file.read(8) == MAGIC:
+            _, data = read_raw_v2(file, header_only=False)
+            return data
+        file.seek(0)
+        data = file.read()
     data = struct.unpack_from(end + 'f' * (len(data) // 4), data)
     return data
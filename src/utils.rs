@@ -84,6 +84,9 @@ pub fn parse_maybe_cutoff(s: &str) -> Result<CutoffOption<Ratio>, ParseFloatErro
     Ok(if s == "no" { None } else { Some(units::ratio(s.parse()?)) })
 }
 
+/// Ratio of the FWHM of a Gaussian to its standard deviation: 2 * sqrt(2 * ln(2))
+pub const FWHM_PER_SIGMA: f32 = 2.354_82;
+
 /// Group numeric digits to facilitate reading long numbers
 pub fn group_digits<F: std::fmt::Display>(n: F) -> String {
     use numsep::{separate, Locale};